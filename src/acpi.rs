@@ -0,0 +1,318 @@
+//! Minimal ACPI table parser.
+//!
+//! Finds the RSDP in the BIOS memory areas, walks the RSDT/XSDT and exposes the tables
+//! by signature. The tables are read through the complete mapping of physical memory.
+//! Reference: [ACPI specification](https://uefi.org/specs/ACPI/6.4/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html)
+use alloc::vec::Vec;
+use core::{mem::size_of, ptr::read_unaligned, slice};
+use x86_64::PhysAddr;
+
+use crate::memory::phys_to_virt;
+
+#[cfg(test)]
+mod tests;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// Physical address of the 16 bit segment of the extended BIOS data area
+const EBDA_SEGMENT_PTR: u64 = 0x40e;
+const BIOS_AREA: (u64, u64) = (0xe_0000, 0x10_0000);
+
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // Only valid for revision >= 2
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+/// Common header of all system description tables
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+/// A validated table. `data` includes the header.
+#[derive(Clone, Copy)]
+pub struct Table {
+    pub address: PhysAddr,
+    pub data: &'static [u8],
+}
+
+impl Table {
+    /// # Safety
+    /// `address` must point to an ACPI table.
+    unsafe fn from_phys(address: PhysAddr) -> Option<Self> {
+        let header: SdtHeader = read_unaligned(phys_to_virt(address).as_ptr());
+        let length = header.length as usize;
+        if length < size_of::<SdtHeader>() {
+            return None;
+        }
+        let data = slice::from_raw_parts(phys_to_virt(address).as_ptr::<u8>(), length);
+        checksum_ok(data).then(|| Table { address, data })
+    }
+
+    #[must_use]
+    pub fn header(&self) -> SdtHeader {
+        unsafe { read_unaligned(self.data.as_ptr().cast()) }
+    }
+
+    #[must_use]
+    pub fn signature(&self) -> [u8; 4] {
+        self.header().signature
+    }
+
+    /// Content after the common header
+    #[must_use]
+    pub fn body(&self) -> &'static [u8] {
+        &self.data[size_of::<SdtHeader>()..]
+    }
+}
+
+fn checksum_ok(data: &[u8]) -> bool {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+/// Finds the root table pointer in the EBDA or the BIOS read-only memory.
+fn find_rsdp() -> Option<Rsdp> {
+    let ebda = u64::from(unsafe {
+        read_unaligned(phys_to_virt(PhysAddr::new(EBDA_SEGMENT_PTR)).as_ptr::<u16>())
+    }) << 4;
+    let areas = [(ebda, ebda + 1024), BIOS_AREA];
+    for (start, end) in areas {
+        if start == 0 {
+            continue;
+        }
+        for address in (start..end).step_by(16) {
+            let ptr = phys_to_virt(PhysAddr::new(address)).as_ptr::<u8>();
+            let bytes = unsafe { slice::from_raw_parts(ptr, 20) };
+            if &bytes[..8] == RSDP_SIGNATURE && checksum_ok(bytes) {
+                return Some(unsafe { read_unaligned(ptr.cast()) });
+            }
+        }
+    }
+    None
+}
+
+/// All tables referenced by the RSDT or XSDT. Empty if there is no ACPI support.
+#[must_use]
+pub fn tables() -> Vec<Table> {
+    let rsdp = match find_rsdp() {
+        Some(rsdp) => rsdp,
+        None => return Vec::new(),
+    };
+    let (root, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (PhysAddr::new(rsdp.xsdt_address), 8)
+    } else {
+        (PhysAddr::new(u64::from(rsdp.rsdt_address)), 4)
+    };
+    let root = match unsafe { Table::from_phys(root) } {
+        Some(root) => root,
+        None => return Vec::new(),
+    };
+    root.body()
+        .chunks_exact(entry_size)
+        .filter_map(|entry| {
+            let address = if entry_size == 8 {
+                u64::from_le_bytes(entry.try_into().unwrap())
+            } else {
+                u64::from(u32::from_le_bytes(entry.try_into().unwrap()))
+            };
+            unsafe { Table::from_phys(PhysAddr::new(address)) }
+        })
+        .collect()
+}
+
+/// Finds a table by its signature, e.g. `b"FACP"` for the FADT.
+#[must_use]
+pub fn find_table(signature: &[u8; 4]) -> Option<Table> {
+    tables()
+        .into_iter()
+        .find(|table| &table.signature() == signature)
+}
+
+/// Processors and interrupt controllers, from the MADT (signature `APIC`)
+pub struct Madt {
+    pub local_apic_address: PhysAddr,
+    /// APIC ids of all usable processors, in table order
+    pub processors: Vec<u8>,
+}
+
+impl Madt {
+    const ENTRY_LOCAL_APIC: u8 = 0;
+    const ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+    const PROCESSOR_ENABLED: u32 = 1;
+
+    #[must_use]
+    pub fn parse() -> Option<Self> {
+        let table = find_table(b"APIC")?;
+        let body = table.body();
+        let mut local_apic_address = u64::from(u32::from_le_bytes(body.get(..4)?.try_into().ok()?));
+        let mut processors = Vec::new();
+
+        // Skip the local APIC address and the flags
+        let mut entries = body.get(8..)?;
+        while let [kind, length, ..] = *entries {
+            let length = usize::from(length);
+            if length < 2 || length > entries.len() {
+                break;
+            }
+            let entry = &entries[..length];
+            match kind {
+                Self::ENTRY_LOCAL_APIC if length >= 8 => {
+                    let flags = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+                    if flags & Self::PROCESSOR_ENABLED != 0 {
+                        processors.push(entry[3]);
+                    }
+                }
+                Self::ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE if length >= 12 => {
+                    local_apic_address = u64::from_le_bytes(entry[4..12].try_into().unwrap());
+                }
+                _ => {}
+            }
+            entries = &entries[length..];
+        }
+
+        Some(Self {
+            local_apic_address: PhysAddr::new(local_apic_address),
+            processors,
+        })
+    }
+}
+
+/// Location of a register, as given in the FADT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    /// See the `SYSTEM_*` constants
+    pub space: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+}
+
+/// Power management registers, from the FADT (signature `FACP`)
+pub struct Fadt {
+    /// Differentiated system description table, whose AML defines the sleep states
+    pub dsdt: PhysAddr,
+    /// Port, to which `acpi_enable` is written to enter ACPI mode. 0 if there is none.
+    pub smi_command: u16,
+    pub acpi_enable: u8,
+    /// Port of the PM1a control register
+    pub pm1a_control: u16,
+    /// Port of the PM1b control register, 0 if there is none
+    pub pm1b_control: u16,
+    /// Reset register and the value, which resets the machine. Only if it is supported.
+    pub reset: Option<(GenericAddress, u8)>,
+}
+
+impl Fadt {
+    const FLAG_RESET_REGISTER: u32 = 1 << 10;
+
+    #[must_use]
+    pub fn parse() -> Option<Self> {
+        let table = find_table(b"FACP")?;
+        // Offsets include the header. Newer revisions append fields.
+        let data = table.data;
+        let u32_at = |offset: usize| {
+            data.get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let u64_at = |offset: usize| {
+            data.get(offset..offset + 8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let port_at = |offset: usize| u32_at(offset).and_then(|port| u16::try_from(port).ok());
+
+        let dsdt = match u64_at(140) {
+            Some(address) if address != 0 => address,
+            _ => u64::from(u32_at(40)?),
+        };
+        let flags = u32_at(112).unwrap_or(0);
+        let reset = match (data.get(116), u64_at(120), data.get(128)) {
+            (Some(space), Some(address), Some(value))
+                if flags & Self::FLAG_RESET_REGISTER != 0 && address != 0 =>
+            {
+                let register = GenericAddress {
+                    space: *space,
+                    address,
+                };
+                Some((register, *value))
+            }
+            _ => None,
+        };
+        Some(Self {
+            dsdt: PhysAddr::new(dsdt),
+            smi_command: port_at(48)?,
+            acpi_enable: *data.get(52)?,
+            pm1a_control: port_at(64)?,
+            pm1b_control: port_at(68)?,
+            reset,
+        })
+    }
+
+    /// The validated DSDT
+    #[must_use]
+    pub fn dsdt(&self) -> Option<Table> {
+        unsafe { Table::from_phys(self.dsdt) }
+    }
+}
+
+/// `SLP_TYPa` and `SLP_TYPb` of a sleep state, e.g. `b"_S5_"`, from the AML of the DSDT.
+///
+/// Doesn't interpret the AML, but looks for the package the firmware defines with
+/// `Name (_S5, Package () { a, b, ... })`.
+#[must_use]
+pub fn sleep_types(aml: &[u8], state: &[u8; 4]) -> Option<(u16, u16)> {
+    const NAME_OP: u8 = 0x08;
+    const ROOT_PREFIX: u8 = b'\\';
+    const PACKAGE_OP: u8 = 0x12;
+
+    let position = (2..aml.len().saturating_sub(4)).find(|&index| {
+        let named = aml[index - 1] == NAME_OP || aml[index - 2..index] == [NAME_OP, ROOT_PREFIX];
+        &aml[index..index + 4] == state && named && aml[index + 4] == PACKAGE_OP
+    })?;
+    // The two high bits of the first byte give the number of further length bytes
+    let length = aml.get(position + 5)?;
+    let mut rest = aml.get(position + 6 + usize::from(length >> 6)..)?;
+    // Number of elements
+    rest = rest.get(1..)?;
+    let a = integer(&mut rest)?;
+    let b = integer(&mut rest)?;
+    Some((a, b))
+}
+
+/// Parses an AML integer constant
+fn integer(aml: &mut &[u8]) -> Option<u16> {
+    const ZERO_OP: u8 = 0x00;
+    const ONE_OP: u8 = 0x01;
+    const BYTE_PREFIX: u8 = 0x0a;
+    const WORD_PREFIX: u8 = 0x0b;
+
+    let (value, size) = match **aml {
+        [ZERO_OP, ..] => (0, 1),
+        [ONE_OP, ..] => (1, 1),
+        [BYTE_PREFIX, value, ..] => (u16::from(value), 2),
+        [WORD_PREFIX, low, high, ..] => (u16::from_le_bytes([low, high]), 3),
+        _ => return None,
+    };
+    *aml = &aml[size..];
+    Some(value)
+}
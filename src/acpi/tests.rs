@@ -0,0 +1,24 @@
+use super::*;
+
+#[test_case]
+fn sleep_types_of_s5() {
+    // Name (\_S5, Package (0x04) { 0x05, Zero, Zero, Zero })
+    let aml = [
+        0x10, 0x0a, 0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x08, 0x04, 0x0a, 0x05, 0x00, 0x00,
+        0x00,
+    ];
+    assert_eq!(sleep_types(&aml, b"_S5_"), Some((5, 0)));
+    assert_eq!(sleep_types(&aml, b"_S3_"), None);
+
+    // Name (_S5, Package (0x02) { 0x0005, One }), with a two byte package length
+    let aml = [
+        0x00, 0x00, 0x08, b'_', b'S', b'5', b'_', 0x12, 0x46, 0x00, 0x02, 0x0b, 0x05, 0x00, 0x01,
+    ];
+    assert_eq!(sleep_types(&aml, b"_S5_"), Some((5, 1)));
+
+    // Only a reference, not the definition
+    let aml = [
+        0x00, 0x00, 0x70, b'_', b'S', b'5', b'_', 0x12, 0x06, 0x02, 0x00, 0x00,
+    ];
+    assert_eq!(sleep_types(&aml, b"_S5_"), None);
+}
@@ -0,0 +1,83 @@
+use core::task::Poll;
+use futures_util::future::poll_fn;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::waker_list::{Waiter, WakerList};
+
+/// Lets `n` tasks wait for each other, before all of them continue.
+pub struct Barrier {
+    n: usize,
+    state: Mutex<BarrierState>,
+    waiters: WakerList,
+}
+
+struct BarrierState {
+    arrived: usize,
+    generation: u64,
+}
+
+/// Exactly one task per generation is the leader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    #[must_use]
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// A barrier for `n` tasks. `n == 0` behaves like `n == 1`.
+    #[must_use]
+    pub fn new(n: usize) -> Self {
+        Self {
+            n: n.max(1),
+            state: Mutex::new(BarrierState {
+                arrived: 0,
+                generation: 0,
+            }),
+            waiters: WakerList::new(),
+        }
+    }
+
+    /// Resolves once `n` tasks called `wait`. The barrier can be reused afterwards.
+    pub async fn wait(&self) -> BarrierWaitResult {
+        let generation = without_interrupts(|| {
+            let mut state = self.state.lock();
+            state.arrived += 1;
+            if state.arrived < self.n {
+                return Some(state.generation);
+            }
+            state.arrived = 0;
+            state.generation += 1;
+            None
+        });
+        let generation = match generation {
+            Some(generation) => generation,
+            None => {
+                self.waiters.wake_all();
+                return BarrierWaitResult(true);
+            }
+        };
+
+        let mut waiter = Waiter::new(&self.waiters);
+        poll_fn(|cx| {
+            let passed = || without_interrupts(|| self.state.lock().generation != generation);
+            if passed() {
+                waiter.cancel();
+                return Poll::Ready(());
+            }
+            // Registration fails only if we got woken, which happens on release
+            if waiter.register(cx.waker()) || passed() {
+                waiter.cancel();
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        BarrierWaitResult(false)
+    }
+}
@@ -0,0 +1,31 @@
+//! Synchronisation primitives for kernel threads, which block the current thread instead of
+//! spinning. The thread gets parked in a `WaitQueue` until it is notified.
+//!
+//! Don't use them in interrupt handlers or while holding an `IrqSpinLock`, as they might
+//! have to switch to another thread. Threads only run on the bootstrap processor, so don't
+//! use them from executor workers on other CPUs either. Debug builds check for this and
+//! for recursive locking.
+mod condvar;
+mod mutex;
+mod semaphore;
+mod wait_queue;
+
+pub use condvar::Condvar;
+pub use mutex::{Mutex, MutexGuard};
+pub use semaphore::{Semaphore, SemaphoreGuard};
+pub use wait_queue::WaitQueue;
+
+/// Blocking with interrupts disabled means, that we are either in an interrupt handler or
+/// hold an `IrqSpinLock`. The scheduler only runs on the bootstrap processor.
+#[inline]
+fn debug_assert_may_block() {
+    debug_assert!(
+        x86_64::instructions::interrupts::are_enabled(),
+        "Blocking with interrupts disabled, e.g. in an interrupt handler"
+    );
+    debug_assert_eq!(
+        crate::smp::cpu_id(),
+        0,
+        "Blocking on an application processor, which doesn't run threads"
+    );
+}
@@ -0,0 +1,49 @@
+use super::{MutexGuard, WaitQueue};
+
+/// Condition variable, used together with a blocking `Mutex`.
+pub struct Condvar {
+    queue: WaitQueue,
+}
+
+impl Condvar {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Releases the lock, blocks until notified and reacquires the lock. Might return
+    /// spuriously, so check the condition in a loop or use `wait_while`.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+        self.queue.wait_with(|| guard.unlock());
+        mutex.lock()
+    }
+
+    /// Blocks as long as `condition` returns `true`.
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.queue.notify_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.queue.notify_all();
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
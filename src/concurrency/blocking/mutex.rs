@@ -0,0 +1,132 @@
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use super::WaitQueue;
+use crate::concurrency::thread;
+
+const NO_OWNER: u64 = u64::MAX;
+
+/// Mutual exclusion lock, which parks the current thread while it is contended.
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicBool,
+    /// Thread id of the holder, only tracked in debug builds
+    owner: AtomicU64,
+    queue: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    #[must_use]
+    pub fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            owner: AtomicU64::new(NO_OWNER),
+            queue: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Blocks until the lock is acquired.
+    ///
+    /// # Panics
+    /// In debug builds, if the current thread already holds the lock or interrupts are
+    /// disabled.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if cfg!(debug_assertions)
+            && self.locked.load(Ordering::Relaxed)
+            && self.owner.load(Ordering::Relaxed) == thread::current_id().as_u64()
+        {
+            panic!("Mutex is already held by the current thread");
+        }
+        self.queue.wait_until(|| self.acquire());
+        MutexGuard { mutex: self }
+    }
+
+    #[must_use]
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        if self.acquire() {
+            Some(MutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn acquire(&self) -> bool {
+        let acquired = self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok();
+        if acquired && cfg!(debug_assertions) {
+            self.owner
+                .store(thread::current_id().as_u64(), Ordering::Relaxed);
+        }
+        acquired
+    }
+
+    fn release(&self) {
+        if cfg!(debug_assertions) {
+            self.owner.store(NO_OWNER, Ordering::Relaxed);
+        }
+        self.locked.store(false, Ordering::Release);
+        self.queue.notify_one();
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    /// Used by `Condvar` to release and reacquire the lock.
+    pub(super) fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+
+    pub(super) fn unlock(self) {
+        let mutex = self.mutex;
+        core::mem::forget(self);
+        mutex.release();
+    }
+}
+
+impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.release();
+    }
+}
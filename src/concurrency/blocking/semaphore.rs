@@ -0,0 +1,71 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::WaitQueue;
+
+/// Counting semaphore, which parks the current thread until a permit is available.
+pub struct Semaphore {
+    permits: AtomicUsize,
+    queue: WaitQueue,
+}
+
+impl Semaphore {
+    #[must_use]
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Blocks until a permit is available.
+    pub fn acquire(&self) -> SemaphoreGuard<'_> {
+        self.queue.wait_until(|| self.take_permit());
+        SemaphoreGuard { semaphore: self }
+    }
+
+    #[must_use]
+    pub fn try_acquire(&self) -> Option<SemaphoreGuard<'_>> {
+        if self.take_permit() {
+            Some(SemaphoreGuard { semaphore: self })
+        } else {
+            None
+        }
+    }
+
+    /// Adds a permit. Usable in interrupt handlers, e.g. to count received packets.
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+        self.queue.notify_one();
+    }
+
+    #[must_use]
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Acquire)
+    }
+
+    fn take_permit(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |permits| {
+                permits.checked_sub(1)
+            })
+            .is_ok()
+    }
+}
+
+/// Releases its permit when dropped.
+pub struct SemaphoreGuard<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl<'a> SemaphoreGuard<'a> {
+    /// Keeps the permit taken, e.g. if it gets released from an interrupt handler later.
+    pub fn forget(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl<'a> Drop for SemaphoreGuard<'a> {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
@@ -0,0 +1,87 @@
+use alloc::collections::VecDeque;
+
+use crate::concurrency::{
+    thread::{self, Thread},
+    IrqSpinLock,
+};
+
+/// Threads waiting for a condition. They are parked until notified.
+pub struct WaitQueue {
+    waiters: IrqSpinLock<VecDeque<Thread>>,
+}
+
+impl WaitQueue {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            waiters: IrqSpinLock::new(VecDeque::new()),
+        }
+    }
+
+    /// Blocks until `condition` returns `true`. It is checked again after every notification.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        super::debug_assert_may_block();
+        loop {
+            if condition() {
+                return;
+            }
+            let me = thread::current();
+            self.waiters.lock().push_back(me);
+            // A notification could have happened before we were queued
+            if condition() {
+                self.remove(me);
+                return;
+            }
+            thread::park();
+            // Still queued after a spurious wakeup
+            self.remove(me);
+        }
+    }
+
+    /// Queues the current thread, calls `before_park` and parks until notified. Used to
+    /// release a lock without missing a notification, which happens inbetween.
+    ///
+    /// Might return spuriously.
+    pub fn wait_with(&self, before_park: impl FnOnce()) {
+        super::debug_assert_may_block();
+        let me = thread::current();
+        self.waiters.lock().push_back(me);
+        before_park();
+        thread::park();
+        self.remove(me);
+    }
+
+    /// Wakes the longest waiting thread. Returns `false` if nobody is waiting.
+    /// Usable in interrupt handlers.
+    pub fn notify_one(&self) -> bool {
+        let waiter = self.waiters.lock().pop_front();
+        match waiter {
+            Some(thread) => {
+                thread.unpark();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wakes all waiting threads and returns how many there were. Usable in interrupt
+    /// handlers.
+    pub fn notify_all(&self) -> usize {
+        let waiters = core::mem::take(&mut *self.waiters.lock());
+        let amount = waiters.len();
+        for thread in waiters {
+            thread.unpark();
+        }
+        amount
+    }
+
+    fn remove(&self, thread: Thread) {
+        self.waiters.lock().retain(|waiter| *waiter != thread);
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
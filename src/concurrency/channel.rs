@@ -0,0 +1,88 @@
+//! Message passing between tasks.
+//!
+//! - `mpsc`: bounded and unbounded multi-producer, single-consumer queues
+//! - `oneshot`: a single value from one task to another
+//! - `broadcast`: every receiver gets a clone of every value
+//! - `watch`: receivers observe the latest value only
+//!
+//! The non-blocking send functions (`mpsc::Sender::try_send`, `oneshot::Sender::send`,
+//! `broadcast::Sender::send`, `watch::Sender::send`) never block or allocate and may be
+//! called from interrupt handlers. They can free memory though, e.g. a replaced value or the
+//! waker of a woken task, which may drop the last reference to that task. The heap allows
+//! this with interrupts disabled, but the `Drop` of such values runs in the handler too.
+use core::fmt;
+
+pub mod broadcast;
+pub mod mpsc;
+pub mod oneshot;
+pub mod watch;
+
+#[cfg(test)]
+mod tests;
+
+/// All receivers are gone. Contains the value, which could not be sent.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("channel closed")
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    /// The channel is bounded and currently full.
+    Full(T),
+    /// All receivers are gone.
+    Closed(T),
+}
+
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(value) | TrySendError::Closed(value) => value,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Closed(_) => f.write_str("Closed(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("channel full"),
+            TrySendError::Closed(_) => f.write_str("channel closed"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    /// Nothing to receive right now.
+    Empty,
+    /// All senders are gone and nothing is left to receive.
+    Closed,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("channel empty"),
+            TryRecvError::Closed => f.write_str("channel closed"),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Every receiver gets a clone of every value sent after it subscribed.
+//!
+//! The channel keeps the last `capacity` values. A receiver that falls further behind
+//! skips the oldest values and gets `RecvError::Lagged` with the amount of skipped values.
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Poll,
+};
+use futures_util::future::poll_fn;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::SendError;
+use crate::concurrency::waker_list::{Waiter, WakerList};
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    waiters: WakerList,
+}
+
+struct State<T> {
+    /// Contains the values with the sequence numbers `next_seq - buffer.len()..next_seq`
+    buffer: VecDeque<T>,
+    next_seq: u64,
+}
+
+/// Creates a channel, which remembers the last `capacity` values.
+///
+/// # Panics
+/// If `capacity` is zero.
+#[must_use]
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "broadcast channel capacity must be positive");
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buffer: VecDeque::with_capacity(capacity),
+            next_seq: 0,
+        }),
+        capacity,
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+        waiters: WakerList::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, next: 0 },
+    )
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecvError {
+    /// All senders are gone and every value was received.
+    Closed,
+    /// The receiver fell behind and the given amount of values was skipped.
+    Lagged(u64),
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecvError::Closed => f.write_str("channel closed"),
+            RecvError::Lagged(n) => write!(f, "receiver lagged behind by {} values", n),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+    Lagged(u64),
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Clone> Sender<T> {
+    /// Returns the amount of receivers, which will see the value. Never blocks or allocates,
+    /// so it can be used in interrupt handlers. The dropped oldest value and the wakers of
+    /// the woken receivers are freed, which the heap allows with interrupts disabled.
+    ///
+    /// # Errors
+    /// If there are no receivers.
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let receivers = self.shared.receivers.load(Ordering::Acquire);
+        if receivers == 0 {
+            return Err(SendError(value));
+        }
+        without_interrupts(|| {
+            let mut state = self.shared.state.lock();
+            if state.buffer.len() == self.shared.capacity {
+                state.buffer.pop_front();
+            }
+            state.buffer.push_back(value);
+            state.next_seq += 1;
+        });
+        self.shared.waiters.wake_all();
+        Ok(receivers)
+    }
+
+    /// Creates a receiver, which gets all values sent from now on.
+    #[must_use]
+    pub fn subscribe(&self) -> Receiver<T> {
+        self.shared.receivers.fetch_add(1, Ordering::AcqRel);
+        let next = without_interrupts(|| self.shared.state.lock().next_seq);
+        Receiver {
+            shared: self.shared.clone(),
+            next,
+        }
+    }
+
+    #[must_use]
+    pub fn receiver_count(&self) -> usize {
+        self.shared.receivers.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.waiters.wake_all();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    /// Sequence number of the next value to receive
+    next: u64,
+}
+
+impl<T: Clone> Receiver<T> {
+    /// # Errors
+    /// If nothing new was sent, the channel is closed or the receiver lagged behind.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let result = without_interrupts(|| {
+            let state = self.shared.state.lock();
+            let oldest = state.next_seq - state.buffer.len() as u64;
+            if self.next < oldest {
+                let skipped = oldest - self.next;
+                self.next = oldest;
+                return Err(TryRecvError::Lagged(skipped));
+            }
+            if self.next == state.next_seq {
+                return Err(TryRecvError::Empty);
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            let value = state.buffer[(self.next - oldest) as usize].clone();
+            self.next += 1;
+            Ok(value)
+        });
+        match result {
+            Err(TryRecvError::Empty) if self.shared.senders.load(Ordering::Acquire) == 0 => {
+                Err(TryRecvError::Closed)
+            }
+            result => result,
+        }
+    }
+
+    /// # Errors
+    /// If the channel is closed or the receiver lagged behind.
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        let shared = self.shared.clone();
+        let mut waiter = Waiter::new(&shared.waiters);
+        poll_fn(|cx| loop {
+            let result = match self.try_recv() {
+                Ok(value) => Ok(value),
+                Err(TryRecvError::Closed) => Err(RecvError::Closed),
+                Err(TryRecvError::Lagged(n)) => Err(RecvError::Lagged(n)),
+                Err(TryRecvError::Empty) if waiter.register(cx.waker()) => continue,
+                // A value could have been sent before the waker was registered
+                Err(TryRecvError::Empty) => match self.try_recv() {
+                    Ok(value) => Ok(value),
+                    Err(TryRecvError::Closed) => Err(RecvError::Closed),
+                    Err(TryRecvError::Lagged(n)) => Err(RecvError::Lagged(n)),
+                    Err(TryRecvError::Empty) => return Poll::Pending,
+                },
+            };
+            waiter.cancel();
+            return Poll::Ready(result);
+        })
+        .await
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    /// The clone continues at the same position.
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::AcqRel);
+        Self {
+            shared: self.shared.clone(),
+            next: self.next,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receivers.fetch_sub(1, Ordering::AcqRel);
+    }
+}
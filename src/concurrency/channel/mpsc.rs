@@ -0,0 +1,276 @@
+//! Multi-producer, single-consumer channels.
+use alloc::sync::Arc;
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use crossbeam_queue::{ArrayQueue, SegQueue};
+use futures_util::{future::poll_fn, task::AtomicWaker, Stream};
+
+use super::{SendError, TryRecvError, TrySendError};
+use crate::concurrency::waker_list::{Waiter, WakerList};
+
+enum Queue<T> {
+    Bounded(ArrayQueue<T>),
+    Unbounded(SegQueue<T>),
+}
+
+impl<T> Queue<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        match self {
+            Queue::Bounded(queue) => queue.push(value),
+            Queue::Unbounded(queue) => {
+                queue.push(value);
+                Ok(())
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        match self {
+            Queue::Bounded(queue) => queue.pop(),
+            Queue::Unbounded(queue) => queue.pop(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Queue::Bounded(queue) => queue.len(),
+            Queue::Unbounded(queue) => queue.len(),
+        }
+    }
+}
+
+struct Chan<T> {
+    queue: Queue<T>,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    receiver_waker: AtomicWaker,
+    /// Senders waiting for free space in a bounded channel
+    send_waiters: WakerList,
+}
+
+impl<T> Chan<T> {
+    fn new(queue: Queue<T>) -> Arc<Self> {
+        Arc::new(Self {
+            queue,
+            senders: AtomicUsize::new(1),
+            receiver_alive: AtomicBool::new(true),
+            receiver_waker: AtomicWaker::new(),
+            send_waiters: WakerList::new(),
+        })
+    }
+
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if !self.receiver_alive.load(Ordering::Acquire) {
+            return Err(TrySendError::Closed(value));
+        }
+        self.queue.push(value).map_err(TrySendError::Full)?;
+        self.receiver_waker.wake();
+        Ok(())
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.queue.pop() {
+            self.send_waiters.wake_one();
+            return Ok(value);
+        }
+        if self.senders.load(Ordering::Acquire) == 0 {
+            // A sender could have pushed right before dropping
+            return self.queue.pop().ok_or(TryRecvError::Closed);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    fn poll_recv(&self, cx: &mut Context) -> Poll<Option<T>> {
+        match self.try_recv() {
+            Ok(value) => return Poll::Ready(Some(value)),
+            Err(TryRecvError::Closed) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => {}
+        }
+        self.receiver_waker.register(cx.waker());
+        // Check again, as a value could have been sent before the waker was registered
+        match self.try_recv() {
+            Ok(value) => Poll::Ready(Some(value)),
+            Err(TryRecvError::Closed) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+
+    fn add_sender(self: &Arc<Self>) -> Arc<Self> {
+        self.senders.fetch_add(1, Ordering::Relaxed);
+        self.clone()
+    }
+
+    fn drop_sender(&self) {
+        if self.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.receiver_waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        self.receiver_alive.store(false, Ordering::Release);
+        self.send_waiters.wake_all();
+    }
+}
+
+/// Creates a channel, which holds at most `capacity` values.
+///
+/// # Panics
+/// If `capacity` is zero.
+#[must_use]
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "mpsc channel capacity must be positive");
+    let chan = Chan::new(Queue::Bounded(ArrayQueue::new(capacity)));
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+/// Creates a channel without a size limit. Sending allocates, so the `UnboundedSender`
+/// must not be used in interrupt handlers.
+#[must_use]
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, Receiver<T>) {
+    let chan = Chan::new(Queue::Unbounded(SegQueue::new()));
+    (UnboundedSender { chan: chan.clone() }, Receiver { chan })
+}
+
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Waits for free space, if the channel is full.
+    ///
+    /// # Errors
+    /// If the receiver was dropped.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = Some(value);
+        let mut waiter = Waiter::new(&self.chan.send_waiters);
+        poll_fn(|cx| loop {
+            let v = value.take().expect("value is only taken once per attempt");
+            match self.chan.try_send(v) {
+                Ok(()) => {
+                    waiter.cancel();
+                    return Poll::Ready(Ok(()));
+                }
+                Err(TrySendError::Closed(v)) => {
+                    waiter.cancel();
+                    return Poll::Ready(Err(SendError(v)));
+                }
+                Err(TrySendError::Full(v)) => value = Some(v),
+            }
+            if !waiter.register(cx.waker()) {
+                // Space could have been freed before registering
+                if self.chan.queue.len() < self.capacity() {
+                    continue;
+                }
+                return Poll::Pending;
+            }
+        })
+        .await
+    }
+
+    /// Never blocks or allocates, so it can be used in interrupt handlers. Waking the
+    /// receiver may free memory, which the heap allows with interrupts disabled.
+    ///
+    /// # Errors
+    /// If the channel is full or the receiver was dropped.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.chan.try_send(value)
+    }
+
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        !self.chan.receiver_alive.load(Ordering::Acquire)
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        match &self.chan.queue {
+            Queue::Bounded(queue) => queue.capacity(),
+            Queue::Unbounded(_) => usize::MAX,
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+pub struct UnboundedSender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> UnboundedSender<T> {
+    /// # Errors
+    /// If the receiver was dropped.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.chan
+            .try_send(value)
+            .map_err(|e| SendError(e.into_inner()))
+    }
+
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        !self.chan.receiver_alive.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Returns `None` once all senders are dropped and the channel is drained.
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| self.chan.poll_recv(cx)).await
+    }
+
+    /// # Errors
+    /// If the channel is empty or all senders are gone.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.chan.try_recv()
+    }
+
+    /// Senders fail from now on, but already sent values can still be received.
+    pub fn close(&mut self) {
+        self.chan.close();
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.chan.poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.chan.close();
+    }
+}
@@ -0,0 +1,131 @@
+//! Sends a single value from one task to another.
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::TryRecvError;
+
+struct Inner<T> {
+    value: Mutex<Option<T>>,
+    /// Set once the sender sent a value or got dropped
+    complete: AtomicBool,
+    receiver_alive: AtomicBool,
+    receiver_waker: AtomicWaker,
+}
+
+#[must_use]
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        value: Mutex::new(None),
+        complete: AtomicBool::new(false),
+        receiver_alive: AtomicBool::new(true),
+        receiver_waker: AtomicWaker::new(),
+    });
+    (
+        Sender {
+            inner: Some(inner.clone()),
+        },
+        Receiver { inner },
+    )
+}
+
+/// The sender got dropped without sending a value.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("sender dropped")
+    }
+}
+
+pub struct Sender<T> {
+    /// `None` after sending
+    inner: Option<Arc<Inner<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Consumes the sender. Can be used in interrupt handlers.
+    ///
+    /// # Errors
+    /// Returns the value if the receiver was dropped.
+    pub fn send(mut self, value: T) -> Result<(), T> {
+        let inner = self.inner.take().expect("`send` consumes the sender");
+        if !inner.receiver_alive.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        without_interrupts(|| *inner.value.lock() = Some(value));
+        inner.complete.store(true, Ordering::Release);
+        inner.receiver_waker.wake();
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.inner
+            .as_ref()
+            .map_or(true, |inner| !inner.receiver_alive.load(Ordering::Acquire))
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            inner.complete.store(true, Ordering::Release);
+            inner.receiver_waker.wake();
+        }
+    }
+}
+
+/// Resolves to the sent value.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// # Errors
+    /// If no value was sent yet or the sender was dropped.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        if !self.inner.complete.load(Ordering::Acquire) {
+            return Err(TryRecvError::Empty);
+        }
+        without_interrupts(|| self.inner.value.lock().take()).ok_or(TryRecvError::Closed)
+    }
+
+    /// Prevents the sender from sending.
+    pub fn close(&mut self) {
+        self.inner.receiver_alive.store(false, Ordering::Release);
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.try_recv() {
+            Ok(value) => return Poll::Ready(Ok(value)),
+            Err(TryRecvError::Closed) => return Poll::Ready(Err(RecvError)),
+            Err(TryRecvError::Empty) => {}
+        }
+        self.inner.receiver_waker.register(cx.waker());
+        match self.try_recv() {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,88 @@
+use super::*;
+use crate::concurrency::tests::{poll_once, Task};
+use alloc::sync::Arc;
+use futures_util::{task::waker, FutureExt};
+
+#[test_case]
+fn test_mpsc_bounded() {
+    let (tx, mut rx) = mpsc::channel(2);
+    let tx2 = tx.clone();
+    tx.try_send(1).unwrap();
+    tx2.send(2).now_or_never().unwrap().unwrap();
+    assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+    assert_eq!(rx.recv().now_or_never(), Some(Some(1)));
+    assert_eq!(rx.try_recv(), Ok(2));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    drop((tx, tx2));
+    assert_eq!(rx.recv().now_or_never(), Some(None));
+}
+
+#[test_case]
+fn test_mpsc_receiver_dropped() {
+    let (tx, rx) = mpsc::unbounded_channel();
+    tx.send(1).unwrap();
+    drop(rx);
+    assert!(tx.is_closed());
+    assert_eq!(tx.send(2), Err(SendError(2)));
+}
+
+#[test_case]
+fn test_oneshot() {
+    let (tx, mut rx) = oneshot::channel();
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    tx.send(7).unwrap();
+    assert_eq!(rx.now_or_never(), Some(Ok(7)));
+
+    let (tx, rx) = oneshot::channel::<u8>();
+    drop(tx);
+    assert_eq!(rx.now_or_never(), Some(Err(oneshot::RecvError)));
+}
+
+#[test_case]
+fn test_broadcast_lagged() {
+    let (tx, mut rx1) = broadcast::channel(2);
+    let mut rx2 = tx.subscribe();
+    for i in 0..3 {
+        tx.send(i).unwrap();
+    }
+    assert_eq!(rx1.try_recv(), Err(broadcast::TryRecvError::Lagged(1)));
+    assert_eq!(rx1.try_recv(), Ok(1));
+    assert_eq!(
+        rx2.recv().now_or_never(),
+        Some(Err(broadcast::RecvError::Lagged(1)))
+    );
+    assert_eq!(rx2.recv().now_or_never(), Some(Ok(1)));
+    assert_eq!(rx2.recv().now_or_never(), Some(Ok(2)));
+    assert!(rx2.recv().now_or_never().is_none());
+}
+
+#[test_case]
+fn test_watch_changed() {
+    let (tx, mut rx) = watch::channel(0);
+    assert!(!rx.has_changed());
+    assert!(rx.changed().now_or_never().is_none());
+    tx.send(5).unwrap();
+    assert_eq!(rx.changed().now_or_never(), Some(Ok(())));
+    assert_eq!(*rx.borrow(), 5);
+    drop(tx);
+    assert_eq!(rx.changed().now_or_never(), Some(Err(watch::RecvError)));
+}
+
+#[test_case]
+fn test_cancelled_waiters_are_removed() {
+    let task = Arc::new(Task);
+    let waker = waker(task.clone());
+
+    let (tx, mut rx) = mpsc::channel(1);
+    tx.try_send(1).unwrap();
+    assert!(poll_once(tx.send(2), &waker).is_pending());
+    assert_eq!(Arc::strong_count(&task), 2);
+    assert_eq!(rx.try_recv(), Ok(1));
+
+    let (btx, mut brx) = broadcast::channel::<u8>(1);
+    assert!(poll_once(brx.recv(), &waker).is_pending());
+    let (_wtx, mut wrx) = watch::channel(0);
+    assert!(poll_once(wrx.changed(), &waker).is_pending());
+    assert_eq!(Arc::strong_count(&task), 2);
+    drop(btx);
+}
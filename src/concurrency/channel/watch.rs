@@ -0,0 +1,188 @@
+//! Single value channel. Receivers only see the most recent value.
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    task::Poll,
+};
+use futures_util::future::poll_fn;
+use spin::{RwLock, RwLockReadGuard};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::SendError;
+use crate::concurrency::waker_list::{Waiter, WakerList};
+
+struct Shared<T> {
+    value: RwLock<T>,
+    /// Incremented on every `send`
+    version: AtomicU64,
+    sender_alive: AtomicBool,
+    receivers: AtomicUsize,
+    waiters: WakerList,
+}
+
+/// Creates a channel with the initial value `init`, which counts as already seen.
+#[must_use]
+pub fn channel<T>(init: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        value: RwLock::new(init),
+        version: AtomicU64::new(0),
+        sender_alive: AtomicBool::new(true),
+        receivers: AtomicUsize::new(1),
+        waiters: WakerList::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            seen_version: 0,
+        },
+    )
+}
+
+/// The sender was dropped.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("sender dropped")
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Replaces the current value and notifies all receivers. Usable in interrupt handlers,
+    /// as long as no receiver holds a `borrow()` while being interrupted.
+    ///
+    /// # Errors
+    /// If there are no receivers.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if self.shared.receivers.load(Ordering::Acquire) == 0 {
+            return Err(SendError(value));
+        }
+        self.send_replace(value);
+        Ok(())
+    }
+
+    /// Like `send`, but stores the value even without receivers and returns the old one.
+    pub fn send_replace(&self, value: T) -> T {
+        let old = without_interrupts(|| core::mem::replace(&mut *self.shared.value.write(), value));
+        self.shared.version.fetch_add(1, Ordering::AcqRel);
+        self.shared.waiters.wake_all();
+        old
+    }
+
+    /// Modifies the value in place and notifies all receivers.
+    pub fn send_modify(&self, modify: impl FnOnce(&mut T)) {
+        without_interrupts(|| modify(&mut *self.shared.value.write()));
+        self.shared.version.fetch_add(1, Ordering::AcqRel);
+        self.shared.waiters.wake_all();
+    }
+
+    #[must_use]
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.shared.value.read()
+    }
+
+    #[must_use]
+    pub fn subscribe(&self) -> Receiver<T> {
+        self.shared.receivers.fetch_add(1, Ordering::AcqRel);
+        Receiver {
+            shared: self.shared.clone(),
+            seen_version: self.shared.version.load(Ordering::Acquire),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.sender_alive.store(false, Ordering::Release);
+        self.shared.waiters.wake_all();
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    seen_version: u64,
+}
+
+impl<T> Receiver<T> {
+    /// Returns the current value without marking it as seen.
+    ///
+    /// Don't hold the guard across an `.await`, as it blocks the sender.
+    #[must_use]
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.shared.value.read()
+    }
+
+    /// Returns the current value and marks it as seen.
+    pub fn borrow_and_update(&mut self) -> RwLockReadGuard<'_, T> {
+        self.seen_version = self.shared.version.load(Ordering::Acquire);
+        self.shared.value.read()
+    }
+
+    #[must_use]
+    pub fn has_changed(&self) -> bool {
+        self.shared.version.load(Ordering::Acquire) != self.seen_version
+    }
+
+    /// Waits until a value is sent, which was not seen yet.
+    ///
+    /// # Errors
+    /// If the sender was dropped.
+    pub async fn changed(&mut self) -> Result<(), RecvError> {
+        let shared = self.shared.clone();
+        let check = |seen: u64| {
+            let version = shared.version.load(Ordering::Acquire);
+            if version != seen {
+                Some(Ok(version))
+            } else if !shared.sender_alive.load(Ordering::Acquire) {
+                Some(Err(RecvError))
+            } else {
+                None
+            }
+        };
+        let seen = self.seen_version;
+        let mut waiter = Waiter::new(&shared.waiters);
+        let version = poll_fn(|cx| loop {
+            if let Some(result) = check(seen) {
+                waiter.cancel();
+                return Poll::Ready(result);
+            }
+            if !waiter.register(cx.waker()) {
+                return match check(seen) {
+                    Some(result) => {
+                        waiter.cancel();
+                        Poll::Ready(result)
+                    }
+                    None => Poll::Pending,
+                };
+            }
+        })
+        .await?;
+        self.seen_version = version;
+        Ok(())
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::AcqRel);
+        Self {
+            shared: self.shared.clone(),
+            seen_version: self.seen_version,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receivers.fetch_sub(1, Ordering::AcqRel);
+    }
+}
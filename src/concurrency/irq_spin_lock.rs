@@ -0,0 +1,135 @@
+use core::{
+    cell::UnsafeCell,
+    hint::spin_loop,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use x86_64::instructions::interrupts;
+
+use crate::smp;
+
+const NO_OWNER: u64 = u64::MAX;
+
+/// Spin lock, which disables interrupts while it is held.
+///
+/// Use it for data shared with interrupt handlers. As interrupts are off, the holder can't
+/// be preempted, so keep the critical section short. Taking the lock twice on the same
+/// CPU panics in debug builds instead of deadlocking. Held locks are counted per CPU, so a
+/// panicking task isn't abandoned with the lock held.
+pub struct IrqSpinLock<T: ?Sized> {
+    locked: AtomicBool,
+    /// CPU id of the holder, only tracked in debug builds
+    owner: AtomicU64,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for IrqSpinLock<T> {}
+unsafe impl<T: ?Sized + Send> Sync for IrqSpinLock<T> {}
+
+impl<T> IrqSpinLock<T> {
+    #[must_use]
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            owner: AtomicU64::new(NO_OWNER),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> IrqSpinLock<T> {
+    /// Disables interrupts and spins until the lock is acquired. Interrupts are restored to
+    /// their previous state once the guard is dropped.
+    ///
+    /// # Panics
+    /// In debug builds, if the executing CPU already holds the lock.
+    pub fn lock(&self) -> IrqSpinLockGuard<'_, T> {
+        let interrupts_were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        if cfg!(debug_assertions)
+            && self.locked.load(Ordering::Relaxed)
+            && self.owner.load(Ordering::Relaxed) == smp::cpu_id() as u64
+        {
+            panic!("IrqSpinLock is already held by the executing CPU");
+        }
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spin_loop();
+        }
+        if cfg!(debug_assertions) {
+            self.owner.store(smp::cpu_id() as u64, Ordering::Relaxed);
+        }
+        smp::enter_critical();
+        IrqSpinLockGuard {
+            lock: self,
+            interrupts_were_enabled,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<IrqSpinLockGuard<'_, T>> {
+        let interrupts_were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            if cfg!(debug_assertions) {
+                self.owner.store(smp::cpu_id() as u64, Ordering::Relaxed);
+            }
+            smp::enter_critical();
+            Some(IrqSpinLockGuard {
+                lock: self,
+                interrupts_were_enabled,
+            })
+        } else {
+            if interrupts_were_enabled {
+                interrupts::enable();
+            }
+            None
+        }
+    }
+
+    #[must_use]
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+pub struct IrqSpinLockGuard<'a, T: ?Sized> {
+    lock: &'a IrqSpinLock<T>,
+    interrupts_were_enabled: bool,
+}
+
+impl<'a, T: ?Sized> Deref for IrqSpinLockGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for IrqSpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for IrqSpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        if cfg!(debug_assertions) {
+            self.lock.owner.store(NO_OWNER, Ordering::Relaxed);
+        }
+        self.lock.locked.store(false, Ordering::Release);
+        smp::exit_critical();
+        if self.interrupts_were_enabled {
+            interrupts::enable();
+        }
+    }
+}
@@ -1,9 +1,30 @@
-use alloc::boxed::Box;
-pub type Task = dyn 'static + FnOnce() + Send + Sync;
+//! Async-aware synchronisation primitives.
+//!
+//! Waiting tasks register their `Waker` and return `Poll::Pending`, so the executor can run
+//! other tasks in the meantime. Guards may be held across an `.await`, which would deadlock
+//! with `spin::Mutex`.
+//!
+//! CPU-bound work, which doesn't fit into a state machine, can run in a preemptively
+//! scheduled kernel `thread` instead. Threads synchronise with the `blocking` primitives,
+//! data shared with interrupt handlers is protected by an `IrqSpinLock`.
+mod barrier;
+pub mod blocking;
+pub mod channel;
+mod irq_spin_lock;
+mod mutex;
+mod notify;
+mod rwlock;
+mod semaphore;
+pub mod thread;
+mod waker_list;
 
-#[repr(C)]
-pub struct TCB {
-    stack: Box<[u8]>,
-    work: Box<Task>,
-    // RIP, RSP, CR3, other registers...?
-}
+#[cfg(test)]
+mod tests;
+
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use irq_spin_lock::{IrqSpinLock, IrqSpinLockGuard};
+pub use mutex::{Mutex, MutexGuard, MutexLockFuture};
+pub use notify::{Notified, Notify};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
+pub(crate) use waker_list::{Waiter, WakerList};
@@ -0,0 +1,144 @@
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+use super::waker_list::WakerList;
+
+/// A mutual exclusion lock, which parks the current task instead of spinning.
+///
+/// Unlike `spin::Mutex` the guard may be held across an `.await`.
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicBool,
+    waiters: WakerList,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    #[must_use]
+    pub fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            waiters: WakerList::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Returns a future, which resolves once the lock is acquired.
+    #[must_use]
+    pub fn lock(&self) -> MutexLockFuture<'_, T> {
+        MutexLockFuture {
+            mutex: self,
+            key: None,
+        }
+    }
+
+    #[must_use]
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    /// No locking needed, as the borrow checker guarantees exclusive access.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    #[must_use]
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[must_use = "futures do nothing unless polled"]
+pub struct MutexLockFuture<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+    /// Set while this future is queued in `mutex.waiters`
+    key: Option<u64>,
+}
+
+impl<'a, T: ?Sized> Future for MutexLockFuture<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        loop {
+            if let Some(guard) = mutex.try_lock() {
+                if let Some(key) = self.key.take() {
+                    mutex.waiters.remove(key);
+                }
+                return Poll::Ready(guard);
+            }
+            if !mutex.waiters.register(&mut self.key, cx.waker()) {
+                break;
+            }
+        }
+        // The lock could have been released between `try_lock` and `register`
+        match mutex.try_lock() {
+            Some(guard) => {
+                if let Some(key) = self.key.take() {
+                    mutex.waiters.remove(key);
+                }
+                Poll::Ready(guard)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for MutexLockFuture<'a, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            // We got woken but won't take the lock -> pass the wakeup on
+            if !self.mutex.waiters.remove(key) {
+                self.mutex.waiters.wake_one();
+            }
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+        self.mutex.waiters.wake_one();
+    }
+}
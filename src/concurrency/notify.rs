@@ -0,0 +1,120 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use super::waker_list::WakerList;
+
+/// Wakes up tasks waiting on `notified()`, without transporting any data.
+///
+/// `notify_one` stores a single permit if no task is waiting, so the notification is not
+/// lost if it races with the waiter. `notify_waiters` only wakes the currently waiting tasks.
+/// Both are safe to call from an interrupt handler.
+pub struct Notify {
+    permit: AtomicBool,
+    /// Incremented by every `notify_waiters`
+    generation: AtomicU64,
+    waiters: WakerList,
+}
+
+impl Notify {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            permit: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            waiters: WakerList::new(),
+        }
+    }
+
+    /// Wakes one waiting task or stores a permit for the next call to `notified()`.
+    pub fn notify_one(&self) {
+        if !self.waiters.wake_one() {
+            self.permit.store(true, Ordering::Release);
+            // A waiter could have registered inbetween
+            if self.waiters.wake_one() {
+                self.permit.store(false, Ordering::Release);
+            }
+        }
+    }
+
+    /// Wakes all tasks, which are currently waiting.
+    pub fn notify_waiters(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.waiters.wake_all();
+    }
+
+    #[must_use]
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            generation: self.generation.load(Ordering::Acquire),
+            key: None,
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[must_use = "futures do nothing unless polled"]
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    generation: u64,
+    key: Option<u64>,
+}
+
+impl Notified<'_> {
+    /// Whether `notify_waiters` was called since creating the future. Takes the permit of
+    /// `notify_one` otherwise.
+    fn take_notification(&self) -> bool {
+        self.notify.generation.load(Ordering::Acquire) != self.generation
+            || self.notify.permit.swap(false, Ordering::AcqRel)
+    }
+
+    /// Removes the registration. A `notify_one` woke it as well -> pass the wakeup on.
+    fn unregister(&mut self) {
+        if let Some(key) = self.key.take() {
+            // Don't swallow a `notify_one` meant for someone
+            if !self.notify.waiters.remove(key)
+                && self.notify.generation.load(Ordering::Acquire) == self.generation
+            {
+                self.notify.notify_one();
+            }
+        }
+    }
+}
+
+impl<'a> Future for Notified<'a> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.take_notification() {
+            self.unregister();
+            return Poll::Ready(());
+        }
+        let notify = self.notify;
+        if notify.waiters.register(&mut self.key, cx.waker()) {
+            // Woken by `notify_one`
+            return Poll::Ready(());
+        }
+        // `notify_one` could have stored a permit before registering, e.g. in an interrupt
+        // handler, when nobody was waiting yet
+        if self.take_notification() {
+            self.unregister();
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a> Drop for Notified<'a> {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
@@ -0,0 +1,168 @@
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Poll,
+};
+use futures_util::future::poll_fn;
+
+use super::waker_list::{Waiter, WakerList};
+
+/// Marks `state` as write locked. Any other value is the amount of readers.
+const WRITER: usize = usize::MAX;
+
+/// A reader-writer lock, which parks the current task instead of spinning.
+///
+/// Readers are preferred, so a steady stream of readers can starve a writer.
+pub struct RwLock<T: ?Sized> {
+    state: AtomicUsize,
+    readers: WakerList,
+    writers: WakerList,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    #[must_use]
+    pub fn new(data: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            readers: WakerList::new(),
+            writers: WakerList::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    #[must_use]
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state >= WRITER - 1 {
+                return None;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(RwLockReadGuard { lock: self }),
+                Err(current) => state = current,
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwLockWriteGuard { lock: self })
+    }
+
+    /// Resolves once shared access is granted.
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        let mut waiter = Waiter::new(&self.readers);
+        poll_fn(|cx| loop {
+            if let Some(guard) = self.try_read() {
+                waiter.cancel();
+                return Poll::Ready(guard);
+            }
+            if !waiter.register(cx.waker()) {
+                return match self.try_read() {
+                    Some(guard) => {
+                        waiter.cancel();
+                        Poll::Ready(guard)
+                    }
+                    None => Poll::Pending,
+                };
+            }
+        })
+        .await
+    }
+
+    /// Resolves once exclusive access is granted.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let mut waiter = Waiter::new(&self.writers);
+        poll_fn(|cx| loop {
+            if let Some(guard) = self.try_write() {
+                waiter.cancel();
+                return Poll::Ready(guard);
+            }
+            if !waiter.register(cx.waker()) {
+                return match self.try_write() {
+                    Some(guard) => {
+                        waiter.cancel();
+                        Poll::Ready(guard)
+                    }
+                    None => Poll::Pending,
+                };
+            }
+        })
+        .await
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        if self.lock.state.fetch_sub(1, Ordering::Release) == 1 {
+            self.lock.writers.wake_one();
+        }
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+        if self.lock.readers.wake_all() == 0 {
+            self.lock.writers.wake_one();
+        }
+    }
+}
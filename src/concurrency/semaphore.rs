@@ -0,0 +1,109 @@
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Poll,
+};
+use futures_util::future::poll_fn;
+
+use super::waker_list::{Waiter, WakerList};
+
+/// Counting semaphore. Waiting tasks are parked until enough permits are available.
+pub struct Semaphore {
+    permits: AtomicUsize,
+    waiters: WakerList,
+}
+
+impl Semaphore {
+    #[must_use]
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+            waiters: WakerList::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Acquire)
+    }
+
+    /// Adds `n` new permits, e.g. after a resource got freed outside of a permit.
+    pub fn add_permits(&self, n: usize) {
+        self.permits.fetch_add(n, Ordering::Release);
+        self.waiters.wake_all();
+    }
+
+    #[must_use]
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    #[must_use]
+    pub fn try_acquire_many(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        let mut current = self.permits.load(Ordering::Relaxed);
+        loop {
+            if current < n {
+                return None;
+            }
+            match self.permits.compare_exchange_weak(
+                current,
+                current - n,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(SemaphorePermit {
+                        semaphore: self,
+                        permits: n,
+                    })
+                }
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1).await
+    }
+
+    /// Resolves once `n` permits could be taken at once.
+    pub async fn acquire_many(&self, n: usize) -> SemaphorePermit<'_> {
+        let mut waiter = Waiter::new(&self.waiters);
+        poll_fn(|cx| loop {
+            if let Some(permit) = self.try_acquire_many(n) {
+                waiter.cancel();
+                return Poll::Ready(permit);
+            }
+            if !waiter.register(cx.waker()) {
+                return match self.try_acquire_many(n) {
+                    Some(permit) => {
+                        waiter.cancel();
+                        Poll::Ready(permit)
+                    }
+                    None => Poll::Pending,
+                };
+            }
+        })
+        .await
+    }
+}
+
+/// Returns its permits to the semaphore when dropped.
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl<'a> SemaphorePermit<'a> {
+    /// Drops the permit without returning it to the semaphore.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl<'a> Drop for SemaphorePermit<'a> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use super::*;
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    task::{Context, Poll, Waker},
+};
+use futures_util::{
+    pin_mut,
+    task::{waker, ArcWake},
+    FutureExt,
+};
+
+/// Only counts the references to the task, through `Arc::strong_count`
+pub(super) struct Task;
+
+impl ArcWake for Task {
+    fn wake_by_ref(_task: &Arc<Self>) {}
+}
+
+pub(super) fn poll_once<F: Future>(future: F, waker: &Waker) -> Poll<F::Output> {
+    pin_mut!(future);
+    future.poll(&mut Context::from_waker(waker))
+}
+
+#[test_case]
+fn test_mutex_excludes() {
+    let mutex = Mutex::new(0);
+    let mut guard = mutex.lock().now_or_never().expect("uncontended lock");
+    *guard += 1;
+    assert!(mutex.try_lock().is_none());
+    assert!(mutex.lock().now_or_never().is_none());
+    drop(guard);
+    assert_eq!(*mutex.try_lock().unwrap(), 1);
+}
+
+#[test_case]
+fn test_rwlock_readers_and_writer() {
+    let lock = RwLock::new(5);
+    let r1 = lock.read().now_or_never().unwrap();
+    let r2 = lock.try_read().unwrap();
+    assert_eq!(*r1 + *r2, 10);
+    assert!(lock.try_write().is_none());
+    drop((r1, r2));
+    let mut w = lock.write().now_or_never().unwrap();
+    *w = 6;
+    assert!(lock.try_read().is_none());
+    drop(w);
+    assert_eq!(*lock.try_read().unwrap(), 6);
+}
+
+#[test_case]
+fn test_semaphore_permits() {
+    let semaphore = Semaphore::new(2);
+    let a = semaphore.acquire().now_or_never().unwrap();
+    let b = semaphore.try_acquire().unwrap();
+    assert_eq!(semaphore.available_permits(), 0);
+    assert!(semaphore.acquire().now_or_never().is_none());
+    drop(a);
+    b.forget();
+    assert_eq!(semaphore.available_permits(), 1);
+}
+
+#[test_case]
+fn test_notify_stores_permit() {
+    let notify = Notify::new();
+    assert!(notify.notified().now_or_never().is_none());
+    notify.notify_one();
+    assert!(notify.notified().now_or_never().is_some());
+    assert!(notify.notified().now_or_never().is_none());
+}
+
+#[test_case]
+fn test_barrier_single_task_is_leader() {
+    let barrier = Barrier::new(1);
+    let result = barrier.wait().now_or_never().unwrap();
+    assert!(result.is_leader());
+}
+
+#[test_case]
+fn test_cancelled_waiters_are_removed() {
+    let task = Arc::new(Task);
+    let waker = waker(task.clone());
+    let semaphore = Semaphore::new(0);
+    assert!(poll_once(semaphore.acquire(), &waker).is_pending());
+    let barrier = Barrier::new(2);
+    assert!(poll_once(barrier.wait(), &waker).is_pending());
+    drop(waker);
+    // No waker is left queued
+    assert_eq!(Arc::strong_count(&task), 1);
+}
@@ -0,0 +1,478 @@
+//! Preemptive kernel threads.
+//!
+//! Every thread owns a heap allocated stack. The timer interrupt drives a round-robin
+//! scheduler, which switches threads once their time slice of `TIME_SLICE_TICKS` is used up.
+//! The thread, which called `init`, becomes the bootstrap thread and keeps the boot stack.
+//! An idle thread runs whenever no other thread is ready.
+//!
+//! A context switch saves the callee-saved registers and `RFLAGS` on the old stack and only
+//! stores `RSP` in the `TCB`. Caller-saved registers are already on the stack, either pushed
+//! by the compiler at the call to `cbos_switch_context` or by the interrupt handler.
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    arch::global_asm,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use spin::Mutex;
+use x86_64::instructions::interrupts::{self, without_interrupts};
+
+use crate::task::timer;
+
+pub const STACK_SIZE: usize = 16 * 1024;
+/// Timer ticks a thread may run before it gets preempted.
+const TIME_SLICE_TICKS: u8 = 2;
+/// Written to the lowest address of every stack, to detect overflows in debug builds.
+const STACK_CANARY: u64 = 0xDEAD_C0DE_CB05_57AC;
+/// Only the reserved bit is set -> starts with interrupts disabled
+const INITIAL_RFLAGS: u64 = 0x2;
+
+pub type Task = dyn 'static + FnOnce() + Send;
+
+static SCHEDULER: Mutex<Option<Scheduler>> = Mutex::new(None);
+/// Id of the running thread. Readable without taking the `SCHEDULER` lock.
+static CURRENT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    #[must_use]
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThreadState {
+    Running,
+    Ready,
+    /// Until the timer reaches the given tick
+    Sleeping(u64),
+    Parked,
+    Finished,
+}
+
+/// Thread control block
+#[repr(C)]
+pub struct TCB {
+    id: ThreadId,
+    /// Stack pointer while the thread is not running
+    rsp: u64,
+    state: ThreadState,
+    /// Empty for the bootstrap thread, which runs on the stack set up by the bootloader
+    stack: Box<[u8]>,
+    /// Taken by the thread on its first run
+    work: Option<Box<Task>>,
+}
+
+impl TCB {
+    fn new(work: Box<Task>) -> Box<Self> {
+        let mut stack = alloc::vec![0u8; STACK_SIZE].into_boxed_slice();
+        stack[..8].copy_from_slice(&STACK_CANARY.to_ne_bytes());
+
+        // Mimics the stack of a thread suspended in `cbos_switch_context`, so that its `ret`
+        // jumps into `thread_entry`.
+        let top = (stack.as_ptr() as u64 + STACK_SIZE as u64) & !0xf;
+        let initial = [
+            INITIAL_RFLAGS,
+            0, // r15
+            0, // r14
+            0, // r13
+            0, // r12
+            0, // rbx
+            0, // rbp
+            thread_entry as usize as u64,
+            0, // Return address of `thread_entry`, which never returns
+        ];
+        let rsp = top - 8 * initial.len() as u64;
+        unsafe {
+            core::ptr::copy_nonoverlapping(initial.as_ptr(), rsp as *mut u64, initial.len());
+        }
+
+        Box::new(Self {
+            id: ThreadId::new(),
+            rsp,
+            state: ThreadState::Ready,
+            stack,
+            work: Some(work),
+        })
+    }
+
+    fn bootstrap() -> Box<Self> {
+        Box::new(Self {
+            id: ThreadId::new(),
+            rsp: 0,
+            state: ThreadState::Running,
+            stack: Box::new([]),
+            work: None,
+        })
+    }
+
+    fn stack_intact(&self) -> bool {
+        self.stack.len() < 8 || self.stack[..8] == STACK_CANARY.to_ne_bytes()
+    }
+}
+
+struct Scheduler {
+    current: Box<TCB>,
+    ready: VecDeque<Box<TCB>>,
+    sleeping: Vec<Box<TCB>>,
+    parked: BTreeMap<ThreadId, Box<TCB>>,
+    /// Can't be freed by themselves, as they still run on their stack
+    dead: Vec<Box<TCB>>,
+    idle: Option<Box<TCB>>,
+    idle_id: ThreadId,
+    /// Unparks, which happened before the thread parked
+    unpark_tokens: BTreeSet<ThreadId>,
+    slice_left: u8,
+}
+
+impl Scheduler {
+    fn wake_sleepers(&mut self, now: u64) {
+        let mut i = 0;
+        while i < self.sleeping.len() {
+            match self.sleeping[i].state {
+                ThreadState::Sleeping(until) if until > now => i += 1,
+                _ => {
+                    let mut thread = self.sleeping.swap_remove(i);
+                    thread.state = ThreadState::Ready;
+                    self.ready.push_back(thread);
+                }
+            }
+        }
+    }
+}
+
+global_asm!(
+    r#"
+.global cbos_switch_context
+cbos_switch_context:
+    push rbp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    pushfq
+    mov [rdi], rsp
+    mov rsp, rsi
+    popfq
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    pop rbp
+    ret
+"#
+);
+
+extern "C" {
+    /// Saves the current context on the stack, stores the stack pointer in `old_rsp` and
+    /// continues with the context stored on the stack at `new_rsp`.
+    fn cbos_switch_context(old_rsp: *mut u64, new_rsp: u64);
+}
+
+/// Must be called once after the heap is initialized. The calling thread becomes the
+/// bootstrap thread.
+///
+/// # Panics
+/// If called twice.
+pub fn init() {
+    println!("Initialising kernel threads ...");
+    // Created first, so that it gets the id 0, which `CURRENT` starts with
+    let bootstrap = TCB::bootstrap();
+    let idle = TCB::new(Box::new(idle_loop));
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        assert!(scheduler.is_none(), "Threads are already initialised");
+        *scheduler = Some(Scheduler {
+            current: bootstrap,
+            ready: VecDeque::new(),
+            sleeping: Vec::new(),
+            parked: BTreeMap::new(),
+            dead: Vec::new(),
+            idle_id: idle.id,
+            idle: Some(idle),
+            unpark_tokens: BTreeSet::new(),
+            slice_left: TIME_SLICE_TICKS,
+        });
+    });
+}
+
+fn idle_loop() {
+    loop {
+        interrupts::enable_and_hlt();
+        yield_now();
+    }
+}
+
+/// Picks the next thread and switches to it. The current thread is filed according to its
+/// state. Returns immediately, if the current thread is running and nothing else is ready.
+///
+/// Interrupts must be disabled.
+fn reschedule() {
+    debug_assert!(!interrupts::are_enabled());
+    let (old_rsp, new_rsp) = {
+        let mut guard = SCHEDULER.lock();
+        let scheduler = match guard.as_mut() {
+            Some(scheduler) => scheduler,
+            None => return,
+        };
+        let next = match scheduler.ready.pop_front() {
+            Some(next) => next,
+            None if scheduler.current.state == ThreadState::Running => return,
+            None => scheduler
+                .idle
+                .take()
+                .expect("idle thread is the only runnable thread"),
+        };
+        let mut prev = core::mem::replace(&mut scheduler.current, next);
+        scheduler.current.state = ThreadState::Running;
+        CURRENT.store(scheduler.current.id.0, Ordering::Relaxed);
+        scheduler.slice_left = TIME_SLICE_TICKS;
+        debug_assert!(prev.stack_intact(), "Stack overflow in {:?}", prev.id);
+
+        let old_rsp: *mut u64 = &mut prev.rsp;
+        let new_rsp = scheduler.current.rsp;
+        match prev.state {
+            ThreadState::Running if prev.id == scheduler.idle_id => {
+                prev.state = ThreadState::Ready;
+                scheduler.idle = Some(prev);
+            }
+            ThreadState::Running | ThreadState::Ready => {
+                prev.state = ThreadState::Ready;
+                scheduler.ready.push_back(prev);
+            }
+            ThreadState::Sleeping(_) => scheduler.sleeping.push(prev),
+            ThreadState::Parked => {
+                scheduler.parked.insert(prev.id, prev);
+            }
+            ThreadState::Finished => scheduler.dead.push(prev),
+        }
+        // The boxed `TCB` doesn't move, so `old_rsp` stays valid
+        (old_rsp, new_rsp)
+    };
+    unsafe { cbos_switch_context(old_rsp, new_rsp) };
+    reap();
+}
+
+/// Frees the stacks of finished threads. We never run on one of them at this point.
+fn reap() {
+    let dead = without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_mut()
+            .map(|scheduler| core::mem::take(&mut scheduler.dead))
+    });
+    drop(dead);
+}
+
+/// First code executed by every new thread. Reached through the `ret` in `cbos_switch_context`.
+extern "C" fn thread_entry() -> ! {
+    reap();
+    let work = without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_mut()
+            .and_then(|scheduler| scheduler.current.work.take())
+            .expect("new thread has work")
+    });
+    interrupts::enable();
+    work();
+    exit();
+}
+
+/// Terminates the current thread.
+pub fn exit() -> ! {
+    interrupts::disable();
+    with_scheduler(|scheduler| {
+        scheduler.current.state = ThreadState::Finished;
+        scheduler.unpark_tokens.remove(&scheduler.current.id);
+    });
+    reschedule();
+    unreachable!("finished thread got scheduled again");
+}
+
+/// Called by the timer interrupt after the end of interrupt was sent.
+pub(crate) fn on_timer_tick() {
+    let preempt = {
+        let mut guard = SCHEDULER.lock();
+        let scheduler = match guard.as_mut() {
+            Some(scheduler) => scheduler,
+            None => return,
+        };
+        scheduler.wake_sleepers(timer::ticks());
+        scheduler.slice_left = scheduler.slice_left.saturating_sub(1);
+        let idling = scheduler.current.id == scheduler.idle_id;
+        !scheduler.ready.is_empty() && (scheduler.slice_left == 0 || idling)
+    };
+    if preempt {
+        reschedule();
+    }
+}
+
+/// # Panics
+/// If `init` was not called.
+fn with_scheduler<R>(f: impl FnOnce(&mut Scheduler) -> R) -> R {
+    without_interrupts(|| f(SCHEDULER.lock().as_mut().expect("Threads are initialised")))
+}
+
+/// A handle to a thread, which can be used to unpark it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thread {
+    id: ThreadId,
+}
+
+impl Thread {
+    #[must_use]
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    /// Makes a parked thread runnable again. If the thread isn't parked, its next call to
+    /// `park` returns immediately.
+    pub fn unpark(&self) {
+        with_scheduler(|scheduler| match scheduler.parked.remove(&self.id) {
+            Some(mut thread) => {
+                thread.state = ThreadState::Ready;
+                scheduler.ready.push_back(thread);
+            }
+            None => {
+                scheduler.unpark_tokens.insert(self.id);
+            }
+        });
+    }
+}
+
+/// # Panics
+/// If `init` was not called.
+#[must_use]
+pub fn current() -> Thread {
+    with_scheduler(|scheduler| Thread {
+        id: scheduler.current.id,
+    })
+}
+
+/// Id of the running thread, without taking any lock. Returns the id of the bootstrap
+/// thread before `init`.
+#[must_use]
+pub fn current_id() -> ThreadId {
+    ThreadId(CURRENT.load(Ordering::Relaxed))
+}
+
+/// Gives up the rest of the time slice.
+pub fn yield_now() {
+    without_interrupts(reschedule);
+}
+
+/// Blocks until `Thread::unpark` is called. Returns immediately if there was an unpark
+/// since the last call. Spurious wakeups are possible, so check the condition in a loop.
+pub fn park() {
+    without_interrupts(|| {
+        let park = with_scheduler(|scheduler| {
+            let id = scheduler.current.id;
+            if scheduler.unpark_tokens.remove(&id) {
+                false
+            } else {
+                scheduler.current.state = ThreadState::Parked;
+                true
+            }
+        });
+        if park {
+            reschedule();
+        }
+    });
+}
+
+/// Blocks the current thread for at least `ticks` timer ticks.
+pub fn sleep(ticks: u64) {
+    let until = timer::ticks() + ticks;
+    without_interrupts(|| {
+        with_scheduler(|scheduler| scheduler.current.state = ThreadState::Sleeping(until));
+        reschedule();
+    });
+}
+
+struct Packet<T> {
+    result: Mutex<Option<T>>,
+    finished: AtomicBool,
+    joiner: Mutex<Option<Thread>>,
+}
+
+/// Waits for the thread to finish with `join`. Dropping it detaches the thread.
+pub struct JoinHandle<T> {
+    thread: Thread,
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    #[must_use]
+    pub fn thread(&self) -> Thread {
+        self.thread
+    }
+
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.packet.finished.load(Ordering::Acquire)
+    }
+
+    /// Blocks until the thread finished and returns its result.
+    ///
+    /// # Panics
+    /// If called from the thread itself, as it would never return.
+    pub fn join(self) -> T {
+        assert_ne!(current(), self.thread, "A thread can't join itself");
+        loop {
+            if self.is_finished() {
+                break;
+            }
+            let me = current();
+            without_interrupts(|| *self.packet.joiner.lock() = Some(me));
+            if self.is_finished() {
+                break;
+            }
+            park();
+        }
+        without_interrupts(|| self.packet.result.lock().take())
+            .expect("finished thread has a result")
+    }
+}
+
+/// Spawns a new kernel thread, which is scheduled round-robin with all other threads.
+///
+/// # Panics
+/// If `init` was not called.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        result: Mutex::new(None),
+        finished: AtomicBool::new(false),
+        joiner: Mutex::new(None),
+    });
+    let their_packet = packet.clone();
+    let tcb = TCB::new(Box::new(move || {
+        let result = f();
+        without_interrupts(|| *their_packet.result.lock() = Some(result));
+        their_packet.finished.store(true, Ordering::Release);
+        if let Some(joiner) = without_interrupts(|| their_packet.joiner.lock().take()) {
+            joiner.unpark();
+        }
+    }));
+    let thread = Thread { id: tcb.id };
+    with_scheduler(|scheduler| scheduler.ready.push_back(tcb));
+    JoinHandle { thread, packet }
+}
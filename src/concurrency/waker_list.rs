@@ -0,0 +1,124 @@
+//! FIFO list of parked tasks, shared by all async primitives in this module.
+use alloc::collections::VecDeque;
+use core::task::Waker;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Each waiter is identified by a key, which is handed out on the first registration and
+/// stored in the future of the waiting task.
+pub(crate) struct WakerList {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    next_key: u64,
+    waiters: VecDeque<(u64, Waker)>,
+}
+
+impl WakerList {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                next_key: 0,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Registers `waker` under `key`, or refreshes the waker if `key` is still queued.
+    ///
+    /// Returns `true` and resets `key` to `None` if the waiter has been woken since its
+    /// last registration. Nothing is registered in that case.
+    pub fn register(&self, key: &mut Option<u64>, waker: &Waker) -> bool {
+        without_interrupts(|| {
+            let mut inner = self.inner.lock();
+            if let Some(k) = *key {
+                if let Some((_, w)) = inner.waiters.iter_mut().find(|(id, _)| *id == k) {
+                    if !w.will_wake(waker) {
+                        *w = waker.clone();
+                    }
+                    return false;
+                }
+                *key = None;
+                return true;
+            }
+            let k = inner.next_key;
+            inner.next_key += 1;
+            inner.waiters.push_back((k, waker.clone()));
+            *key = Some(k);
+            false
+        })
+    }
+
+    /// Returns `true` if `key` was still queued, `false` if it has been woken already.
+    pub fn remove(&self, key: u64) -> bool {
+        without_interrupts(|| {
+            let mut inner = self.inner.lock();
+            match inner.waiters.iter().position(|(id, _)| *id == key) {
+                Some(index) => {
+                    inner.waiters.remove(index);
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+
+    /// Wakes the longest waiting task. Returns `false` if nobody is waiting.
+    pub fn wake_one(&self) -> bool {
+        let waiter = without_interrupts(|| self.inner.lock().waiters.pop_front());
+        match waiter {
+            Some((_, waker)) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wakes all waiting tasks and returns how many there were.
+    pub fn wake_all(&self) -> usize {
+        let waiters = without_interrupts(|| core::mem::take(&mut self.inner.lock().waiters));
+        let amount = waiters.len();
+        for (_, waker) in waiters {
+            waker.wake();
+        }
+        amount
+    }
+}
+
+/// Registration of a waiting future in a `WakerList`. It is removed once the future is
+/// dropped or resolves, so a cancelled waiter doesn't keep its waker, and its task, queued.
+pub(crate) struct Waiter<'a> {
+    list: &'a WakerList,
+    key: Option<u64>,
+}
+
+impl<'a> Waiter<'a> {
+    pub fn new(list: &'a WakerList) -> Self {
+        Self { list, key: None }
+    }
+
+    /// See `WakerList::register`
+    pub fn register(&mut self, waker: &Waker) -> bool {
+        self.list.register(&mut self.key, waker)
+    }
+
+    /// Removes the registration, e.g. once the future resolved.
+    pub fn cancel(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.list.remove(key);
+        }
+    }
+}
+
+impl<'a> Drop for Waiter<'a> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            // Woken, but not going to use the wakeup -> pass it on
+            if !self.list.remove(key) {
+                self.list.wake_one();
+            }
+        }
+    }
+}
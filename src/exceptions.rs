@@ -26,6 +26,11 @@ lazy_static! {
 /// the exceptions!
 pub fn init_idt() {
     println!("Enabling exception handling ...");
+    load_idt();
+}
+
+/// Loads the shared IDT on the executing CPU, e.g. an application processor.
+pub fn load_idt() {
     IDT.load();
 }
 
@@ -0,0 +1,74 @@
+//! Boot parameters, passed through the firmware configuration interface of QEMU.
+//!
+//! They are named files given on the QEMU command line, e.g.
+//! `-fw_cfg name=opt/cbos/keymap,string=de`. Without QEMU, no file exists.
+//! Reference: [QEMU docs](https://www.qemu.org/docs/master/specs/fw_cfg.html)
+use alloc::{string::String, vec, vec::Vec};
+use x86_64::instructions::port::Port;
+
+use crate::concurrency::IrqSpinLock;
+
+const SELECTOR: u16 = 0x510;
+const DATA: u16 = 0x511;
+/// Items read through the selector
+const SIGNATURE: u16 = 0x0000;
+const FILE_DIR: u16 = 0x0019;
+/// Size, selector, reserved and name of a directory entry, all big endian
+const ENTRY_SIZE: usize = 64;
+const NAME_OFFSET: usize = 8;
+/// More files are considered garbage
+const MAX_FILES: usize = 4096;
+
+/// Selector and data port. Selecting an item restarts reading at its beginning.
+static PORTS: IrqSpinLock<(Port<u16>, Port<u8>)> =
+    IrqSpinLock::new((Port::new(SELECTOR), Port::new(DATA)));
+
+/// Fills `buffer` with the start of the item `selector`
+fn read(selector: u16, buffer: &mut [u8]) {
+    let mut ports = PORTS.lock();
+    unsafe {
+        ports.0.write(selector);
+        for byte in buffer {
+            *byte = ports.1.read();
+        }
+    }
+}
+
+fn is_present() -> bool {
+    let mut signature = [0; 4];
+    read(SIGNATURE, &mut signature);
+    signature == *b"QEMU"
+}
+
+/// Contents of the file `name`, e.g. `opt/cbos/keymap`
+#[must_use]
+pub fn file(name: &str) -> Option<Vec<u8>> {
+    if !is_present() {
+        return None;
+    }
+    let mut count = [0; 4];
+    read(FILE_DIR, &mut count);
+    let count = (u32::from_be_bytes(count) as usize).min(MAX_FILES);
+    let mut directory = vec![0; 4 + count * ENTRY_SIZE];
+    read(FILE_DIR, &mut directory);
+    directory[4..].chunks_exact(ENTRY_SIZE).find_map(|entry| {
+        let entry_name = &entry[NAME_OFFSET..];
+        let len = entry_name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(entry_name.len());
+        if &entry_name[..len] != name.as_bytes() {
+            return None;
+        }
+        let size = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+        let mut contents = vec![0; size as usize];
+        read(u16::from_be_bytes([entry[4], entry[5]]), &mut contents);
+        Some(contents)
+    })
+}
+
+/// Like `file`, as text without surrounding whitespace
+#[must_use]
+pub fn string(name: &str) -> Option<String> {
+    file(name).map(|contents| String::from_utf8_lossy(&contents).trim().into())
+}
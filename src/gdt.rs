@@ -1,34 +1,57 @@
 use crate::println;
+use alloc::boxed::Box;
 use lazy_static::lazy_static;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
-/// Loads the global descriptor table
+/// Size of every interrupt stack
+const IST_STACK_SIZE: usize = 5 * 4096;
+
+/// Loads the global descriptor table of the bootstrap processor
 pub fn init_gdt() {
+    println!("Load GDT ...");
+    load(&GDT);
+}
+
+/// Loads a new GDT with its own TSS and interrupt stacks. Every application processor
+/// needs one, as the busy flag of a TSS prevents sharing it between CPUs.
+pub fn init_ap_gdt() {
+    let mut tss = TaskStateSegment::new();
+    tss.interrupt_stack_table[IST_INDEX::DOUBLE_FAULT as usize] = {
+        let stack: &'static mut [u8] = Box::leak(alloc::vec![0; IST_STACK_SIZE].into_boxed_slice());
+        // Bytes only guarantee an alignment of 1
+        (VirtAddr::from_ptr(stack.as_ptr()) + IST_STACK_SIZE).align_down(16_u64)
+    };
+    let tss: &'static TaskStateSegment = Box::leak(Box::new(tss));
+    load(Box::leak(Box::new(create_gdt(tss))));
+}
+
+fn create_gdt(tss: &'static TaskStateSegment) -> (GlobalDescriptorTable, SegmentSelectors) {
+    let mut gdt = GlobalDescriptorTable::new();
+    let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+    let tss_selector = gdt.add_entry(Descriptor::tss_segment(tss));
+    (
+        gdt,
+        SegmentSelectors {
+            code_selector,
+            tss_selector,
+        },
+    )
+}
+
+fn load(gdt: &'static (GlobalDescriptorTable, SegmentSelectors)) {
     use x86_64::instructions::segmentation::{Segment, CS};
     use x86_64::instructions::tables::load_tss;
-    println!("Load GDT ...");
-    GDT.0.load();
+    gdt.0.load();
     unsafe {
-        CS::set_reg(GDT.1.code_selector);
-        load_tss(GDT.1.tss_selector);
+        CS::set_reg(gdt.1.code_selector);
+        load_tss(gdt.1.tss_selector);
     }
 }
 
 lazy_static! {
-    static ref GDT: (GlobalDescriptorTable, SegmentSelectors) = {
-        let mut gdt = GlobalDescriptorTable::new();
-        let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        (
-            gdt,
-            SegmentSelectors {
-                code_selector,
-                tss_selector,
-            },
-        )
-    };
+    static ref GDT: (GlobalDescriptorTable, SegmentSelectors) = create_gdt(&TSS);
 }
 
 struct SegmentSelectors {
@@ -51,12 +74,11 @@ lazy_static! {
     static ref TSS: TaskStateSegment = {
         let mut tss = TaskStateSegment::new();
         tss.interrupt_stack_table[IST_INDEX::DOUBLE_FAULT as usize] = {
-            const STACK_SIZE: usize = 5 * 4096;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+            static mut STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
 
             let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
             // stack_end
-            stack_start + STACK_SIZE
+            (stack_start + IST_STACK_SIZE).align_down(16_u64)
         };
         tss
     };
@@ -7,3 +7,9 @@ pub fn hlt_loop() -> ! {
         x86_64::instructions::hlt();
     }
 }
+
+/// Stops the executing CPU for good. Only an NMI or a reset wakes it.
+pub fn park() -> ! {
+    x86_64::instructions::interrupts::disable();
+    hlt_loop()
+}
@@ -1,4 +1,3 @@
-use lazy_static::lazy_static;
 use pic8259::ChainedPics;
 use spin::Mutex;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
@@ -17,14 +16,36 @@ pub static PICS: Mutex<ChainedPics> =
 pub fn init_pic() {
     println!("Enabling interrupt handling ...");
     unsafe { PICS.lock().initialize() };
+    // The firmware may have masked the mouse. IRQ 2 cascades to the second PIC.
+    unmask_irq(2);
+    unmask_irq(InterruptIndex::Mouse.as_u8() - PIC_1_OFFSET);
+    unmask_irq(InterruptIndex::Serial1.as_u8() - PIC_1_OFFSET);
     x86_64::instructions::interrupts::enable();
 }
 
+/// Allows the IRQ line `irq` (0..16) to raise interrupts
+fn unmask_irq(irq: u8) {
+    use x86_64::instructions::port::Port;
+    let _pics = PICS.lock();
+    let (mut port, line) = if irq < 8 {
+        (Port::<u8>::new(0x21), irq)
+    } else {
+        (Port::<u8>::new(0xa1), irq - 8)
+    };
+    unsafe {
+        let mask = port.read();
+        port.write(mask & !(1 << line));
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard = PIC_1_OFFSET + 1,
+    /// COM1
+    Serial1 = PIC_1_OFFSET + 4,
+    Mouse = PIC_2_OFFSET + 4,
 }
 
 impl InterruptIndex {
@@ -40,6 +61,12 @@ impl InterruptIndex {
 pub fn setup_interupt_handlers(idt: &mut InterruptDescriptorTable) {
     idt[InterruptIndex::Timer.as_usize()].set_handler_fn(handler_timer_interrupt);
     idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(handler_keyboard_interrupt);
+    idt[InterruptIndex::Mouse.as_usize()].set_handler_fn(handler_mouse_interrupt);
+    idt[InterruptIndex::Serial1.as_usize()].set_handler_fn(handler_serial1_interrupt);
+    idt[usize::from(crate::smp::apic::SPURIOUS_VECTOR)].set_handler_fn(handler_spurious_interrupt);
+    idt[usize::from(crate::smp::apic::WAKEUP_VECTOR)].set_handler_fn(handler_wakeup_interrupt);
+    idt[usize::from(crate::smp::apic::WATCHDOG_VECTOR)].set_handler_fn(handler_watchdog_interrupt);
+    idt[usize::from(crate::smp::apic::PARK_VECTOR)].set_handler_fn(handler_park_interrupt);
 }
 
 #[inline]
@@ -48,24 +75,86 @@ fn end_of_interrupt(what: InterruptIndex) {
     unsafe { pics.notify_end_of_interrupt(what.as_u8()) }
 }
 
-extern "x86-interrupt" fn handler_timer_interrupt(_stack_frame: InterruptStackFrame) {
-    use crate::task::timer;
-    timer::tick();
-    end_of_interrupt(InterruptIndex::Timer);
+/// Counts a running interrupt handler on the executing CPU, until it is dropped. See
+/// `smp::in_task_context`.
+struct InHandler;
+
+impl InHandler {
+    fn enter() -> Self {
+        crate::smp::enter_critical();
+        InHandler
+    }
 }
 
-extern "x86-interrupt" fn handler_keyboard_interrupt(_stack_frame: InterruptStackFrame) {
-    use pc_keyboard::{layouts, HandleControl, Keyboard, ScancodeSet1};
-    use x86_64::instructions::port::Port;
-    lazy_static! {
-        pub static ref KB: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)
-        );
+impl Drop for InHandler {
+    fn drop(&mut self) {
+        crate::smp::exit_critical();
     }
+}
+
+extern "x86-interrupt" fn handler_timer_interrupt(mut stack_frame: InterruptStackFrame) {
+    use crate::task::{executor::watchdog, timer};
+    let frame_pointer = watchdog::interrupted_frame_pointer();
+    {
+        let _handler = InHandler::enter();
+        timer::tick();
+        end_of_interrupt(InterruptIndex::Timer);
+        watchdog::on_timer_tick(&mut stack_frame, frame_pointer);
+    }
+    // Might switch to another thread, so the end of interrupt must be sent before. The
+    // handler isn't counted anymore, as the other thread continues outside of it.
+    crate::concurrency::thread::on_timer_tick();
+}
 
-    let mut port = Port::new(0x60);
-    let scancode: u8 = unsafe { port.read() };
-    crate::task::keyboard::add_scancode(scancode);
+/// Sent by the local APIC, when an interrupt vanished before it was accepted. Must not be
+/// acknowledged.
+extern "x86-interrupt" fn handler_spurious_interrupt(_stack_frame: InterruptStackFrame) {}
+
+/// Only interrupts `hlt`, the woken CPU looks for work itself.
+extern "x86-interrupt" fn handler_wakeup_interrupt(_stack_frame: InterruptStackFrame) {
+    let _handler = InHandler::enter();
+    crate::smp::apic::end_of_interrupt();
+}
+
+/// Sent by the watchdog on the bootstrap processor, when this CPU polls a task for too long.
+extern "x86-interrupt" fn handler_watchdog_interrupt(mut stack_frame: InterruptStackFrame) {
+    use crate::task::executor::watchdog;
+    let frame_pointer = watchdog::interrupted_frame_pointer();
+    let _handler = InHandler::enter();
+    crate::smp::apic::end_of_interrupt();
+    watchdog::handle_stall(&mut stack_frame, frame_pointer);
+}
+
+/// Sent by `smp::park_others`. The interrupted task is never resumed.
+extern "x86-interrupt" fn handler_park_interrupt(_stack_frame: InterruptStackFrame) {
+    crate::smp::apic::end_of_interrupt();
+    crate::hal::park();
+}
+
+extern "x86-interrupt" fn handler_keyboard_interrupt(_stack_frame: InterruptStackFrame) {
+    let _handler = InHandler::enter();
+    if let Some(scancode) = crate::ps2::read_keyboard_byte() {
+        crate::task::keyboard::add_scancode(scancode);
+    }
 
     end_of_interrupt(InterruptIndex::Keyboard);
 }
+
+extern "x86-interrupt" fn handler_mouse_interrupt(_stack_frame: InterruptStackFrame) {
+    let _handler = InHandler::enter();
+    if let Some(byte) = crate::ps2::read_mouse_byte() {
+        crate::task::mouse::add_byte(byte);
+    }
+
+    end_of_interrupt(InterruptIndex::Mouse);
+}
+
+extern "x86-interrupt" fn handler_serial1_interrupt(_stack_frame: InterruptStackFrame) {
+    let _handler = InHandler::enter();
+    // The UART raises the interrupt once, even if several bytes arrived
+    while let Some(byte) = crate::serial::try_receive() {
+        crate::task::serial::add_byte(byte);
+    }
+
+    end_of_interrupt(InterruptIndex::Serial1);
+}
@@ -0,0 +1,99 @@
+//! Kernel log ring.
+//!
+//! Keeps the latest `CAPACITY` bytes written with `kprint!`, so messages scrolled out of
+//! the kernel view can still be read, e.g. with `dmesg`. The ring lives in a static array,
+//! so it works before the heap is initialised and from interrupt handlers.
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use crate::concurrency::IrqSpinLock;
+
+#[cfg(test)]
+mod tests;
+
+/// Bytes kept, older ones are overwritten
+pub const CAPACITY: usize = 16 * 1024;
+
+static LOG: IrqSpinLock<Ring<CAPACITY>> = IrqSpinLock::new(Ring::new());
+
+struct Ring<const N: usize> {
+    buffer: [u8; N],
+    /// Index of the oldest byte
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> Ring<N> {
+    const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        // Only the tail fits anyway
+        let bytes = &bytes[bytes.len().saturating_sub(N)..];
+        for &byte in bytes {
+            let end = (self.start + self.len) % N;
+            self.buffer[end] = byte;
+            if self.len == N {
+                self.start = (self.start + 1) % N;
+            } else {
+                self.len += 1;
+            }
+        }
+    }
+
+    /// Without the first line, if it got partially overwritten
+    fn contents(&self) -> String {
+        let (first, second) = if self.start + self.len <= N {
+            (&self.buffer[self.start..self.start + self.len], &[][..])
+        } else {
+            (
+                &self.buffer[self.start..],
+                &self.buffer[..self.start + self.len - N],
+            )
+        };
+        let mut bytes = Vec::with_capacity(self.len);
+        bytes.extend_from_slice(first);
+        bytes.extend_from_slice(second);
+        let mut text = String::from_utf8_lossy(&bytes).into_owned();
+        if self.len == N {
+            match text.find('\n') {
+                Some(end) => text.replace_range(..=end, ""),
+                None => text.clear(),
+            }
+        }
+        text
+    }
+
+    fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> fmt::Write for Ring<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Appends to the log. Called by `kprint!`.
+pub fn write_fmt(args: fmt::Arguments) {
+    fmt::Write::write_fmt(&mut *LOG.lock(), args).unwrap();
+}
+
+/// The kept messages, oldest first
+#[must_use]
+pub fn contents() -> String {
+    LOG.lock().contents()
+}
+
+/// Drops all kept messages
+pub fn clear() {
+    LOG.lock().clear();
+}
@@ -0,0 +1,15 @@
+use super::*;
+
+#[test_case]
+fn ring_keeps_latest_bytes() {
+    let mut ring = Ring::<8>::new();
+    ring.push(b"ab\n");
+    assert_eq!(ring.contents(), "ab\n");
+    ring.push(b"cd\nef\n");
+    // Full, the partial first line is dropped
+    assert_eq!(ring.contents(), "cd\nef\n");
+    ring.push(b"g\nhijklmnop\n");
+    assert_eq!(ring.contents(), "");
+    ring.clear();
+    assert_eq!(ring.contents(), "");
+}
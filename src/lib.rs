@@ -21,11 +21,18 @@ use bootloader::entry_point;
 use bootloader::BootInfo;
 
 // Barebones os
+pub mod acpi;
 pub mod exceptions;
+pub mod fw_cfg;
 pub mod gdt;
 pub mod hal;
 pub mod interrupts;
+pub mod klog;
+pub mod pci;
+pub mod power;
+pub mod ps2;
 pub mod serial;
+pub mod smp;
 pub mod util;
 #[macro_use]
 pub mod vga;
@@ -46,12 +53,18 @@ pub mod task;
 pub mod programs;
 
 pub fn init(boot_info: &'static BootInfo) {
+    // Locks look up the CPU id, so this must run first
+    smp::init_bsp();
     // Avoid bug where first two commands don't output
     println!("Booting cbos ...");
     gdt::init_gdt();
     exceptions::init_idt();
+    ps2::init();
     interrupts::init_pic();
     memory::init(boot_info);
+    concurrency::thread::init();
+    smp::init(boot_info);
+    task::keyboard::init();
 }
 
 pub mod tests;
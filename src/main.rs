@@ -24,13 +24,31 @@ fn run() {
     };
 
     let mut kb = task::keyboard::ScancodeStream::new();
+    let mut serial = task::serial::SerialKeyStream::new();
     let mut executor = Executor::new();
     executor.set_global_spawner().unwrap();
+    // A failing program must not take down the kernel
+    executor::set_panic_isolation(true);
 
-    executor::spawn(Task::new(async move { programs::run_statusline().await }));
-    executor::spawn(Task::new(async move {
-        programs::run_shell(&mut kb).await;
-    }));
+    executor::spawn(
+        Task::new(async move { programs::run_statusline().await }).with_name("statusline"),
+    )
+    .unwrap();
+    executor::spawn(
+        Task::new(async move {
+            programs::run_shell(programs::Terminal::Vga(&mut kb)).await;
+        })
+        .with_name("shell"),
+    )
+    .unwrap();
+    // E.g. for tests driving QEMU with `-serial stdio` from the host
+    executor::spawn(
+        Task::new(async move {
+            programs::run_shell(programs::Terminal::Serial(&mut serial)).await;
+        })
+        .with_name("serial shell"),
+    )
+    .unwrap();
     executor.run();
     kprintln!("Reached end of run()");
     cbos::hal::hlt_loop();
@@ -54,6 +72,8 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    // Only returns, if the panic happened outside of an isolated task
+    task::executor::abort_panicking_task(info);
     eprintln!("{}", info);
     cbos::hal::hlt_loop();
 }
@@ -1,16 +1,32 @@
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::ptr::null_mut;
 use linked_list_allocator::LockedHeap;
+use x86_64::instructions::interrupts::without_interrupts;
 use x86_64::structures::paging::{
     mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
 };
 use x86_64::VirtAddr;
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
-pub const HEAP_SIZE: usize = 128 * 1024;
+/// Large enough for a few dozen kernel thread stacks
+pub const HEAP_SIZE: usize = 1024 * 1024;
 
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: IrqSafeHeap = IrqSafeHeap(LockedHeap::empty());
+
+/// Disables interrupts while the heap is locked. Otherwise an interrupt handler, which
+/// allocates (e.g. by waking a thread), deadlocks if it interrupts an allocation.
+struct IrqSafeHeap(LockedHeap);
+
+unsafe impl GlobalAlloc for IrqSafeHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| self.0.alloc(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        without_interrupts(|| self.0.dealloc(ptr, layout));
+    }
+}
 
 /// # Errors
 /// Returns an error, if the allocation of either a frame table or the actual physical memory
@@ -39,7 +55,7 @@ pub fn init_heap(
     }
 
     unsafe {
-        ALLOCATOR.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
+        ALLOCATOR.0.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
     }
     Ok(())
 }
@@ -24,8 +24,13 @@ use bootloader::{
     bootinfo::{MemoryMap, MemoryRegionType},
     BootInfo,
 };
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
 use x86_64::{
-    structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB},
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, Mapper, OffsetPageTable, Page, PageTable,
+        PageTableFlags, PhysFrame, Size4KiB, Translate,
+    },
     PhysAddr, VirtAddr,
 };
 
@@ -35,11 +40,19 @@ const FRAME_SIZE_HUGE_2MB: usize = 512 * FRAME_SIZE_NORMAL;
 #[allow(dead_code)]
 const FRAME_SIZE_HUGE_1GB: usize = 512 * FRAME_SIZE_HUGE_2MB;
 
+/// Frames below 1 MiB are left to firmware and the AP startup trampoline.
+const LOW_MEMORY_END: u64 = 0x10_0000;
+
 pub mod allocator;
 
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+/// Kept after `init` to map MMIO regions and the like.
+static PAGING: Mutex<Option<(OffsetPageTable<'static>, BootInfoFrameAllocator)>> = Mutex::new(None);
+
 pub fn init(boot_info: &'static BootInfo) {
     println!("Initialising page and heap allocator ...");
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    PHYSICAL_MEMORY_OFFSET.store(boot_info.physical_memory_offset, Ordering::Relaxed);
     let mut mapper = unsafe { init_mapper(phys_mem_offset) };
     let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
     let total_pages = frame_allocator.available_frames();
@@ -55,6 +68,78 @@ pub fn init(boot_info: &'static BootInfo) {
         let total_memory_kb = region.range.end_frame_number * 4;
         println!("Total physical memory: {} MiB", total_memory_kb / 1024);
     }
+    *PAGING.lock() = Some((mapper, frame_allocator));
+}
+
+/// Virtual address, at which the physical address is reachable through the complete
+/// mapping of the physical memory.
+#[must_use]
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) + addr.as_u64())
+}
+
+/// Makes sure that a physical region, e.g. of a memory mapped device, is reachable through
+/// `phys_to_virt`. The bootloader only maps regions listed in the memory map.
+///
+/// # Errors
+/// If page tables could not be allocated.
+///
+/// # Panics
+/// If called before `init`.
+pub fn map_physical_region(start: PhysAddr, size: u64) -> Result<VirtAddr, MapToError<Size4KiB>> {
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH;
+    let mut paging = PAGING.lock();
+    let (mapper, frame_allocator) = paging.as_mut().expect("memory is initialised");
+    let first = PhysFrame::<Size4KiB>::containing_address(start);
+    let last = PhysFrame::<Size4KiB>::containing_address(start + size.saturating_sub(1));
+    for frame in PhysFrame::range_inclusive(first, last) {
+        let page = Page::containing_address(phys_to_virt(frame.start_address()));
+        if mapper.translate_addr(page.start_address()).is_some() {
+            continue;
+        }
+        unsafe { mapper.map_to(frame, page, flags, frame_allocator) }?.flush();
+    }
+    Ok(phys_to_virt(start))
+}
+
+/// Maps a frame below 1 MiB to the same virtual address, as needed by code that runs
+/// while paging gets enabled.
+///
+/// # Errors
+/// If page tables could not be allocated or the page is used otherwise.
+///
+/// # Panics
+/// If called before `init`.
+pub fn identity_map_low_frame(frame: PhysFrame) -> Result<(), MapToError<Size4KiB>> {
+    debug_assert!(frame.start_address().as_u64() < LOW_MEMORY_END);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let mut paging = PAGING.lock();
+    let (mapper, frame_allocator) = paging.as_mut().expect("memory is initialised");
+    let page = Page::containing_address(VirtAddr::new(frame.start_address().as_u64()));
+    if mapper.translate_addr(page.start_address()) == Some(frame.start_address()) {
+        return Ok(());
+    }
+    unsafe { mapper.identity_map(frame, flags, frame_allocator) }?.flush();
+    Ok(())
+}
+
+/// Returns the first usable frame below 1 MiB, which is never handed out by the frame
+/// allocator.
+#[must_use]
+pub fn free_low_frame(memory_map: &MemoryMap) -> Option<PhysFrame> {
+    memory_map
+        .iter()
+        .filter(|region| region.region_type == MemoryRegionType::Usable)
+        .find_map(|region| {
+            // The first page holds the real mode IVT and BIOS data
+            let start = region.range.start_addr().max(FRAME_SIZE_NORMAL as u64);
+            let end = region.range.end_addr().min(LOW_MEMORY_END);
+            (start + FRAME_SIZE_NORMAL as u64 <= end)
+                .then(|| PhysFrame::containing_address(PhysAddr::new(start)))
+        })
 }
 
 unsafe fn init_mapper(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
@@ -112,7 +197,9 @@ impl BootInfoFrameAllocator {
             .filter(|region| region.region_type == MemoryRegionType::Usable);
         let addr_ranges =
             usable_regions.map(|region| region.range.start_addr()..region.range.end_addr());
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(FRAME_SIZE_NORMAL));
+        let frame_addresses = addr_ranges
+            .flat_map(|r| r.step_by(FRAME_SIZE_NORMAL))
+            .filter(|addr| *addr >= LOW_MEMORY_END);
         frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
     }
 
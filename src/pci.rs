@@ -0,0 +1,150 @@
+//! PCI devices, enumerated through the legacy configuration ports.
+//!
+//! Every function of every device on every bus is probed, which is fast enough for the few
+//! buses of QEMU. Reference: [OSDev Wiki](https://wiki.osdev.org/PCI)
+use alloc::vec::Vec;
+use core::fmt;
+use x86_64::instructions::port::Port;
+
+use crate::concurrency::IrqSpinLock;
+
+const CONFIG_ADDRESS: u16 = 0xcf8;
+const CONFIG_DATA: u16 = 0xcfc;
+const ENABLE: u32 = 1 << 31;
+/// Read for the vendor id of absent functions
+const NO_VENDOR: u16 = 0xffff;
+const DEVICES_PER_BUS: u8 = 32;
+const FUNCTIONS_PER_DEVICE: u8 = 8;
+const MULTI_FUNCTION: u8 = 1 << 7;
+
+/// Address and data port, which must be accessed in pairs
+static PORTS: IrqSpinLock<(Port<u32>, Port<u32>)> =
+    IrqSpinLock::new((Port::new(CONFIG_ADDRESS), Port::new(CONFIG_DATA)));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl Address {
+    /// The aligned dword at `offset` of the configuration space
+    #[must_use]
+    pub fn read(self, offset: u8) -> u32 {
+        let address = ENABLE
+            | u32::from(self.bus) << 16
+            | u32::from(self.device) << 11
+            | u32::from(self.function) << 8
+            | u32::from(offset & 0xfc);
+        let mut ports = PORTS.lock();
+        unsafe {
+            ports.0.write(address);
+            ports.1.read()
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Device {
+    pub address: Address,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+}
+
+impl Device {
+    fn probe(address: Address) -> Option<Self> {
+        let [vendor_low, vendor_high, device_low, device_high] = address.read(0x00).to_le_bytes();
+        let vendor_id = u16::from_le_bytes([vendor_low, vendor_high]);
+        if vendor_id == NO_VENDOR {
+            return None;
+        }
+        let [revision, prog_if, subclass, class] = address.read(0x08).to_le_bytes();
+        Some(Self {
+            address,
+            vendor_id,
+            device_id: u16::from_le_bytes([device_low, device_high]),
+            class,
+            subclass,
+            prog_if,
+            revision,
+        })
+    }
+
+    /// Of the class and subclass, e.g. "Host bridge"
+    #[must_use]
+    pub fn class_name(&self) -> &'static str {
+        match (self.class, self.subclass) {
+            (0x01, 0x01) => "IDE controller",
+            (0x01, 0x06) => "SATA controller",
+            (0x01, 0x08) => "NVMe controller",
+            (0x01, _) => "Mass storage controller",
+            (0x02, 0x00) => "Ethernet controller",
+            (0x02, _) => "Network controller",
+            (0x03, 0x00) => "VGA compatible controller",
+            (0x03, _) => "Display controller",
+            (0x04, 0x03) => "Audio device",
+            (0x04, _) => "Multimedia controller",
+            (0x05, _) => "Memory controller",
+            (0x06, 0x00) => "Host bridge",
+            (0x06, 0x01) => "ISA bridge",
+            (0x06, 0x04) => "PCI bridge",
+            (0x06, _) => "Bridge",
+            (0x07, _) => "Communication controller",
+            (0x08, _) => "System peripheral",
+            (0x0c, 0x03) => "USB controller",
+            (0x0c, 0x05) => "SMBus",
+            (0x0c, _) => "Serial bus controller",
+            _ => "Unclassified device",
+        }
+    }
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {:04x}:{:04x} (rev {:02x})",
+            self.address,
+            self.class_name(),
+            self.vendor_id,
+            self.device_id,
+            self.revision
+        )
+    }
+}
+
+/// All functions of all devices, ordered by address
+#[must_use]
+pub fn devices() -> Vec<Device> {
+    let mut devices = Vec::new();
+    for bus in 0..=u8::MAX {
+        for device in 0..DEVICES_PER_BUS {
+            let address = |function| Address {
+                bus,
+                device,
+                function,
+            };
+            let first = match Device::probe(address(0)) {
+                Some(first) => first,
+                None => continue,
+            };
+            let header_type = address(0).read(0x0c).to_le_bytes()[2];
+            devices.push(first);
+            if header_type & MULTI_FUNCTION != 0 {
+                devices.extend((1..FUNCTIONS_PER_DEVICE).filter_map(|f| Device::probe(address(f))));
+            }
+        }
+    }
+    devices
+}
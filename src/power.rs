@@ -0,0 +1,219 @@
+//! Shutdown, reboot and halt.
+//!
+//! `prepare` tells the tasks waiting in `notified` what is about to happen and gives them
+//! `GRACE_TICKS` to react, e.g. to flush output. Afterwards `shutdown`, `reboot` and `halt`
+//! park all other CPUs, which stops all tasks, and never return.
+//!
+//! - Shutdown enters the ACPI sleep state S5, with the sleep types of `\_S5` from the DSDT.
+//!   Without ACPI, it tries the PM ports of the PIIX4 and ICH9 in QEMU, of Bochs and of
+//!   VirtualBox and at last QEMU's `isa-debug-exit` device.
+//! - Reboot tries the ACPI reset register, the reset line of the 8042 and at last a triple
+//!   fault.
+//! - Halt parks the executing CPU as well.
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use lazy_static::lazy_static;
+use x86_64::{
+    instructions::{interrupts, port::Port, tables::lidt},
+    structures::DescriptorTablePointer,
+    PhysAddr, VirtAddr,
+};
+
+use crate::{
+    acpi::{self, Fadt, GenericAddress},
+    concurrency::Notify,
+    hal, memory, ps2, smp,
+    task::timer,
+    tests::{exit_qemu, QemuExitCode},
+};
+
+/// Timer ticks, which tasks get to react to the notification
+pub const GRACE_TICKS: u64 = 10;
+
+/// Enables the sleep state written to `SLP_TYP`
+const SLP_EN: u16 = 1 << 13;
+const SLP_TYP_SHIFT: u16 = 10;
+const SLP_TYP_MASK: u16 = 0b111 << SLP_TYP_SHIFT;
+/// Set once the firmware switched to ACPI mode
+const SCI_EN: u16 = 1;
+/// Polls of PM1a control, until the firmware is given up on
+const ACPI_ENABLE_TIMEOUT: usize = 1_000_000;
+
+/// Ports and values, which power off emulators without ACPI
+const FALLBACK_PM_PORTS: [(u16, u16); 3] = [
+    // PIIX4 and ICH9 in QEMU
+    (0x604, 0x2000),
+    // Bochs and older QEMU
+    (0xb004, 0x2000),
+    (0x4004, 0x3400),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Action {
+    Shutdown = 1,
+    Reboot = 2,
+    Halt = 3,
+}
+
+impl Action {
+    /// Never returns
+    pub fn perform(self) -> ! {
+        match self {
+            Action::Shutdown => shutdown(),
+            Action::Reboot => reboot(),
+            Action::Halt => halt(),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Shutdown => f.write_str("shutdown"),
+            Action::Reboot => f.write_str("reboot"),
+            Action::Halt => f.write_str("halt"),
+        }
+    }
+}
+
+/// 0 until an action was requested
+static REQUESTED: AtomicU8 = AtomicU8::new(0);
+
+lazy_static! {
+    static ref NOTIFY: Notify = Notify::new();
+}
+
+/// The action, which is about to be performed
+#[must_use]
+pub fn requested() -> Option<Action> {
+    match REQUESTED.load(Ordering::Acquire) {
+        1 => Some(Action::Shutdown),
+        2 => Some(Action::Reboot),
+        3 => Some(Action::Halt),
+        _ => None,
+    }
+}
+
+/// Resolves once an action was requested, with the action.
+pub async fn notified() -> Action {
+    // Before checking, so a notification inbetween isn't missed
+    let notified = NOTIFY.notified();
+    if let Some(action) = requested() {
+        return action;
+    }
+    notified.await;
+    requested().expect("Requested before notifying")
+}
+
+/// Notifies the tasks and waits `GRACE_TICKS` for them to react. The notification can't be
+/// taken back, so the action must be performed afterwards, even if this future is dropped.
+pub async fn prepare(action: Action) {
+    REQUESTED.store(action as u8, Ordering::Release);
+    NOTIFY.notify_waiters();
+    timer::sleep(GRACE_TICKS).await;
+}
+
+/// Powers the machine off. Halts, if nothing worked.
+pub fn shutdown() -> ! {
+    // Other CPUs could be parked while holding the lock of the heap or the page tables
+    let s5 = sleep_state_s5();
+    smp::park_others();
+    interrupts::disable();
+    if let Some((fadt, sleep_types)) = s5 {
+        enter_sleep_state(&fadt, sleep_types);
+    }
+    unsafe {
+        for (port, value) in FALLBACK_PM_PORTS {
+            Port::<u16>::new(port).write(value);
+        }
+    }
+    // Only exits QEMU, if started with `-device isa-debug-exit`, e.g. by the tests
+    exit_qemu(QemuExitCode::Success);
+    hal::park()
+}
+
+/// The FADT and the sleep types of S5, if the DSDT defines them
+fn sleep_state_s5() -> Option<(Fadt, (u16, u16))> {
+    let fadt = Fadt::parse()?;
+    let sleep_types = acpi::sleep_types(fadt.dsdt()?.body(), b"_S5_")?;
+    if fadt.pm1a_control == 0 {
+        return None;
+    }
+    Some((fadt, sleep_types))
+}
+
+fn enter_sleep_state(fadt: &Fadt, (a, b): (u16, u16)) {
+    let sleep = |control: u16, sleep_type: u16| {
+        (control & !SLP_TYP_MASK) | ((sleep_type << SLP_TYP_SHIFT) & SLP_TYP_MASK) | SLP_EN
+    };
+    let mut pm1a = Port::<u16>::new(fadt.pm1a_control);
+    unsafe {
+        if pm1a.read() & SCI_EN == 0 && fadt.smi_command != 0 && fadt.acpi_enable != 0 {
+            Port::<u8>::new(fadt.smi_command).write(fadt.acpi_enable);
+            for _ in 0..ACPI_ENABLE_TIMEOUT {
+                if pm1a.read() & SCI_EN != 0 {
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+        }
+        if fadt.pm1b_control != 0 {
+            let mut pm1b = Port::<u16>::new(fadt.pm1b_control);
+            pm1b.write(sleep(pm1b.read(), b));
+        }
+        pm1a.write(sleep(pm1a.read(), a));
+    }
+}
+
+/// Resets the machine
+pub fn reboot() -> ! {
+    let reset = reset_register();
+    smp::park_others();
+    interrupts::disable();
+    match reset {
+        Some((ResetRegister::Io(port), value)) => unsafe { Port::<u8>::new(port).write(value) },
+        Some((ResetRegister::Memory(address), value)) => unsafe {
+            address.as_mut_ptr::<u8>().write_volatile(value);
+        },
+        None => {}
+    }
+    ps2::pulse_reset_line();
+    // Without an IDT, the next exception can't be handled, which resets the CPU
+    unsafe {
+        lidt(&DescriptorTablePointer {
+            limit: 0,
+            base: VirtAddr::zero(),
+        });
+    }
+    interrupts::int3();
+    hal::park()
+}
+
+/// ACPI reset register, which is mapped before the other CPUs are parked
+enum ResetRegister {
+    Io(u16),
+    Memory(VirtAddr),
+}
+
+/// The register and the value, which resets the machine
+fn reset_register() -> Option<(ResetRegister, u8)> {
+    let (register, value) = Fadt::parse()?.reset?;
+    let register = match register.space {
+        GenericAddress::SYSTEM_IO => ResetRegister::Io(u16::try_from(register.address).ok()?),
+        GenericAddress::SYSTEM_MEMORY => ResetRegister::Memory(
+            memory::map_physical_region(PhysAddr::new(register.address), 1).ok()?,
+        ),
+        // E.g. PCI configuration space
+        _ => return None,
+    };
+    Some((register, value))
+}
+
+/// Stops all tasks and parks all CPUs
+pub fn halt() -> ! {
+    smp::park_others();
+    hal::park()
+}
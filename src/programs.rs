@@ -1,5 +1,9 @@
-mod shell;
+pub mod line_editor;
+pub mod shell;
 mod statusline;
+pub mod terminal;
 
+pub use line_editor::LineEditor;
 pub use shell::run as run_shell;
 pub use statusline::run as run_statusline;
+pub use terminal::{Console, Terminal};
@@ -0,0 +1,463 @@
+//! Line editing for interactive programs, drawn into `STDOUT` or with ANSI escape sequences
+//! on a serial terminal.
+//!
+//! Supported keys:
+//! - Backspace and Delete remove the character before and under the cursor
+//! - Left, Right, Home and End (or Ctrl+A, Ctrl+E) move the cursor
+//! - Ctrl+K and Ctrl+U delete up to the end and the start of the line
+//! - Ctrl+W deletes the word before the cursor
+//! - Up and Down browse the history
+//! - Tab completes the word at the cursor, see `Completer`
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    vec::Vec,
+};
+use core::fmt::Write;
+use futures_util::StreamExt;
+use pc_keyboard::{DecodedKey, KeyCode};
+
+use super::terminal::{Console, Terminal};
+use crate::task::{keyboard::ScancodeStream, serial::SerialKeyStream};
+use crate::vga::{
+    primitives::{ScreenChar, ScreenPos},
+    VgaBuffer, View, STDOUT,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Characters in a line. Long lines wrap, so it has to fit into the `STDOUT` area.
+const MAX_LEN: usize = 256;
+
+const CTRL_A: char = '\u{1}';
+const CTRL_E: char = '\u{5}';
+const CTRL_K: char = '\u{b}';
+const CTRL_U: char = '\u{15}';
+const CTRL_W: char = '\u{17}';
+const BACKSPACE: char = '\u{8}';
+const DELETE: char = '\u{7f}';
+
+/// Suggests completions for the word at the cursor
+pub trait Completer: Send + Sync {
+    /// `words` are the complete words before the one being completed, which is `word`.
+    /// Candidates not starting with `word` are ignored.
+    fn complete(&self, words: &[&str], word: &str) -> Vec<String>;
+}
+
+impl<F> Completer for F
+where
+    F: Fn(&[&str], &str) -> Vec<String> + Send + Sync,
+{
+    fn complete(&self, words: &[&str], word: &str) -> Vec<String> {
+        self(words, word)
+    }
+}
+
+/// Completes the first word from a list of commands and hands the arguments over to the
+/// completer of the command, if it has one.
+#[derive(Default)]
+pub struct CommandCompleter {
+    commands: BTreeMap<String, Option<Box<dyn Completer>>>,
+}
+
+impl CommandCompleter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A command, whose arguments aren't completed
+    pub fn add(&mut self, command: &str) {
+        self.commands.insert(command.into(), None);
+    }
+
+    pub fn add_with_arguments(&mut self, command: &str, arguments: impl Completer + 'static) {
+        self.commands
+            .insert(command.into(), Some(Box::new(arguments)));
+    }
+}
+
+impl Completer for CommandCompleter {
+    fn complete(&self, words: &[&str], word: &str) -> Vec<String> {
+        match words.split_first() {
+            None => self.commands.keys().cloned().collect(),
+            Some((command, arguments)) => match self.commands.get(*command) {
+                Some(Some(completer)) => completer.complete(arguments, word),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+/// Previously entered lines, the oldest first
+pub struct History {
+    entries: VecDeque<String>,
+    capacity: usize,
+}
+
+impl History {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Ignores empty lines and repetitions of the previous one.
+    pub fn push(&mut self, line: &str) {
+        if line.trim().is_empty() || self.entries.back().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.entries.push_back(line.into());
+        self.truncate();
+    }
+
+    /// Drops the oldest entries, if there are more than `capacity`.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.truncate();
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn truncate(&mut self) {
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+pub struct LineEditor {
+    line: Vec<char>,
+    cursor: usize,
+    history: History,
+    /// Entry shown while browsing the history
+    browsing: Option<usize>,
+    /// The line being edited before browsing started
+    draft: Vec<char>,
+    completer: Option<Box<dyn Completer>>,
+    /// Ambiguous completions, which should be listed
+    candidates: Vec<String>,
+}
+
+impl LineEditor {
+    #[must_use]
+    pub fn new(history_size: usize) -> Self {
+        Self {
+            line: Vec::new(),
+            cursor: 0,
+            history: History::new(history_size),
+            browsing: None,
+            draft: Vec::new(),
+            completer: None,
+            candidates: Vec::new(),
+        }
+    }
+
+    pub fn set_completer(&mut self, completer: impl Completer + 'static) {
+        self.completer = Some(Box::new(completer));
+    }
+
+    #[must_use]
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Prints `prompt` and reads a line after it. The line is added to the history.
+    pub async fn read_line(&mut self, terminal: &mut Terminal<'_>, prompt: &str) -> String {
+        match terminal {
+            Terminal::Vga(kb) => self.read_line_vga(kb, prompt).await,
+            Terminal::Serial(keys) => self.read_line_serial(keys, prompt).await,
+        }
+    }
+
+    /// Prints `prompt` to `STDERR` and draws the line into `STDOUT`
+    async fn read_line_vga(&mut self, kb: &mut ScancodeStream, prompt: &str) -> String {
+        eprint!("{}", prompt);
+        let mut start = STDOUT.lock().cursor();
+        let mut drawn = 0;
+        self.render(&mut STDOUT.lock(), &mut start, &mut drawn, true);
+        while let Some(key) = kb.next().await {
+            let submitted = self.handle_key(key);
+            let mut stdout = STDOUT.lock();
+            let listing = !self.candidates.is_empty();
+            self.render(
+                &mut stdout,
+                &mut start,
+                &mut drawn,
+                submitted.is_none() && !listing,
+            );
+            if let Some(line) = submitted {
+                stdout.put_byte(b'\n');
+                return line;
+            }
+            if listing {
+                // Below the line, followed by the prompt and the line again
+                stdout.put_byte(b'\n');
+                for candidate in core::mem::take(&mut self.candidates) {
+                    stdout.print(&candidate);
+                    stdout.print("  ");
+                }
+                stdout.put_byte(b'\n');
+                drop(stdout);
+                eprint!("{}", prompt);
+                start = STDOUT.lock().cursor();
+                drawn = 0;
+                self.render(&mut STDOUT.lock(), &mut start, &mut drawn, true);
+            }
+        }
+        String::new()
+    }
+
+    /// Prints `prompt` to the serial terminal and redraws the line after every key
+    async fn read_line_serial(&mut self, keys: &mut SerialKeyStream, prompt: &str) -> String {
+        let console = Console::Serial;
+        console.eprint(prompt);
+        // Of the terminal, within the line
+        let mut shown = 0;
+        while let Some(key) = keys.next().await {
+            if let Some(line) = self.handle_key(key) {
+                console.print("\n");
+                return line;
+            }
+            if !self.candidates.is_empty() {
+                let candidates = core::mem::take(&mut self.candidates);
+                console.print(&format!("\n{}\n", candidates.join("  ")));
+                console.eprint(prompt);
+                shown = 0;
+            }
+            console.print(&self.render_ansi(&mut shown));
+        }
+        String::new()
+    }
+
+    /// Escape sequences, which redraw the line after the prompt. `shown` is the cursor of the
+    /// terminal, which is moved to the one of the line.
+    fn render_ansi(&self, shown: &mut usize) -> String {
+        let mut output = String::new();
+        if *shown > 0 {
+            let _ = write!(output, "\x1b[{}D", shown);
+        }
+        output.extend(self.line.iter());
+        // Clears the rest of a line, which shrunk
+        output.push_str("\x1b[K");
+        let back = self.line.len() - self.cursor;
+        if back > 0 {
+            let _ = write!(output, "\x1b[{}D", back);
+        }
+        *shown = self.cursor;
+        output
+    }
+
+    /// Applies `key` to the line. Returns the line, once Enter was pressed.
+    fn handle_key(&mut self, key: DecodedKey) -> Option<String> {
+        match key {
+            DecodedKey::Unicode('\n') => return Some(self.submit()),
+            DecodedKey::Unicode(BACKSPACE) => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.line.remove(self.cursor);
+                }
+            }
+            DecodedKey::Unicode(DELETE) | DecodedKey::RawKey(KeyCode::Delete) => {
+                if self.cursor < self.line.len() {
+                    self.line.remove(self.cursor);
+                }
+            }
+            DecodedKey::Unicode(CTRL_A) | DecodedKey::RawKey(KeyCode::Home) => self.cursor = 0,
+            DecodedKey::Unicode(CTRL_E) | DecodedKey::RawKey(KeyCode::End) => {
+                self.cursor = self.line.len();
+            }
+            DecodedKey::Unicode(CTRL_K) => self.line.truncate(self.cursor),
+            DecodedKey::Unicode(CTRL_U) => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            DecodedKey::Unicode(CTRL_W) => {
+                let before = &self.line[..self.cursor];
+                let word_end = before.iter().rposition(|c| *c != ' ').map_or(0, |i| i + 1);
+                let word_start = before[..word_end]
+                    .iter()
+                    .rposition(|c| *c == ' ')
+                    .map_or(0, |i| i + 1);
+                self.line.drain(word_start..self.cursor);
+                self.cursor = word_start;
+            }
+            DecodedKey::RawKey(KeyCode::ArrowLeft) => self.cursor = self.cursor.saturating_sub(1),
+            DecodedKey::RawKey(KeyCode::ArrowRight) => {
+                self.cursor = (self.cursor + 1).min(self.line.len());
+            }
+            DecodedKey::RawKey(KeyCode::ArrowUp) => self.browse_back(),
+            DecodedKey::RawKey(KeyCode::ArrowDown) => self.browse_forward(),
+            DecodedKey::Unicode('\t') => self.complete(),
+            DecodedKey::Unicode(character) if !character.is_control() => {
+                if self.line.len() < MAX_LEN {
+                    self.line.insert(self.cursor, character);
+                    self.cursor += 1;
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn insert(&mut self, text: &str) {
+        for character in text.chars() {
+            if self.line.len() >= MAX_LEN {
+                break;
+            }
+            self.line.insert(self.cursor, character);
+            self.cursor += 1;
+        }
+    }
+
+    /// Completes the word before the cursor as far as it is unambiguous. If that doesn't
+    /// add anything, the candidates are listed.
+    fn complete(&mut self) {
+        let completer = match &self.completer {
+            Some(completer) => completer,
+            None => return,
+        };
+        let word_start = self.line[..self.cursor]
+            .iter()
+            .rposition(|c| *c == ' ')
+            .map_or(0, |i| i + 1);
+        let before: String = self.line[..word_start].iter().collect();
+        let words: Vec<&str> = before.split_whitespace().collect();
+        let word: String = self.line[word_start..self.cursor].iter().collect();
+
+        let mut candidates = completer.complete(&words, &word);
+        candidates.retain(|candidate| candidate.starts_with(word.as_str()));
+        candidates.sort_unstable();
+        candidates.dedup();
+        match candidates.as_slice() {
+            [] => {}
+            [candidate] => {
+                let rest = String::from(&candidate[word.len()..]) + " ";
+                self.insert(&rest);
+            }
+            [first, others @ ..] => {
+                let common = others.iter().fold(first.len(), |common, other| {
+                    first
+                        .char_indices()
+                        .zip(other.chars())
+                        .find(|((_, a), b)| a != b)
+                        .map_or(common.min(other.len()), |((i, _), _)| common.min(i))
+                });
+                if common > word.len() {
+                    let rest = String::from(&first[word.len()..common]);
+                    self.insert(&rest);
+                } else {
+                    self.candidates = candidates;
+                }
+            }
+        }
+    }
+
+    fn submit(&mut self) -> String {
+        let line: String = self.line.drain(..).collect();
+        self.cursor = 0;
+        self.browsing = None;
+        self.history.push(&line);
+        line
+    }
+
+    fn show(&mut self, line: Vec<char>) {
+        self.line = line;
+        self.cursor = self.line.len();
+    }
+
+    fn browse_back(&mut self) {
+        let index = match self.browsing {
+            Some(index) => index.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.line.clone();
+                self.history.len() - 1
+            }
+        };
+        self.browsing = Some(index);
+        self.show(self.history.entries[index].chars().collect());
+    }
+
+    fn browse_forward(&mut self) {
+        if let Some(index) = self.browsing {
+            if index + 1 < self.history.len() {
+                self.browsing = Some(index + 1);
+                self.show(self.history.entries[index + 1].chars().collect());
+            } else {
+                self.browsing = None;
+                let draft = core::mem::take(&mut self.draft);
+                self.show(draft);
+            }
+        }
+    }
+
+    /// Redraws the line, which starts at `start`, and scrolls if it doesn't fit. `drawn`
+    /// is the amount of cells painted previously, which are cleared if the line shrunk.
+    #[allow(clippy::cast_possible_truncation)]
+    fn render(&self, view: &mut View, start: &mut ScreenPos, drawn: &mut usize, cursor: bool) {
+        let size = view.size();
+        let cols = usize::from(size.cols);
+        // One more cell for the cursor at the end of the line
+        let cells = self.line.len() + 1;
+        while start.row > 0
+            && usize::from(start.row) * cols + usize::from(start.col) + cells
+                > usize::from(size.rows) * cols
+        {
+            view.shift_up();
+            start.row -= 1;
+        }
+        let position = |index: usize| {
+            let offset = usize::from(start.col) + index;
+            ScreenPos {
+                row: start.row + (offset / cols) as u8,
+                col: (offset % cols) as u8,
+            }
+        };
+        let color_code = view.color_code();
+        for index in 0..cells.max(*drawn) {
+            let character = self.line.get(index).copied().unwrap_or(' ');
+            let screen_char = ScreenChar {
+                ascii_character: if character.is_ascii() {
+                    character as u8
+                } else {
+                    0xfe
+                },
+                color_code: if cursor && index == self.cursor {
+                    color_code.inverted()
+                } else {
+                    color_code
+                },
+            };
+            view.write_at(position(index), screen_char);
+        }
+        *drawn = cells;
+        view.set_cursor(position(self.line.len()));
+    }
+}
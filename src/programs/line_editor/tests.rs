@@ -0,0 +1,159 @@
+use super::*;
+use alloc::vec;
+
+fn type_str(editor: &mut LineEditor, s: &str) {
+    for character in s.chars() {
+        assert_eq!(editor.handle_key(DecodedKey::Unicode(character)), None);
+    }
+}
+
+fn press(editor: &mut LineEditor, code: KeyCode) {
+    assert_eq!(editor.handle_key(DecodedKey::RawKey(code)), None);
+}
+
+fn enter(editor: &mut LineEditor) -> String {
+    editor.handle_key(DecodedKey::Unicode('\n')).unwrap()
+}
+
+#[test_case]
+fn backspace_and_delete() {
+    let mut editor = LineEditor::new(4);
+    type_str(&mut editor, "helllo");
+    type_str(
+        &mut editor,
+        &[BACKSPACE, BACKSPACE].iter().collect::<String>(),
+    );
+    type_str(&mut editor, "o!");
+    press(&mut editor, KeyCode::Home);
+    press(&mut editor, KeyCode::Delete);
+    type_str(&mut editor, "H");
+    assert_eq!(enter(&mut editor), "Hello!");
+}
+
+#[test_case]
+fn cursor_movement() {
+    let mut editor = LineEditor::new(4);
+    type_str(&mut editor, "ac");
+    press(&mut editor, KeyCode::ArrowLeft);
+    type_str(&mut editor, "b");
+    type_str(&mut editor, &CTRL_A.to_string());
+    press(&mut editor, KeyCode::ArrowLeft);
+    type_str(&mut editor, ">");
+    type_str(&mut editor, &CTRL_E.to_string());
+    press(&mut editor, KeyCode::ArrowRight);
+    type_str(&mut editor, "<");
+    assert_eq!(enter(&mut editor), ">abc<");
+}
+
+#[test_case]
+fn kill_commands() {
+    let mut editor = LineEditor::new(4);
+    type_str(&mut editor, "echo hello  world");
+    type_str(&mut editor, &CTRL_W.to_string());
+    assert_eq!(editor.line.iter().collect::<String>(), "echo hello  ");
+    type_str(&mut editor, &CTRL_W.to_string());
+    assert_eq!(editor.line.iter().collect::<String>(), "echo ");
+
+    type_str(&mut editor, "a b");
+    for _ in 0..3 {
+        press(&mut editor, KeyCode::ArrowLeft);
+    }
+    type_str(&mut editor, &CTRL_K.to_string());
+    assert_eq!(editor.line.iter().collect::<String>(), "echo ");
+    press(&mut editor, KeyCode::ArrowLeft);
+    type_str(&mut editor, &CTRL_U.to_string());
+    assert_eq!(enter(&mut editor), " ");
+}
+
+#[test_case]
+fn history_browsing() {
+    let mut editor = LineEditor::new(2);
+    for line in ["one", "two", "two", "three"] {
+        type_str(&mut editor, line);
+        enter(&mut editor);
+    }
+    // Repetitions are skipped and the oldest entry dropped
+    assert_eq!(
+        editor.history().iter().collect::<Vec<_>>(),
+        ["two", "three"]
+    );
+
+    type_str(&mut editor, "dra");
+    press(&mut editor, KeyCode::ArrowUp);
+    press(&mut editor, KeyCode::ArrowUp);
+    press(&mut editor, KeyCode::ArrowUp);
+    assert_eq!(editor.line.iter().collect::<String>(), "two");
+    press(&mut editor, KeyCode::ArrowDown);
+    press(&mut editor, KeyCode::ArrowDown);
+    type_str(&mut editor, "ft");
+    assert_eq!(enter(&mut editor), "draft");
+
+    editor.history_mut().set_capacity(1);
+    assert_eq!(editor.history().iter().collect::<Vec<_>>(), ["draft"]);
+}
+
+fn completing_editor() -> LineEditor {
+    let mut completer = CommandCompleter::new();
+    for command in ["help", "history", "keymap"] {
+        completer.add(command);
+    }
+    completer.add_with_arguments("keymap", |_: &[&str], _: &str| {
+        vec!["de".into(), "dvorak".into(), "us".into()]
+    });
+    let mut editor = LineEditor::new(4);
+    editor.set_completer(completer);
+    editor
+}
+
+#[test_case]
+fn completes_unambiguous_words() {
+    let mut editor = completing_editor();
+    type_str(&mut editor, "k\t");
+    assert_eq!(editor.line.iter().collect::<String>(), "keymap ");
+    type_str(&mut editor, "u\t");
+    assert_eq!(enter(&mut editor), "keymap us ");
+}
+
+#[test_case]
+fn lists_ambiguous_candidates() {
+    let mut editor = completing_editor();
+    type_str(&mut editor, "h\t");
+    assert_eq!(editor.candidates, ["help", "history"]);
+    // The common prefix is inserted, before anything is listed
+    editor.candidates.clear();
+    type_str(
+        &mut editor,
+        &[BACKSPACE, BACKSPACE].iter().collect::<String>(),
+    );
+    type_str(&mut editor, "keymap d\t");
+    assert_eq!(editor.line.iter().collect::<String>(), "keymap d");
+    assert_eq!(editor.candidates, ["de", "dvorak"]);
+
+    let mut editor = completing_editor();
+    type_str(&mut editor, "keymap dv\t");
+    assert!(editor.candidates.is_empty());
+    assert_eq!(enter(&mut editor), "keymap dvorak ");
+}
+
+#[test_case]
+fn unknown_commands_are_not_completed() {
+    let mut editor = completing_editor();
+    type_str(&mut editor, "help k\t");
+    assert!(editor.candidates.is_empty());
+    assert_eq!(enter(&mut editor), "help k");
+}
+
+#[test_case]
+fn renders_with_escape_sequences() {
+    let mut editor = LineEditor::new(4);
+    let mut shown = 0;
+    type_str(&mut editor, "abc");
+    assert_eq!(editor.render_ansi(&mut shown), "abc\x1b[K");
+    assert_eq!(shown, 3);
+    press(&mut editor, KeyCode::ArrowLeft);
+    press(&mut editor, KeyCode::ArrowLeft);
+    assert_eq!(editor.render_ansi(&mut shown), "\x1b[3Dabc\x1b[K\x1b[2D");
+    type_str(&mut editor, &CTRL_K.to_string());
+    assert_eq!(editor.render_ansi(&mut shown), "\x1b[1Da\x1b[K");
+    assert_eq!(shown, 1);
+}
@@ -1,35 +1,586 @@
 //! Minell. A `MInimal shELL`.
-//! Supports basic operations, like:
-//! - None
+//!
+//! Lines are split into words by the `tokenizer`, the first word names a `Command` of the
+//! registry. Other modules can add their own commands with `register`.
+//!
+//! `$NAME` expands shell variables, which are managed with `set`, `unset` and `export`. The
+//! prompt is the expanded variable `PS1`.
+//!
+//! Commands write to an `Io` instead of the screen, so `a | b` can feed the output of one
+//! command to the next one and `> file` and `>> file` redirect it to a file, see `pipeline`.
+//!
+//! `command &` runs the command as a background job, see `jobs`. Ctrl+C cancels the command
+//! in the foreground. Ctrl+Z only stops jobs, which were brought to the foreground with
+//! `fg`. The shell can't continue before its own command finished, so that can't be stopped.
+//!
+//! Lines, and the files run with `source`, are scripts with conditionals, loops and functions,
+//! see `script`. Before the first prompt, the shell sources `init.sh`.
+//!
+//! A shell runs on a `Terminal`, the VGA screen with the keyboard or the serial line. Its
+//! output and errors go to the `Console` of that terminal.
 
-use crate::prelude::*;
-use crate::task;
-use crate::task::keyboard::ScancodeStream;
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{future::Future, pin::Pin};
+use futures_util::{future, pin_mut};
+
+use super::line_editor::{Completer, LineEditor};
+use super::terminal::{Console, Terminal};
+use crate::task::{
+    executor,
+    keyboard::{self, Hotkey, KeyCode, ScancodeStream},
+    serial::SerialKeyStream,
+    yield_now, Task,
+};
+
+mod builtins;
+mod command;
+mod diagnostics;
+pub mod files;
+mod filters;
+mod io;
+mod jobs;
+mod pipeline;
+mod script;
+mod tokenizer;
+mod variables;
+
+#[cfg(test)]
+mod tests;
+
+pub use command::{
+    commands, find, register, unregister, Command, CommandFuture, CommandResult, RegisterError,
+};
+pub use io::{pipe, Input, Io, Output, PipeReader, PipeWriter, PIPE_CAPACITY};
+pub use jobs::{
+    Control, ControlState, Controlled, Foreground, ForegroundGuard, Job, JobState, Jobs,
+    CANCELLED_STATUS,
+};
+pub use pipeline::{parse, ParseError, Pipeline, Redirect, Stage};
+pub use script::{parse as parse_script, ScriptError, Statement};
+pub use tokenizer::{expand, is_valid_name, lex, tokenize, tokenize_with, Token, TokenizeError};
+pub use variables::{var, Environment, InvalidName, Variables, ENVIRONMENT};
+
+/// Entries of the command history
+const HISTORY_SIZE: usize = 32;
+
+/// Of functions and scripts, so endless recursion doesn't overflow the stack
+const MAX_CALL_DEPTH: usize = 16;
+
+/// Unless `PS1` is set to something else
+const DEFAULT_PROMPT: &str = "> ";
+
+/// Sourced before the first prompt
+pub const INIT_SCRIPT: &str = "init.sh";
+
+/// `INIT_SCRIPT`, unless the file was already created, e.g. by a test
+const EMBEDDED_INIT_SCRIPT: &str = include_str!("shell/init.sh");
+
+/// Ctrl+C and Ctrl+Z, as sent by a serial terminal
+const INTERRUPT_KEY: char = '\u{3}';
+const STOP_KEY: char = '\u{1a}';
 
 /// Entrypoint.
-pub async fn run(kb: &mut ScancodeStream) {
-    eprintln!("\nMinell. A MInimal shELL.\nType help for help. Exit to exit ..");
+pub async fn run(mut terminal: Terminal<'_>) {
+    let console = terminal.console();
+    console.eprintln(format_args!(
+        "\nMinell. A MInimal shELL.\nType help for help. Exit to exit .."
+    ));
+    let mut shell = Shell::new();
+    shell.console = console;
+    if !files::exists(INIT_SCRIPT) {
+        files::write(INIT_SCRIPT, EMBEDDED_INIT_SCRIPT);
+    }
+    execute_interactively(
+        &mut shell,
+        &mut terminal,
+        &format!("source {}", INIT_SCRIPT),
+    )
+    .await;
     loop {
-        eprint!("> ");
-        // parse_input
-        let command: String = task::keyboard::get_and_print_line(kb, 80).await;
-        println!("");
-        parse_command(&command).await;
+        shell.report_jobs();
+        let prompt = shell.prompt();
+        let line = shell.editor.read_line(&mut terminal, &prompt).await;
+        execute_interactively(&mut shell, &mut terminal, &line).await;
     }
 }
 
-async fn parse_command(command: &str) {
-    async {}.await;
-    match command {
-        "help" => print_help(),
-        "shutdown" | "exit" => todo!("Not implemented yet"),
-        _ => println!("Command not found. Type `help` for more information."),
+/// Executes the line, while Ctrl+C and Ctrl+Z control the foreground
+async fn execute_interactively(shell: &mut Shell, terminal: &mut Terminal<'_>, line: &str) {
+    match terminal {
+        Terminal::Vga(kb) => execute_with_hotkeys(shell, kb, line).await,
+        Terminal::Serial(keys) => {
+            let foreground = shell.foreground();
+            let execute = shell.execute(line);
+            let control = control_keys(keys, &foreground);
+            pin_mut!(execute, control);
+            future::select(execute, control).await;
+        }
+    }
+}
+
+/// Reads the serial terminal while a command runs. Other keys are kept for the next prompt,
+/// e.g. the following lines of a script sent by the host.
+async fn control_keys(keys: &mut SerialKeyStream, foreground: &Foreground) {
+    loop {
+        if keys.wait_for(&[INTERRUPT_KEY, STOP_KEY]).await == INTERRUPT_KEY {
+            foreground.interrupt();
+        } else {
+            foreground.stop();
+        }
+    }
+}
+
+/// Executes the line with Ctrl+C and Ctrl+Z as hotkeys of the keyboard
+async fn execute_with_hotkeys(shell: &mut Shell, kb: &mut ScancodeStream, line: &str) {
+    // Only while a command runs, at the prompt the keys belong to the line editor
+    let interrupt = shell.foreground();
+    let stop = shell.foreground();
+    let hotkeys = [
+        keyboard::register_hotkey(Hotkey::new(KeyCode::C).ctrl(), move |_| {
+            interrupt.interrupt();
+        }),
+        keyboard::register_hotkey(Hotkey::new(KeyCode::Z).ctrl(), move |_| {
+            stop.stop();
+        }),
+    ];
+    {
+        let execute = shell.execute(line);
+        pin_mut!(execute);
+        // Nobody reads the keyboard meanwhile, but the hotkeys need to be decoded
+        future::select(execute, kb.events().pump()).await;
+    }
+    for hotkey in hotkeys {
+        keyboard::unregister_hotkey(hotkey);
     }
 }
 
-fn print_help() {
-    println!("Alternatives are denoted using |");
-    println!("======= Command : Function =======");
-    println!("           help : Prints this.");
-    println!("exit | shutdown : Shuts down the pc.");
+/// Future of `Shell::run_statements`, boxed as functions and `source` run statements recursively
+type Statements<'a> = Pin<Box<dyn Future<Output = ()> + Send + Sync + 'a>>;
+
+/// State of a shell, which commands may access
+pub struct Shell {
+    editor: LineEditor,
+    variables: Variables,
+    /// Of the previous command, 0 on success
+    status: u8,
+    jobs: Jobs,
+    foreground: Arc<Foreground>,
+    functions: BTreeMap<String, Arc<Vec<Statement>>>,
+    /// `$1`, ... of the running script or function
+    arguments: Vec<String>,
+    /// Of the running script or function
+    depth: usize,
+    /// Of the terminal the shell runs on
+    console: Console,
+}
+
+impl Shell {
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        let mut editor = LineEditor::new(HISTORY_SIZE);
+        editor.set_completer(RegistryCompleter);
+        let mut variables = Variables::default();
+        variables
+            .set("PS1", DEFAULT_PROMPT)
+            .expect("PS1 is a valid name");
+        Self {
+            editor,
+            variables,
+            status: 0,
+            jobs: Jobs::default(),
+            foreground: Arc::default(),
+            functions: BTreeMap::new(),
+            arguments: Vec::new(),
+            depth: 0,
+            console: Console::Vga,
+        }
+    }
+
+    /// Runs jobs, with a copy of the variables and functions
+    fn subshell(&self) -> Self {
+        let mut shell = Self::new();
+        shell.variables = self.variables.clone();
+        shell.status = self.status;
+        shell.functions = self.functions.clone();
+        shell.arguments = self.arguments.clone();
+        shell.console = self.console;
+        shell
+    }
+
+    #[must_use]
+    pub fn editor(&self) -> &LineEditor {
+        &self.editor
+    }
+
+    #[must_use]
+    pub fn variables(&self) -> &Variables {
+        &self.variables
+    }
+
+    pub fn variables_mut(&mut self) -> &mut Variables {
+        &mut self.variables
+    }
+
+    #[must_use]
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    pub fn jobs_mut(&mut self) -> &mut Jobs {
+        &mut self.jobs
+    }
+
+    /// Where the output of the terminal and errors go
+    #[must_use]
+    pub fn console(&self) -> Console {
+        self.console
+    }
+
+    /// Receives Ctrl+C and Ctrl+Z, e.g. from hotkeys
+    #[must_use]
+    pub fn foreground(&self) -> Arc<Foreground> {
+        self.foreground.clone()
+    }
+
+    /// Prints and removes the finished jobs
+    pub fn report_jobs(&mut self) {
+        for job in self.jobs.reap() {
+            self.console.eprintln(format_args!(
+                "[{}] {}  {}",
+                job.id(),
+                job.state(),
+                job.line()
+            ));
+        }
+    }
+
+    /// Value of the shell variable `name`, the exit status for `?` or an argument
+    fn lookup(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.status.to_string()),
+            "#" => Some(self.arguments.len().to_string()),
+            "@" => Some(self.arguments.join(" ")),
+            _ => match name.parse::<usize>() {
+                Ok(index) => index
+                    .checked_sub(1)
+                    .and_then(|index| self.arguments.get(index))
+                    .cloned(),
+                Err(_) => self.variables.get(name).map(String::from),
+            },
+        }
+    }
+
+    /// `PS1` with expanded variables
+    #[must_use]
+    pub fn prompt(&self) -> String {
+        let ps1 = self.variables.get("PS1").unwrap_or(DEFAULT_PROMPT);
+        expand(ps1, &|name| self.lookup(name)).unwrap_or_else(|_| ps1.into())
+    }
+
+    /// Exit status of the previous command
+    #[must_use]
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    #[must_use]
+    pub fn function(&self, name: &str) -> Option<&Arc<Vec<Statement>>> {
+        self.functions.get(name)
+    }
+
+    /// Runs a command line, which may be a whole script. Errors are printed.
+    pub async fn execute(&mut self, line: &str) {
+        self.execute_to(line, Output::Terminal(self.console)).await;
+    }
+
+    /// Runs a command line, whose output goes to `stdout` instead of the terminal. Background
+    /// jobs still write to the terminal.
+    pub async fn execute_to(&mut self, line: &str, stdout: Output) {
+        let statements = match parse_script(line) {
+            Ok(statements) => statements,
+            Err(error) => {
+                self.console.eprintln(format_args!("{}", error));
+                self.status = 2;
+                return;
+            }
+        };
+        let control = Arc::new(Control::new(false));
+        let foreground = self.foreground.clone();
+        let _foreground = foreground.enter(control.clone());
+        // A cancelled script or function doesn't restore them itself
+        let arguments = self.arguments.clone();
+        let depth = self.depth;
+        let running = self.run_statements(&statements, &stdout);
+        if Controlled::new(running, control).await.is_none() {
+            self.console.eprintln(format_args!("^C"));
+            self.status = CANCELLED_STATUS;
+            self.arguments = arguments;
+            self.depth = depth;
+        }
+    }
+
+    /// Runs the script file with the arguments `$1`, ...
+    ///
+    /// # Errors
+    /// If the file doesn't exist or isn't a valid script.
+    pub async fn source(
+        &mut self,
+        name: &str,
+        args: &[String],
+        stdout: &Output,
+    ) -> Result<(), String> {
+        let script = files::read(name).ok_or_else(|| format!("{}: No such file", name))?;
+        let statements = parse_script(&script).map_err(|error| format!("{}: {}", name, error))?;
+        self.call(&statements, args, stdout).await;
+        Ok(())
+    }
+
+    /// Runs a script or function with the arguments `$1`, ...
+    async fn call(&mut self, statements: &[Statement], args: &[String], stdout: &Output) {
+        if self.depth == MAX_CALL_DEPTH {
+            self.console.eprintln(format_args!("Too many nested calls"));
+            self.status = 1;
+            return;
+        }
+        self.depth += 1;
+        let arguments = core::mem::replace(&mut self.arguments, args.to_vec());
+        self.run_statements(statements, stdout).await;
+        self.arguments = arguments;
+        self.depth -= 1;
+    }
+
+    fn run_statements<'a>(
+        &'a mut self,
+        statements: &'a [Statement],
+        stdout: &'a Output,
+    ) -> Statements<'a> {
+        Box::pin(async move {
+            for statement in statements {
+                self.run_statement(statement, stdout).await;
+            }
+        })
+    }
+
+    /// The exit status is the one of the last command, which ran in a body, or 0
+    async fn run_statement(&mut self, statement: &Statement, stdout: &Output) {
+        match statement {
+            Statement::Command(line) => self.run_command(line, stdout.clone()).await,
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.run_command(condition, stdout.clone()).await;
+                let branch = if self.status == 0 { then } else { otherwise };
+                self.status = 0;
+                self.run_statements(branch, stdout).await;
+            }
+            Statement::For { name, words, body } => {
+                let words = match tokenize_with(words, &|variable| self.lookup(variable)) {
+                    Ok(words) => words,
+                    Err(error) => {
+                        self.console.eprintln(format_args!("{}", error));
+                        self.status = 2;
+                        return;
+                    }
+                };
+                self.status = 0;
+                for word in words {
+                    self.variables
+                        .set(name, &word)
+                        .expect("Checked by the parser");
+                    self.run_statements(body, stdout).await;
+                    // Lets other tasks and the hotkeys run
+                    yield_now().await;
+                }
+            }
+            Statement::While { condition, body } => {
+                let mut status = 0;
+                loop {
+                    self.run_command(condition, stdout.clone()).await;
+                    if self.status != 0 {
+                        break;
+                    }
+                    self.run_statements(body, stdout).await;
+                    status = self.status;
+                    yield_now().await;
+                }
+                self.status = status;
+            }
+            Statement::Function { name, body } => {
+                self.functions.insert(name.clone(), body.clone());
+                self.status = 0;
+            }
+        }
+    }
+
+    /// Runs a single command line of a script
+    async fn run_command(&mut self, line: &str, stdout: Output) {
+        let pipeline = match lex(line, &|name| self.lookup(name)) {
+            Ok(tokens) => parse(tokens).map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        let pipeline = match pipeline {
+            Ok(pipeline) => pipeline,
+            Err(error) => {
+                self.console.eprintln(format_args!("{}", error));
+                self.status = 2;
+                return;
+            }
+        };
+        if pipeline.stages.is_empty() {
+            return;
+        }
+        if pipeline.background {
+            let line = line.trim_end().trim_end_matches('&').trim_end();
+            self.spawn_job(line, pipeline);
+            return;
+        }
+        self.run_pipeline(&pipeline, stdout).await;
+    }
+
+    /// Runs the pipeline in a subshell, which is spawned as a task.
+    fn spawn_job(&mut self, line: &str, pipeline: Pipeline) {
+        let control = Arc::new(Control::new(true));
+        let mut subshell = self.subshell();
+        let console = self.console;
+        let job = control.clone();
+        let task = Task::new(async move {
+            let pipeline = Box::pin(async move {
+                subshell
+                    .run_pipeline(&pipeline, Output::Terminal(console))
+                    .await;
+                subshell.status
+            });
+            let status = Controlled::new(pipeline, job.clone()).await;
+            job.set_status(status.unwrap_or(CANCELLED_STATUS));
+        })
+        .with_name(String::from(line));
+        self.status = match executor::spawn(task) {
+            Ok(handle) => {
+                let id = self.jobs.add(line.into(), control, handle);
+                self.console.eprintln(format_args!("[{}] {}", id, line));
+                0
+            }
+            Err(error) => {
+                self.console
+                    .eprintln(format_args!("Can't start a job: {:?}", error));
+                1
+            }
+        };
+    }
+
+    /// Runs all commands concurrently, connected by pipes. All but the last one run in
+    /// subshells, the exit status is the one of the last command. A command is stopped once
+    /// the next one no longer reads its output, e.g. after `head`.
+    async fn run_pipeline(&mut self, pipeline: &Pipeline, stdout: Output) {
+        let (last, others) = match pipeline.stages.split_last() {
+            Some(split) => split,
+            None => return,
+        };
+        let mut inputs = Vec::with_capacity(pipeline.stages.len());
+        let mut outputs = Vec::with_capacity(pipeline.stages.len());
+        inputs.push(Input::Empty);
+        for _ in others {
+            let (writer, reader) = pipe();
+            outputs.push(Output::Pipe(writer));
+            inputs.push(Input::Pipe(reader));
+        }
+        outputs.push(stdout);
+        let mut ios: Vec<Io> = inputs
+            .into_iter()
+            .zip(outputs)
+            .zip(&pipeline.stages)
+            .map(|((stdin, stdout), stage)| Io {
+                stdin,
+                // Drops the pipe, so the next command gets no input
+                stdout: match &stage.redirect {
+                    Some(redirect) => Output::file(&redirect.file, redirect.append),
+                    None => stdout,
+                },
+            })
+            .collect();
+        let io = ios.pop().expect("One per command");
+        let mut subshells: Vec<Shell> = others.iter().map(|_| self.subshell()).collect();
+        let others = subshells
+            .iter_mut()
+            .zip(others)
+            .zip(ios)
+            .map(|((shell, stage), io)| shell.run_writer(&stage.words, io));
+        future::join(future::join_all(others), self.run_words(&last.words, io)).await;
+    }
+
+    /// Like `run_words`, but stops once nobody reads the output anymore
+    async fn run_writer(&mut self, words: &[String], io: Io) {
+        let closed = io.stdout.reader_closed();
+        let run = self.run_words(words, io);
+        pin_mut!(run, closed);
+        future::select(run, closed).await;
+    }
+
+    /// Runs the function or command named by the first word. The streams are closed
+    /// afterwards.
+    async fn run_words(&mut self, words: &[String], mut io: Io) {
+        let (name, args) = match words.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let environment = self.variables.environment();
+        // Outside of a task, e.g. in tests, commands just don't see the environment
+        let _ = ENVIRONMENT.try_with(|current| *current.borrow_mut() = environment);
+        // Functions don't read any input
+        if let Some(body) = self.functions.get(name).cloned() {
+            self.call(&body, args, &io.stdout).await;
+            return;
+        }
+        self.status = match find(name) {
+            Some(command) => match command.run(self, &mut io, args).await {
+                Ok(()) => 0,
+                Err(error) => {
+                    if !error.is_empty() {
+                        self.console.eprintln(format_args!("{}: {}", name, error));
+                    }
+                    1
+                }
+            },
+            None => {
+                self.console.eprintln(format_args!(
+                    "{}: Command not found. Type `help` for more information.",
+                    name
+                ));
+                127
+            }
+        };
+    }
+}
+
+/// Completes command names and hands arguments to `Command::complete`. Redirections are
+/// completed with file names.
+struct RegistryCompleter;
+
+impl Completer for RegistryCompleter {
+    fn complete(&self, words: &[&str], word: &str) -> Vec<String> {
+        // Only the last command of a pipeline matters
+        let words = match words.iter().rposition(|word| matches!(*word, "|" | "&")) {
+            Some(operator) => &words[operator + 1..],
+            None => words,
+        };
+        if matches!(words.last(), Some(&(">" | ">>"))) {
+            return files::names();
+        }
+        match words.split_first() {
+            None => command::names().into_iter().map(String::from).collect(),
+            Some((name, args)) => find(name)
+                .map(|command| command.complete(args, word))
+                .unwrap_or_default(),
+        }
+    }
 }
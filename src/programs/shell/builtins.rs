@@ -0,0 +1,839 @@
+//! Commands, which are always available.
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use futures_util::future;
+
+use super::command::{self, Command, CommandFuture, CommandResult};
+use super::{files, Io, JobState, Shell};
+use crate::power::{self, Action};
+use crate::programs::terminal::Console;
+use crate::task::{executor, Task};
+
+pub(super) fn all() -> Vec<Arc<dyn Command>> {
+    vec![
+        Arc::new(Help),
+        Arc::new(Cpus),
+        Arc::new(Keymap),
+        Arc::new(History),
+        Arc::new(Set),
+        Arc::new(Unset),
+        Arc::new(Export),
+        Arc::new(Env),
+        Arc::new(Jobs),
+        Arc::new(Fg),
+        Arc::new(Bg),
+        Arc::new(Kill),
+        Arc::new(Source),
+        Arc::new(True),
+        Arc::new(False),
+        Arc::new(Test),
+        Arc::new(Shutdown),
+        Arc::new(Reboot),
+        Arc::new(Halt),
+    ]
+}
+
+/// Splits `NAME=VALUE`
+fn assignment(arg: &str) -> (&str, Option<&str>) {
+    match arg.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (arg, None),
+    }
+}
+
+struct Help;
+
+impl Command for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "help [command]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lists the commands, or shows how to use one."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            match args {
+                [] => {
+                    let commands = command::commands();
+                    let synopsis = |command: &Arc<dyn Command>| {
+                        let mut synopsis = String::from(command.usage());
+                        for alias in command.aliases() {
+                            synopsis = synopsis + " | " + alias;
+                        }
+                        synopsis
+                    };
+                    let width = commands.iter().map(|c| synopsis(c).len()).max();
+                    io.stdout
+                        .write_line("Alternatives are denoted using |")
+                        .await;
+                    for command in &commands {
+                        let line = format!(
+                            "{:>width$} : {}",
+                            synopsis(command),
+                            command.description(),
+                            width = width.unwrap_or(0)
+                        );
+                        io.stdout.write_line(&line).await;
+                    }
+                    Ok(())
+                }
+                [name] => match command::find(name) {
+                    Some(command) => {
+                        io.stdout
+                            .write_line(&format!("Usage: {}", command.usage()))
+                            .await;
+                        if !command.aliases().is_empty() {
+                            let aliases = command.aliases().join(", ");
+                            io.stdout.write_line(&format!("Aliases: {}", aliases)).await;
+                        }
+                        io.stdout.write_line(command.description()).await;
+                        Ok(())
+                    }
+                    None => Err(format!("Unknown command {}", name)),
+                },
+                _ => Err(format!("Usage: {}", self.usage())),
+            }
+        })
+    }
+
+    fn complete(&self, args: &[&str], _word: &str) -> Vec<String> {
+        if args.is_empty() {
+            command::names().into_iter().map(String::from).collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+struct Cpus;
+
+impl Command for Cpus {
+    fn name(&self) -> &'static str {
+        "cpus"
+    }
+
+    fn usage(&self) -> &'static str {
+        "cpus"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lists the CPUs and whether they are online."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let cpus = crate::smp::cpus();
+            let summary = format!(
+                "{} of {} CPUs online",
+                crate::smp::online_count(),
+                cpus.len()
+            );
+            io.stdout.write_line(&summary).await;
+            for cpu in cpus {
+                let line = format!(
+                    "CPU {}: APIC id {}, {}",
+                    cpu.id,
+                    cpu.apic_id,
+                    if cpu.online { "online" } else { "offline" }
+                );
+                io.stdout.write_line(&line).await;
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Keymap;
+
+impl Command for Keymap {
+    fn name(&self) -> &'static str {
+        "keymap"
+    }
+
+    fn usage(&self) -> &'static str {
+        "keymap [name]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Shows or sets the keyboard layout."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        use crate::task::keyboard::{self, Layout};
+        Box::pin(async move {
+            match args {
+                [] => {
+                    let current = keyboard::layout();
+                    for layout in Layout::ALL {
+                        let marker = if layout == current { '*' } else { ' ' };
+                        io.stdout
+                            .write_line(&format!("{} {}", marker, layout))
+                            .await;
+                    }
+                    Ok(())
+                }
+                [name] => match name.parse::<Layout>() {
+                    Ok(layout) => {
+                        keyboard::set_layout(layout);
+                        Ok(())
+                    }
+                    Err(_) => Err(String::from("Unknown keymap. Type `keymap` to list them.")),
+                },
+                _ => Err(format!("Usage: {}", self.usage())),
+            }
+        })
+    }
+
+    fn complete(&self, args: &[&str], _word: &str) -> Vec<String> {
+        use crate::task::keyboard::Layout;
+        if args.is_empty() {
+            Layout::ALL
+                .iter()
+                .map(|layout| layout.name().into())
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+struct History;
+
+impl Command for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn usage(&self) -> &'static str {
+        "history"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lists the previous commands, Up and Down recall them."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            for (i, line) in shell.editor().history().iter().enumerate() {
+                io.stdout
+                    .write_line(&format!("{:>4}  {}", i + 1, line))
+                    .await;
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Set;
+
+impl Command for Set {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn usage(&self) -> &'static str {
+        "set [NAME=VALUE]..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Sets shell variables, or lists all of them."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            if args.is_empty() {
+                for (name, value) in shell.variables().iter() {
+                    io.stdout.write_line(&format!("{}={}", name, value)).await;
+                }
+            }
+            for arg in args {
+                match assignment(arg) {
+                    (name, Some(value)) => {
+                        if shell.variables_mut().set(name, value).is_err() {
+                            return Err(format!("Invalid variable name {}", name));
+                        }
+                    }
+                    (_, None) => return Err(format!("Usage: {}", self.usage())),
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Unset;
+
+impl Command for Unset {
+    fn name(&self) -> &'static str {
+        "unset"
+    }
+
+    fn usage(&self) -> &'static str {
+        "unset NAME..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Removes shell variables."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        _io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            for name in args {
+                shell.variables_mut().unset(name);
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Export;
+
+impl Command for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn usage(&self) -> &'static str {
+        "export NAME[=VALUE]..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Adds shell variables to the environment of commands."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        _io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            if args.is_empty() {
+                return Err(format!("Usage: {}", self.usage()));
+            }
+            for arg in args {
+                let (name, value) = assignment(arg);
+                let variables = shell.variables_mut();
+                let valid = variables.export(name).is_ok()
+                    && value.map_or(true, |value| variables.set(name, value).is_ok());
+                if !valid {
+                    return Err(format!("Invalid variable name {}", name));
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Env;
+
+impl Command for Env {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn usage(&self) -> &'static str {
+        "env"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lists the environment, which commands receive."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            // The one of the running task, which is what commands see
+            let environment = super::ENVIRONMENT
+                .try_with(|environment| environment.borrow().clone())
+                .unwrap_or_default();
+            for (name, value) in environment.iter() {
+                io.stdout.write_line(&format!("{}={}", name, value)).await;
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Jobs;
+
+impl Command for Jobs {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn usage(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lists the background jobs."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            for job in shell.jobs().iter() {
+                let line = format!("[{}] {:<12} {}", job.id(), job.state(), job.line());
+                io.stdout.write_line(&line).await;
+            }
+            // Reported now, so not again at the next prompt
+            shell.jobs_mut().reap();
+            Ok(())
+        })
+    }
+}
+
+struct Fg;
+
+impl Command for Fg {
+    fn name(&self) -> &'static str {
+        "fg"
+    }
+
+    fn usage(&self) -> &'static str {
+        "fg [%JOB]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Resumes a background job and waits for it. Ctrl+Z stops the job again."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        _io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(fg(shell, args, self.usage()))
+    }
+}
+
+async fn fg(shell: &mut Shell, args: &[String], usage: &str) -> CommandResult {
+    let id = job_argument(shell, args, usage)?;
+    let foreground = shell.foreground();
+    let console = shell.console();
+    let job = shell.jobs_mut().get_mut(id).ok_or("No such job")?;
+    console.eprintln(format_args!("{}", job.line()));
+    job.control().resume();
+    let state = {
+        let _foreground = foreground.enter(job.control().clone());
+        job.wait().await
+    };
+    match state {
+        JobState::Stopped => {
+            console.eprintln(format_args!("\n[{}] Stopped  {}", id, job.line()));
+            Ok(())
+        }
+        JobState::Running => unreachable!("Waited for the job"),
+        JobState::Done(0) => {
+            shell.jobs_mut().remove(id);
+            Ok(())
+        }
+        state => {
+            shell.jobs_mut().remove(id);
+            Err(state.to_string())
+        }
+    }
+}
+
+struct Bg;
+
+impl Command for Bg {
+    fn name(&self) -> &'static str {
+        "bg"
+    }
+
+    fn usage(&self) -> &'static str {
+        "bg [%JOB]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Resumes a stopped job in the background."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        _io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move { bg(shell, args, self.usage()) })
+    }
+}
+
+fn bg(shell: &Shell, args: &[String], usage: &str) -> CommandResult {
+    let id = job_argument(shell, args, usage)?;
+    let job = shell.jobs().get(id).ok_or("No such job")?;
+    if job.control().resume() {
+        shell
+            .console()
+            .eprintln(format_args!("[{}] {}", id, job.line()));
+        Ok(())
+    } else {
+        Err(format!("Job {} isn't stopped", id))
+    }
+}
+
+struct Kill;
+
+impl Command for Kill {
+    fn name(&self) -> &'static str {
+        "kill"
+    }
+
+    fn usage(&self) -> &'static str {
+        "kill [-STOP|-CONT] %JOB..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Cancels, stops or resumes jobs."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        _io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move { kill(shell, args, self.usage()) })
+    }
+
+    fn complete(&self, _args: &[&str], word: &str) -> Vec<String> {
+        if word.starts_with('-') {
+            ["-INT", "-STOP", "-CONT"]
+                .iter()
+                .map(|signal| (*signal).into())
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+fn kill(shell: &Shell, args: &[String], usage: &str) -> CommandResult {
+    let (signal, specs) = match args {
+        [signal, specs @ ..] if signal.starts_with('-') => (signal.as_str(), specs),
+        specs => ("-INT", specs),
+    };
+    if specs.is_empty() {
+        return Err(format!("Usage: {}", usage));
+    }
+    for spec in specs {
+        let id = shell.jobs().find(Some(spec))?;
+        let control = shell.jobs().get(id).ok_or("No such job")?.control();
+        match signal {
+            "-INT" | "-KILL" | "-TERM" => control.cancel(),
+            "-STOP" => control.stop(),
+            "-CONT" => control.resume(),
+            _ => return Err(format!("Unknown signal {}", signal)),
+        };
+    }
+    Ok(())
+}
+
+/// Job number of the only argument, or of the current job without one
+fn job_argument(shell: &Shell, args: &[String], usage: &str) -> Result<usize, String> {
+    match args {
+        [] => shell.jobs().find(None),
+        [spec] => shell.jobs().find(Some(spec)),
+        _ => Err(format!("Usage: {}", usage)),
+    }
+}
+
+struct Source;
+
+impl Command for Source {
+    fn name(&self) -> &'static str {
+        "source"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["."]
+    }
+
+    fn usage(&self) -> &'static str {
+        "source FILE [ARG]..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Runs the script in the file, with the arguments as $1, ..."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(source(shell, io, args, self.usage()))
+    }
+
+    fn complete(&self, _args: &[&str], _word: &str) -> Vec<String> {
+        files::names()
+    }
+}
+
+/// Fails silently, if the last command of the script failed
+async fn source(shell: &mut Shell, io: &mut Io, args: &[String], usage: &str) -> CommandResult {
+    let (name, args) = args
+        .split_first()
+        .ok_or_else(|| format!("Usage: {}", usage))?;
+    shell.source(name, args, &io.stdout).await?;
+    if shell.status() == 0 {
+        Ok(())
+    } else {
+        Err(String::new())
+    }
+}
+
+struct True;
+
+impl Command for True {
+    fn name(&self) -> &'static str {
+        "true"
+    }
+
+    fn usage(&self) -> &'static str {
+        "true"
+    }
+
+    fn description(&self) -> &'static str {
+        "Succeeds."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        _io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move { Ok(()) })
+    }
+}
+
+struct False;
+
+impl Command for False {
+    fn name(&self) -> &'static str {
+        "false"
+    }
+
+    fn usage(&self) -> &'static str {
+        "false"
+    }
+
+    fn description(&self) -> &'static str {
+        "Fails silently."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        _io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move { Err(String::new()) })
+    }
+}
+
+struct Test;
+
+impl Command for Test {
+    fn name(&self) -> &'static str {
+        "test"
+    }
+
+    fn usage(&self) -> &'static str {
+        "test EXPRESSION"
+    }
+
+    fn description(&self) -> &'static str {
+        "Fails silently, unless TEXT, -n/-z TEXT, -e FILE, A =/!= B or X -eq/-lt/... Y holds. ! negates."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        _io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            match evaluate(&args) {
+                Some(true) => Ok(()),
+                Some(false) => Err(String::new()),
+                None => Err(format!("Usage: {}", self.usage())),
+            }
+        })
+    }
+}
+
+/// `None`, if it isn't an expression of `test`
+fn evaluate(args: &[&str]) -> Option<bool> {
+    match *args {
+        [] => Some(false),
+        ["!", ref rest @ ..] => evaluate(rest).map(|result| !result),
+        [text] => Some(!text.is_empty()),
+        ["-n", text] => Some(!text.is_empty()),
+        ["-z", text] => Some(text.is_empty()),
+        ["-e", name] => Some(files::exists(name)),
+        [a, "=", b] => Some(a == b),
+        [a, "!=", b] => Some(a != b),
+        [a, operator, b] => {
+            let (a, b) = (a.parse::<i64>().ok()?, b.parse::<i64>().ok()?);
+            match operator {
+                "-eq" => Some(a == b),
+                "-ne" => Some(a != b),
+                "-lt" => Some(a < b),
+                "-le" => Some(a <= b),
+                "-gt" => Some(a > b),
+                "-ge" => Some(a >= b),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+struct Shutdown;
+
+impl Command for Shutdown {
+    fn name(&self) -> &'static str {
+        "shutdown"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["exit"]
+    }
+
+    fn usage(&self) -> &'static str {
+        "shutdown"
+    }
+
+    fn description(&self) -> &'static str {
+        "Powers the pc off."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        _io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(power(shell.console(), Action::Shutdown))
+    }
+}
+
+struct Reboot;
+
+impl Command for Reboot {
+    fn name(&self) -> &'static str {
+        "reboot"
+    }
+
+    fn usage(&self) -> &'static str {
+        "reboot"
+    }
+
+    fn description(&self) -> &'static str {
+        "Restarts the pc."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        _io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(power(shell.console(), Action::Reboot))
+    }
+}
+
+struct Halt;
+
+impl Command for Halt {
+    fn name(&self) -> &'static str {
+        "halt"
+    }
+
+    fn usage(&self) -> &'static str {
+        "halt"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stops all tasks and CPUs, without powering off."
+    }
+
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        _io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(power(shell.console(), Action::Halt))
+    }
+}
+
+/// Notifies the tasks first. Never finishes. The action runs in a task of its own, so
+/// cancelling the command with Ctrl+C doesn't stop it halfway.
+async fn power(console: Console, action: Action) -> CommandResult {
+    let task = Task::new(async move {
+        power::prepare(action).await;
+        action.perform();
+    })
+    .with_name(action.to_string());
+    executor::spawn(task).map_err(|error| format!("Can't start: {:?}", error))?;
+    console.eprintln(format_args!("Going down for {} ...", action));
+    future::pending().await
+}
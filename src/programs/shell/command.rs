@@ -0,0 +1,142 @@
+//! The `Command` trait and the registry of all commands.
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::{fmt, future::Future, pin::Pin};
+use lazy_static::lazy_static;
+use spin::RwLock;
+
+use super::{builtins, diagnostics, filters, Io, Shell};
+
+/// Future of `Command::run`. `Sync`, as it is part of the shell task.
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = CommandResult> + Send + Sync + 'a>>;
+
+/// Errors are printed by the shell, prefixed with the command name. An empty error only sets
+/// the exit status.
+pub type CommandResult = Result<(), String>;
+
+pub trait Command: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Other names, which run the same command
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Synopsis, e.g. `keymap [name]`
+    fn usage(&self) -> &'static str;
+
+    /// A single line for `help`
+    fn description(&self) -> &'static str;
+
+    /// `args` excludes the command name. Output goes to `io.stdout`, not to the screen.
+    fn run<'a>(
+        &'a self,
+        shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a>;
+
+    /// Candidates for tab completion of the argument `word`, after the arguments `args`.
+    fn complete(&self, args: &[&str], word: &str) -> Vec<String> {
+        let _ = (args, word);
+        Vec::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegisterError {
+    /// The name or an alias is already used
+    NameTaken(String),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::NameTaken(name) => write!(f, "Command {} already exists", name),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    /// By name and alias
+    commands: BTreeMap<&'static str, Arc<dyn Command>>,
+}
+
+impl Registry {
+    fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        for command in builtins::all()
+            .into_iter()
+            .chain(filters::all())
+            .chain(diagnostics::all())
+        {
+            registry
+                .insert(command)
+                .expect("Builtin commands have distinct names");
+        }
+        registry
+    }
+
+    fn insert(&mut self, command: Arc<dyn Command>) -> Result<(), RegisterError> {
+        let names = || core::iter::once(command.name()).chain(command.aliases().iter().copied());
+        if let Some(taken) = names().find(|name| self.commands.contains_key(name)) {
+            return Err(RegisterError::NameTaken(taken.into()));
+        }
+        for name in names() {
+            self.commands.insert(name, command.clone());
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: RwLock<Registry> = RwLock::new(Registry::with_builtins());
+}
+
+/// Adds a command to all shells.
+///
+/// # Errors
+/// If the name or one of the aliases is taken.
+pub fn register(command: impl Command + 'static) -> Result<(), RegisterError> {
+    REGISTRY.write().insert(Arc::new(command))
+}
+
+/// Removes the command with the name or alias `name`, including all its other names.
+/// Returns `false` if there is none.
+pub fn unregister(name: &str) -> bool {
+    let mut registry = REGISTRY.write();
+    let command = match registry.commands.get(name) {
+        Some(command) => command.clone(),
+        None => return false,
+    };
+    registry
+        .commands
+        .retain(|_, other| !Arc::ptr_eq(other, &command));
+    true
+}
+
+/// Looks the command up by name or alias.
+#[must_use]
+pub fn find(name: &str) -> Option<Arc<dyn Command>> {
+    REGISTRY.read().commands.get(name).cloned()
+}
+
+/// All commands once, sorted by name
+#[must_use]
+pub fn commands() -> Vec<Arc<dyn Command>> {
+    let registry = REGISTRY.read();
+    let mut commands: Vec<Arc<dyn Command>> = registry
+        .commands
+        .iter()
+        .filter(|(name, command)| **name == command.name())
+        .map(|(_, command)| command.clone())
+        .collect();
+    commands.sort_by_key(|command| command.name());
+    commands
+}
+
+/// Names and aliases of all commands
+#[must_use]
+pub fn names() -> Vec<&'static str> {
+    REGISTRY.read().commands.keys().copied().collect()
+}
@@ -0,0 +1,326 @@
+//! Commands, which show the state of the machine and the kernel.
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::arch::x86_64::{__cpuid, CpuidResult};
+
+use super::command::{Command, CommandFuture};
+use super::{Io, Shell};
+use crate::{klog, pci, smp, task::timer};
+
+pub(super) fn all() -> Vec<Arc<dyn Command>> {
+    vec![
+        Arc::new(Uptime),
+        Arc::new(CpuInfo),
+        Arc::new(Lspci),
+        Arc::new(Dmesg),
+        Arc::new(Clear),
+        Arc::new(Echo),
+    ]
+}
+
+struct Uptime;
+
+impl Command for Uptime {
+    fn name(&self) -> &'static str {
+        "uptime"
+    }
+
+    fn usage(&self) -> &'static str {
+        "uptime"
+    }
+
+    fn description(&self) -> &'static str {
+        "Shows how long the kernel has been running."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let seconds = timer::uptime().as_secs();
+            let uptime = format!(
+                "up {}:{:02}:{:02} ({} ticks)",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60,
+                timer::ticks()
+            );
+            io.stdout.write_line(&uptime).await;
+            Ok(())
+        })
+    }
+}
+
+/// Names of the feature bits in EDX of leaf 1, empty if reserved
+const EDX_FEATURES: [&str; 32] = [
+    "fpu", "vme", "de", "pse", "tsc", "msr", "pae", "mce", "cx8", "apic", "", "sep", "mtrr", "pge",
+    "mca", "cmov", "pat", "pse36", "psn", "clflush", "", "ds", "acpi", "mmx", "fxsr", "sse",
+    "sse2", "ss", "htt", "tm", "ia64", "pbe",
+];
+/// Names of the feature bits in ECX of leaf 1, empty if reserved
+const ECX_FEATURES: [&str; 32] = [
+    "sse3",
+    "pclmulqdq",
+    "dtes64",
+    "monitor",
+    "ds_cpl",
+    "vmx",
+    "smx",
+    "est",
+    "tm2",
+    "ssse3",
+    "cnxt_id",
+    "sdbg",
+    "fma",
+    "cx16",
+    "xtpr",
+    "pdcm",
+    "",
+    "pcid",
+    "dca",
+    "sse4_1",
+    "sse4_2",
+    "x2apic",
+    "movbe",
+    "popcnt",
+    "tsc_deadline",
+    "aes",
+    "xsave",
+    "osxsave",
+    "avx",
+    "f16c",
+    "rdrand",
+    "hypervisor",
+];
+const EXTENDED_LEAVES: u32 = 0x8000_0000;
+const BRAND_LEAVES: [u32; 3] = [0x8000_0002, 0x8000_0003, 0x8000_0004];
+
+fn cpuid(leaf: u32) -> CpuidResult {
+    #[allow(unused_unsafe)]
+    unsafe {
+        __cpuid(leaf)
+    }
+}
+
+fn bytes(registers: &[u32]) -> String {
+    let bytes: Vec<u8> = registers.iter().flat_map(|r| r.to_le_bytes()).collect();
+    String::from_utf8_lossy(&bytes)
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .into()
+}
+
+/// Names of the set bits
+fn features(bits: u32, names: &[&'static str; 32]) -> impl Iterator<Item = &'static str> + '_ {
+    names
+        .iter()
+        .enumerate()
+        .filter(move |(bit, name)| !name.is_empty() && bits & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+}
+
+struct CpuInfo;
+
+impl Command for CpuInfo {
+    fn name(&self) -> &'static str {
+        "cpuinfo"
+    }
+
+    fn usage(&self) -> &'static str {
+        "cpuinfo"
+    }
+
+    fn description(&self) -> &'static str {
+        "Shows the vendor, model and features of the CPU."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let vendor = cpuid(0);
+            let vendor = bytes(&[vendor.ebx, vendor.edx, vendor.ecx]);
+            io.stdout.write_line(&format!("vendor: {}", vendor)).await;
+            if cpuid(EXTENDED_LEAVES).eax >= BRAND_LEAVES[2] {
+                let brand: Vec<u32> = BRAND_LEAVES
+                    .iter()
+                    .map(|leaf| cpuid(*leaf))
+                    .flat_map(|r| [r.eax, r.ebx, r.ecx, r.edx])
+                    .collect();
+                io.stdout
+                    .write_line(&format!("brand: {}", bytes(&brand)))
+                    .await;
+            }
+            let info = cpuid(1);
+            let mut family = (info.eax >> 8) & 0xf;
+            let mut model = (info.eax >> 4) & 0xf;
+            if family == 0xf {
+                family += (info.eax >> 20) & 0xff;
+            }
+            if family == 0x6 || family >= 0xf {
+                model |= ((info.eax >> 16) & 0xf) << 4;
+            }
+            let version = format!(
+                "family: {}, model: {}, stepping: {}",
+                family,
+                model,
+                info.eax & 0xf
+            );
+            io.stdout.write_line(&version).await;
+            let features: Vec<&str> = features(info.edx, &EDX_FEATURES)
+                .chain(features(info.ecx, &ECX_FEATURES))
+                .collect();
+            let features = format!("features: {}", features.join(" "));
+            io.stdout.write_line(&features).await;
+            let cpus = format!(
+                "cpus: {} of {} online",
+                smp::online_count(),
+                smp::cpus().len()
+            );
+            io.stdout.write_line(&cpus).await;
+            Ok(())
+        })
+    }
+}
+
+struct Lspci;
+
+impl Command for Lspci {
+    fn name(&self) -> &'static str {
+        "lspci"
+    }
+
+    fn usage(&self) -> &'static str {
+        "lspci"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lists the PCI devices as BUS:DEVICE.FUNCTION CLASS: VENDOR:DEVICE."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            for device in pci::devices() {
+                io.stdout.write_line(&device.to_string()).await;
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Dmesg;
+
+impl Command for Dmesg {
+    fn name(&self) -> &'static str {
+        "dmesg"
+    }
+
+    fn usage(&self) -> &'static str {
+        "dmesg [-c]"
+    }
+
+    fn description(&self) -> &'static str {
+        "Prints the kernel log, -c clears it afterwards."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let clear = match args {
+                [] => false,
+                [flag] if flag == "-c" => true,
+                _ => return Err(format!("Usage: {}", self.usage())),
+            };
+            io.stdout.write_str(&klog::contents()).await;
+            if clear {
+                klog::clear();
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Clear;
+
+impl Command for Clear {
+    fn name(&self) -> &'static str {
+        "clear"
+    }
+
+    fn usage(&self) -> &'static str {
+        "clear"
+    }
+
+    fn description(&self) -> &'static str {
+        "Clears the terminal, unless the output is redirected."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            if let Some(console) = io.stdout.console() {
+                console.clear();
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Echo;
+
+impl Command for Echo {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+
+    fn usage(&self) -> &'static str {
+        "echo [-n] [WORD]..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Prints the words, -n without the trailing newline."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let (newline, words) = match args {
+                [flag, words @ ..] if flag == "-n" => (false, words),
+                words => (true, words),
+            };
+            io.stdout.write_str(&words.join(" ")).await;
+            if newline {
+                io.stdout.write_str("\n").await;
+            }
+            Ok(())
+        })
+    }
+}
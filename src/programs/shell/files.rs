@@ -0,0 +1,40 @@
+//! Text files, which only live in memory, as there is no filesystem yet.
+//!
+//! Names are flat, there are no directories. Output is redirected into them with `>` and
+//! `>>`.
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use spin::RwLock;
+
+static FILES: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+/// Contents of the file
+#[must_use]
+pub fn read(name: &str) -> Option<String> {
+    FILES.read().get(name).cloned()
+}
+
+/// Creates or replaces the file.
+pub fn write(name: &str, contents: &str) {
+    FILES.write().insert(name.into(), contents.into());
+}
+
+/// Appends to the file, which is created if it doesn't exist.
+pub fn append(name: &str, text: &str) {
+    FILES.write().entry(name.into()).or_default().push_str(text);
+}
+
+/// Returns `false`, if the file doesn't exist.
+pub fn remove(name: &str) -> bool {
+    FILES.write().remove(name).is_some()
+}
+
+#[must_use]
+pub fn exists(name: &str) -> bool {
+    FILES.read().contains_key(name)
+}
+
+/// Sorted names of all files
+#[must_use]
+pub fn names() -> Vec<String> {
+    FILES.read().keys().cloned().collect()
+}
@@ -0,0 +1,404 @@
+//! Commands working with files and the output of other commands.
+//!
+//! Filters read the files named by their arguments, or their input without any, and write
+//! the result to their output, e.g. `help | grep job | wc -l`.
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use futures_util::StreamExt;
+use pc_keyboard::DecodedKey;
+
+use super::command::{Command, CommandFuture, CommandResult};
+use super::{files, Input, Io, Shell};
+use crate::programs::terminal::Console;
+use crate::task::keyboard::ScancodeStream;
+use crate::vga::{VgaBuffer, STDOUT};
+
+pub(super) fn all() -> Vec<Arc<dyn Command>> {
+    vec![
+        Arc::new(Cat),
+        Arc::new(Ls),
+        Arc::new(Rm),
+        Arc::new(Grep),
+        Arc::new(Head),
+        Arc::new(Wc),
+        Arc::new(More),
+    ]
+}
+
+/// Replaces the input with the contents of the files, unless there are none.
+fn open_files(io: &mut Io, names: &[String]) -> CommandResult {
+    if names.is_empty() {
+        return Ok(());
+    }
+    let mut text = String::new();
+    for name in names {
+        let contents = files::read(name).ok_or_else(|| format!("{}: No such file", name))?;
+        text.push_str(&contents);
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+    io.stdin = Input::Text(text);
+    Ok(())
+}
+
+/// Splits leading flags like `-iv` into their letters, which must be in `allowed`.
+fn flags<'a>(args: &'a [String], allowed: &str) -> Result<(Vec<char>, &'a [String]), String> {
+    let count = args
+        .iter()
+        .take_while(|arg| arg.len() > 1 && arg.starts_with('-'))
+        .count();
+    let (flags, rest) = args.split_at(count);
+    let letters: Vec<char> = flags.iter().flat_map(|flag| flag.chars().skip(1)).collect();
+    match letters.iter().find(|letter| !allowed.contains(**letter)) {
+        Some(letter) => Err(format!("Unknown option -{}", letter)),
+        None => Ok((letters, rest)),
+    }
+}
+
+struct Cat;
+
+impl Command for Cat {
+    fn name(&self) -> &'static str {
+        "cat"
+    }
+
+    fn usage(&self) -> &'static str {
+        "cat [FILE]..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Prints the files, or the input without any."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(cat(io, args))
+    }
+
+    fn complete(&self, _args: &[&str], _word: &str) -> Vec<String> {
+        files::names()
+    }
+}
+
+async fn cat(io: &mut Io, args: &[String]) -> CommandResult {
+    open_files(io, args)?;
+    while let Some(line) = io.stdin.read_line().await {
+        io.stdout.write_line(&line).await;
+    }
+    Ok(())
+}
+
+struct Ls;
+
+impl Command for Ls {
+    fn name(&self) -> &'static str {
+        "ls"
+    }
+
+    fn usage(&self) -> &'static str {
+        "ls"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lists the files."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            for name in files::names() {
+                io.stdout.write_line(&name).await;
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Rm;
+
+impl Command for Rm {
+    fn name(&self) -> &'static str {
+        "rm"
+    }
+
+    fn usage(&self) -> &'static str {
+        "rm FILE..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Removes files."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        _io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            if args.is_empty() {
+                return Err(format!("Usage: {}", self.usage()));
+            }
+            match args.iter().find(|name| !files::remove(name)) {
+                Some(name) => Err(format!("{}: No such file", name)),
+                None => Ok(()),
+            }
+        })
+    }
+
+    fn complete(&self, _args: &[&str], _word: &str) -> Vec<String> {
+        files::names()
+    }
+}
+
+struct Grep;
+
+impl Command for Grep {
+    fn name(&self) -> &'static str {
+        "grep"
+    }
+
+    fn usage(&self) -> &'static str {
+        "grep [-icv] TEXT [FILE]..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Prints the lines containing the text. -i ignores case, -v inverts, -c counts."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(grep(io, args, self.usage()))
+    }
+
+    fn complete(&self, _args: &[&str], _word: &str) -> Vec<String> {
+        files::names()
+    }
+}
+
+/// Fails silently if no line matched, so scripts can test for it.
+async fn grep(io: &mut Io, args: &[String], usage: &str) -> CommandResult {
+    let (flags, args) = flags(args, "icv")?;
+    let (pattern, names) = args
+        .split_first()
+        .ok_or_else(|| format!("Usage: {}", usage))?;
+    let ignore_case = flags.contains(&'i');
+    let pattern = if ignore_case {
+        pattern.to_lowercase()
+    } else {
+        pattern.clone()
+    };
+    open_files(io, names)?;
+    let mut matches = 0_usize;
+    while let Some(line) = io.stdin.read_line().await {
+        let found = if ignore_case {
+            line.to_lowercase().contains(&pattern)
+        } else {
+            line.contains(&pattern)
+        };
+        if found != flags.contains(&'v') {
+            matches += 1;
+            if !flags.contains(&'c') {
+                io.stdout.write_line(&line).await;
+            }
+        }
+    }
+    if flags.contains(&'c') {
+        io.stdout.write_line(&matches.to_string()).await;
+    }
+    if matches == 0 {
+        Err(String::new())
+    } else {
+        Ok(())
+    }
+}
+
+struct Head;
+
+impl Command for Head {
+    fn name(&self) -> &'static str {
+        "head"
+    }
+
+    fn usage(&self) -> &'static str {
+        "head [-n LINES] [FILE]..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Prints the first 10 lines, or as many as given."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(head(io, args, self.usage()))
+    }
+
+    fn complete(&self, _args: &[&str], _word: &str) -> Vec<String> {
+        files::names()
+    }
+}
+
+async fn head(io: &mut Io, args: &[String], usage: &str) -> CommandResult {
+    let (count, names) = match args {
+        [flag, count, names @ ..] if flag == "-n" => (count.parse().ok(), names),
+        names => (Some(10), names),
+    };
+    let count: usize = count.ok_or_else(|| format!("Usage: {}", usage))?;
+    open_files(io, names)?;
+    for _ in 0..count {
+        match io.stdin.read_line().await {
+            Some(line) => io.stdout.write_line(&line).await,
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+struct Wc;
+
+impl Command for Wc {
+    fn name(&self) -> &'static str {
+        "wc"
+    }
+
+    fn usage(&self) -> &'static str {
+        "wc [-lwc] [FILE]..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Counts lines, words and bytes, or only the ones selected."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(wc(io, args))
+    }
+
+    fn complete(&self, _args: &[&str], _word: &str) -> Vec<String> {
+        files::names()
+    }
+}
+
+async fn wc(io: &mut Io, args: &[String]) -> CommandResult {
+    let (mut flags, names) = flags(args, "lwc")?;
+    if flags.is_empty() {
+        flags = vec!['l', 'w', 'c'];
+    }
+    open_files(io, names)?;
+    let (mut lines, mut words, mut bytes) = (0_usize, 0_usize, 0_usize);
+    while let Some(line) = io.stdin.read_line().await {
+        lines += 1;
+        words += line.split_whitespace().count();
+        bytes += line.len() + 1;
+    }
+    let counts: Vec<String> = ['l', 'w', 'c']
+        .iter()
+        .zip([lines, words, bytes])
+        .filter(|(flag, _)| flags.contains(flag))
+        .map(|(_, count)| format!("{:>7}", count))
+        .collect();
+    io.stdout.write_line(&counts.join(" ")).await;
+    Ok(())
+}
+
+struct More;
+
+impl Command for More {
+    fn name(&self) -> &'static str {
+        "more"
+    }
+
+    fn usage(&self) -> &'static str {
+        "more [FILE]..."
+    }
+
+    fn description(&self) -> &'static str {
+        "Shows a page at a time. Space shows the next page, Enter the next line, q quits."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(more(io, args))
+    }
+
+    fn complete(&self, _args: &[&str], _word: &str) -> Vec<String> {
+        files::names()
+    }
+}
+
+const MORE_PROMPT: &str = "--More--";
+
+/// Only pages on the VGA terminal, otherwise it passes the input through like `cat`. Serial
+/// terminals scroll back themselves.
+async fn more(io: &mut Io, args: &[String]) -> CommandResult {
+    open_files(io, args)?;
+    if io.stdout.console() != Some(Console::Vga) {
+        return cat(io, &[]).await;
+    }
+    // One row stays for the prompt
+    let page = usize::from(STDOUT.lock().size().rows)
+        .saturating_sub(1)
+        .max(1);
+    // Takes the focus from the shell, which gets it back once this is dropped
+    let mut keys: Option<ScancodeStream> = None;
+    let mut remaining = page;
+    while let Some(line) = io.stdin.read_line().await {
+        if remaining == 0 {
+            let keys = keys.get_or_insert_with(|| {
+                let keys = ScancodeStream::new();
+                keys.events().focus();
+                keys
+            });
+            io.stdout.write_str(MORE_PROMPT).await;
+            let key = loop {
+                match keys.next().await {
+                    Some(DecodedKey::Unicode(key @ (' ' | '\n' | 'q'))) => break key,
+                    Some(_) => {}
+                    None => break 'q',
+                }
+            };
+            io.stdout.write_str("\r").await;
+            io.stdout.write_str(&" ".repeat(MORE_PROMPT.len())).await;
+            io.stdout.write_str("\r").await;
+            remaining = match key {
+                ' ' => page,
+                '\n' => 1,
+                _ => return Ok(()),
+            };
+        }
+        io.stdout.write_line(&line).await;
+        remaining -= 1;
+    }
+    Ok(())
+}
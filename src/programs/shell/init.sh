@@ -0,0 +1,6 @@
+# Sourced by Minell before the first prompt.
+#
+# It is embedded into the kernel and copied to the file init.sh at boot, unless that file
+# already exists. Commands, conditionals, loops and functions work like at the prompt, e.g.
+#
+#     for NAME in a b; do set "GREETING_$NAME=hello $NAME"; done
@@ -0,0 +1,311 @@
+//! Input and output of commands.
+//!
+//! Commands write to `Io::stdout` and read from `Io::stdin`, instead of using `print!`.
+//! The shell connects them to the terminal, to a pipe between two commands or to a file.
+//! Errors still go to the console of the shell.
+//!
+//! Pipes are bounded, so a writer waits for the reader to catch up. Once the reader is
+//! gone, further output is discarded and the shell stops the writing command.
+use alloc::{string::String, sync::Arc};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::{
+    future::{self, poll_fn},
+    task::AtomicWaker,
+};
+use spin::Mutex;
+
+use super::files;
+use crate::{
+    concurrency::{Waiter, WakerList},
+    programs::terminal::Console,
+};
+
+/// Buffered bytes, after which writers wait. The last write may exceed it, so text of any
+/// length can be written.
+pub const PIPE_CAPACITY: usize = 4096;
+
+/// Streams of a command
+pub struct Io {
+    pub stdin: Input,
+    pub stdout: Output,
+}
+
+impl Io {
+    /// No input and output to the terminal
+    #[must_use]
+    pub fn terminal(console: Console) -> Self {
+        Self {
+            stdin: Input::Empty,
+            stdout: Output::Terminal(console),
+        }
+    }
+}
+
+pub enum Input {
+    /// Ends right away, e.g. for the first command of a pipeline
+    Empty,
+    Pipe(PipeReader),
+    /// Fixed text, e.g. for tests
+    Text(String),
+}
+
+impl Input {
+    /// Next line without the newline. `None` at the end of the input.
+    pub async fn read_line(&mut self) -> Option<String> {
+        match self {
+            Input::Empty => None,
+            Input::Pipe(pipe) => pipe.read_line().await,
+            Input::Text(text) if text.is_empty() => None,
+            Input::Text(text) => {
+                let rest = match text.find('\n') {
+                    Some(end) => text.split_off(end + 1),
+                    None => String::new(),
+                };
+                let mut line = core::mem::replace(text, rest);
+                if line.ends_with('\n') {
+                    line.pop();
+                }
+                Some(line)
+            }
+        }
+    }
+
+    /// Everything up to the end of the input
+    pub async fn read_to_string(&mut self) -> String {
+        let mut text = String::new();
+        while let Some(line) = self.read_line().await {
+            text.push_str(&line);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Cloned for each command of a script, which all write to the same output
+#[derive(Clone)]
+pub enum Output {
+    /// The `STDOUT` view or the serial terminal
+    Terminal(Console),
+    Pipe(PipeWriter),
+    /// Appends to the file with this name
+    File(String),
+}
+
+impl Output {
+    /// Creates or truncates the file, unless the output is appended.
+    #[must_use]
+    pub fn file(name: &str, append: bool) -> Self {
+        if !append || !files::exists(name) {
+            files::write(name, "");
+        }
+        Output::File(name.into())
+    }
+
+    #[must_use]
+    pub fn is_terminal(&self) -> bool {
+        self.console().is_some()
+    }
+
+    /// Of the terminal, if the output isn't redirected
+    #[must_use]
+    pub fn console(&self) -> Option<Console> {
+        match self {
+            Output::Terminal(console) => Some(*console),
+            _ => None,
+        }
+    }
+
+    /// Waits while a pipe is full. Writing can't fail, so unlike `fmt::Write` there is no
+    /// result to handle.
+    pub async fn write_str(&mut self, text: &str) {
+        match self {
+            Output::Terminal(console) => console.print(text),
+            Output::Pipe(pipe) => pipe.write(text).await,
+            Output::File(name) => files::append(name, text),
+        }
+    }
+
+    /// `text` followed by a newline. Formatted text is passed as `&format!(..)`, as the
+    /// arguments of `writeln!` can't be held across an `.await`.
+    pub async fn write_line(&mut self, text: &str) {
+        self.write_str(text).await;
+        self.write_str("\n").await;
+    }
+
+    /// Resolves once nobody reads the output anymore, so the writing command can be stopped.
+    /// Never for the terminal or a file.
+    pub fn reader_closed(&self) -> impl Future<Output = ()> + Send + Sync + 'static {
+        let pipe = match self {
+            Output::Pipe(writer) => Some(writer.pipe.clone()),
+            _ => None,
+        };
+        async move {
+            match pipe {
+                Some(pipe) => {
+                    let mut waiter = Waiter::new(&pipe.writer_waiters);
+                    poll_fn(|cx| loop {
+                        if pipe.reader_closed.load(Ordering::Acquire) {
+                            waiter.cancel();
+                            return Poll::Ready(());
+                        }
+                        if !waiter.register(cx.waker()) {
+                            // The reader could have been dropped before registering
+                            if pipe.reader_closed.load(Ordering::Acquire) {
+                                continue;
+                            }
+                            return Poll::Pending;
+                        }
+                    })
+                    .await;
+                }
+                None => future::pending().await,
+            }
+        }
+    }
+}
+
+/// Connects the output of one command to the input of the next one
+pub fn pipe() -> (PipeWriter, PipeReader) {
+    let pipe = Arc::new(Pipe {
+        buffer: Mutex::new(String::new()),
+        writers: AtomicUsize::new(1),
+        reader_closed: AtomicBool::new(false),
+        waker: AtomicWaker::new(),
+        writer_waiters: WakerList::new(),
+    });
+    (PipeWriter { pipe: pipe.clone() }, PipeReader { pipe })
+}
+
+/// Holds up to `PIPE_CAPACITY` bytes
+struct Pipe {
+    buffer: Mutex<String>,
+    /// Closed, once there are none left
+    writers: AtomicUsize,
+    /// Further output is discarded
+    reader_closed: AtomicBool,
+    /// Of the reader
+    waker: AtomicWaker,
+    /// Writers waiting for space or for the reader to close
+    writer_waiters: WakerList,
+}
+
+impl Pipe {
+    /// Appends `text`, unless the buffer is full
+    fn try_write(&self, text: &str) -> bool {
+        let mut buffer = self.buffer.lock();
+        if buffer.len() >= PIPE_CAPACITY {
+            return false;
+        }
+        buffer.push_str(text);
+        drop(buffer);
+        self.waker.wake();
+        true
+    }
+}
+
+/// Closes the pipe when the last clone is dropped, which ends the input of the reader
+pub struct PipeWriter {
+    pipe: Arc<Pipe>,
+}
+
+impl PipeWriter {
+    /// Waits for the reader to make room. Discards `text` once the reader is gone.
+    async fn write(&self, text: &str) {
+        let pipe = &self.pipe;
+        let mut waiter = Waiter::new(&pipe.writer_waiters);
+        poll_fn(|cx| loop {
+            if pipe.reader_closed.load(Ordering::Acquire) || pipe.try_write(text) {
+                waiter.cancel();
+                return Poll::Ready(());
+            }
+            if !waiter.register(cx.waker()) {
+                // The reader could have made room before registering
+                if pipe.reader_closed.load(Ordering::Acquire) || pipe.try_write(text) {
+                    waiter.cancel();
+                    return Poll::Ready(());
+                }
+                return Poll::Pending;
+            }
+        })
+        .await;
+    }
+}
+
+impl Clone for PipeWriter {
+    fn clone(&self) -> Self {
+        self.pipe.writers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            pipe: self.pipe.clone(),
+        }
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        if self.pipe.writers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.pipe.waker.wake();
+        }
+    }
+}
+
+pub struct PipeReader {
+    pipe: Arc<Pipe>,
+}
+
+impl PipeReader {
+    /// Next line without the newline, see `Input::read_line`
+    pub fn read_line(&mut self) -> ReadLine<'_> {
+        ReadLine { reader: self }
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.pipe.reader_closed.store(true, Ordering::Release);
+        self.pipe.writer_waiters.wake_all();
+    }
+}
+
+/// Future of `PipeReader::read_line`
+pub struct ReadLine<'a> {
+    reader: &'a mut PipeReader,
+}
+
+impl Future for ReadLine<'_> {
+    type Output = Option<String>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<String>> {
+        let pipe = &self.reader.pipe;
+        // Before checking, so a write inbetween wakes us
+        pipe.waker.register(cx.waker());
+        // Read before the buffer, so no output written before closing is missed
+        let closed = pipe.writers.load(Ordering::Acquire) == 0;
+        let mut buffer = pipe.buffer.lock();
+        if let Some(end) = buffer.find('\n') {
+            let rest = buffer.split_off(end + 1);
+            let mut line = core::mem::replace(&mut *buffer, rest);
+            drop(buffer);
+            pipe.writer_waiters.wake_all();
+            line.pop();
+            Poll::Ready(Some(line))
+        } else if closed && buffer.is_empty() {
+            Poll::Ready(None)
+        } else if closed {
+            Poll::Ready(Some(core::mem::take(&mut *buffer)))
+        } else if buffer.len() >= PIPE_CAPACITY {
+            // A single line longer than the pipe. It's passed on as is, so the writer can
+            // continue.
+            let line = core::mem::take(&mut *buffer);
+            drop(buffer);
+            pipe.writer_waiters.wake_all();
+            Poll::Ready(Some(line))
+        } else {
+            Poll::Pending
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Job control.
+//!
+//! `command &` runs the command in a subshell, which is spawned as a task of the global
+//! executor. Jobs, and the command the shell runs itself, are wrapped in `Controlled`,
+//! through which they can be stopped, resumed and cancelled. A cancelled future is dropped,
+//! without being polled again. The `Foreground` command receives Ctrl+C and Ctrl+Z, which
+//! only stops jobs.
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU8, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+
+use crate::task::executor::{JoinError, JoinHandle};
+
+/// Exit status of a cancelled command, as if it got a SIGINT
+pub const CANCELLED_STATUS: u8 = 130;
+
+mod state {
+    pub const RUNNING: u8 = 0;
+    pub const STOPPED: u8 = 1;
+    pub const CANCELLED: u8 = 2;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlState {
+    Running,
+    Stopped,
+    Cancelled,
+}
+
+/// Shared by a controlled future and whoever controls it
+pub struct Control {
+    state: AtomicU8,
+    /// The shell can't continue before its own command finished, so only jobs are stoppable
+    stoppable: bool,
+    /// Exit status, once the job finished
+    status: AtomicU8,
+    /// Task of the controlled future
+    task: AtomicWaker,
+    /// Waits for the job to stop, e.g. `fg`
+    observer: AtomicWaker,
+}
+
+impl Control {
+    #[must_use]
+    pub fn new(stoppable: bool) -> Self {
+        Self {
+            state: AtomicU8::new(state::RUNNING),
+            stoppable,
+            status: AtomicU8::new(0),
+            task: AtomicWaker::new(),
+            observer: AtomicWaker::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn state(&self) -> ControlState {
+        match self.state.load(Ordering::Acquire) {
+            state::RUNNING => ControlState::Running,
+            state::STOPPED => ControlState::Stopped,
+            _ => ControlState::Cancelled,
+        }
+    }
+
+    /// Returns `false`, if it was already cancelled.
+    pub fn cancel(&self) -> bool {
+        let cancelled = self.state.swap(state::CANCELLED, Ordering::AcqRel) != state::CANCELLED;
+        self.notify();
+        cancelled
+    }
+
+    /// Returns `false`, if it isn't stoppable or isn't running.
+    pub fn stop(&self) -> bool {
+        let stopped = self.stoppable && self.transition(state::RUNNING, state::STOPPED);
+        self.notify();
+        stopped
+    }
+
+    /// Returns `false`, if it wasn't stopped.
+    pub fn resume(&self) -> bool {
+        let resumed = self.transition(state::STOPPED, state::RUNNING);
+        self.notify();
+        resumed
+    }
+
+    /// Called by the task of a job, with the exit status of its command
+    pub fn set_status(&self, status: u8) {
+        self.status.store(status, Ordering::Release);
+    }
+
+    fn transition(&self, from: u8, to: u8) -> bool {
+        self.state
+            .compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    fn notify(&self) {
+        self.task.wake();
+        self.observer.wake();
+    }
+}
+
+/// Runs the future, while it isn't stopped. Resolves to `None`, once it was cancelled.
+pub struct Controlled<F> {
+    future: F,
+    control: Arc<Control>,
+}
+
+impl<F: Future + Unpin> Controlled<F> {
+    pub fn new(future: F, control: Arc<Control>) -> Self {
+        Self { future, control }
+    }
+}
+
+impl<F: Future + Unpin> Future for Controlled<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // Before checking the state, so a change inbetween wakes us
+        self.control.task.register(cx.waker());
+        match self.control.state() {
+            ControlState::Running => Pin::new(&mut self.future).poll(cx).map(Some),
+            ControlState::Stopped => Poll::Pending,
+            ControlState::Cancelled => Poll::Ready(None),
+        }
+    }
+}
+
+/// Command, which receives Ctrl+C and Ctrl+Z
+#[derive(Default)]
+pub struct Foreground {
+    current: Mutex<Option<Arc<Control>>>,
+}
+
+impl Foreground {
+    /// Cancels the foreground command. Returns `false`, if there is none.
+    pub fn interrupt(&self) -> bool {
+        self.current
+            .lock()
+            .as_ref()
+            .map_or(false, |control| control.cancel())
+    }
+
+    /// Stops the foreground job. Returns `false`, if there is none.
+    pub fn stop(&self) -> bool {
+        self.current
+            .lock()
+            .as_ref()
+            .map_or(false, |control| control.stop())
+    }
+
+    /// Makes `control` the foreground, until the guard is dropped.
+    pub fn enter(&self, control: Arc<Control>) -> ForegroundGuard<'_> {
+        let previous = self.current.lock().replace(control);
+        ForegroundGuard {
+            foreground: self,
+            previous,
+        }
+    }
+}
+
+/// Restores the previous foreground command
+pub struct ForegroundGuard<'a> {
+    foreground: &'a Foreground,
+    previous: Option<Arc<Control>>,
+}
+
+impl Drop for ForegroundGuard<'_> {
+    fn drop(&mut self) {
+        *self.foreground.current.lock() = self.previous.take();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    /// With the exit status
+    Done(u8),
+    /// Panicked or killed by the watchdog
+    Failed(JoinError),
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Running => f.write_str("Running"),
+            JobState::Stopped => f.write_str("Stopped"),
+            JobState::Done(0) => f.write_str("Done"),
+            JobState::Done(CANCELLED_STATUS) => f.write_str("Interrupted"),
+            JobState::Done(status) => write!(f, "Exit {}", status),
+            JobState::Failed(error) => write!(f, "Failed: {}", error),
+        }
+    }
+}
+
+/// Command running in the background
+pub struct Job {
+    id: usize,
+    line: String,
+    control: Arc<Control>,
+    handle: JoinHandle,
+}
+
+impl Job {
+    /// Number of the job, which `%n` refers to
+    #[must_use]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Command line, which started the job
+    #[must_use]
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    #[must_use]
+    pub fn control(&self) -> &Arc<Control> {
+        &self.control
+    }
+
+    #[must_use]
+    pub fn state(&self) -> JobState {
+        match self.handle.try_join() {
+            Some(Ok(())) => JobState::Done(self.control.status.load(Ordering::Acquire)),
+            Some(Err(error)) => JobState::Failed(error),
+            None if self.control.state() == ControlState::Stopped => JobState::Stopped,
+            None => JobState::Running,
+        }
+    }
+
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Resolves once the job finished or was stopped.
+    pub fn wait(&mut self) -> Wait<'_> {
+        Wait { job: self }
+    }
+}
+
+/// Future of `Job::wait`
+pub struct Wait<'a> {
+    job: &'a mut Job,
+}
+
+impl Future for Wait<'_> {
+    type Output = JobState;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<JobState> {
+        self.job.control.observer.register(cx.waker());
+        if Pin::new(&mut self.job.handle).poll(cx).is_ready()
+            || self.job.control.state() == ControlState::Stopped
+        {
+            Poll::Ready(self.job.state())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Jobs of a shell
+#[derive(Default)]
+pub struct Jobs {
+    /// Ordered by id
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    /// Adds a spawned job, whose task sets the exit status with `Control::set_status`. Returns the id.
+    pub fn add(&mut self, line: String, control: Arc<Control>, handle: JoinHandle) -> usize {
+        let id = self.jobs.last().map_or(1, |job| job.id + 1);
+        self.jobs.push(Job {
+            id,
+            line,
+            control,
+            handle,
+        });
+        id
+    }
+
+    #[must_use]
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// The job, which is meant without a job number: the newest one
+    #[must_use]
+    pub fn current(&self) -> Option<&Job> {
+        self.jobs.last()
+    }
+
+    /// Job number of `%n`, `n` or, without a `spec`, of the current job
+    ///
+    /// # Errors
+    /// If there is no such job.
+    pub fn find(&self, spec: Option<&str>) -> Result<usize, String> {
+        let job = match spec {
+            None => self.current(),
+            Some(spec) => spec
+                .strip_prefix('%')
+                .unwrap_or(spec)
+                .parse()
+                .ok()
+                .and_then(|id| self.get(id)),
+        };
+        job.map(Job::id).ok_or_else(|| String::from("No such job"))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Removes and returns the finished jobs
+    pub fn reap(&mut self) -> Vec<Job> {
+        let (finished, running) = core::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(Job::is_finished);
+        self.jobs = running;
+        finished
+    }
+}
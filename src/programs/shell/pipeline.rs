@@ -0,0 +1,90 @@
+//! Groups the tokens of a line into the commands of a pipeline.
+//!
+//! `a | b > file &` runs `a` and `b` concurrently, with the output of `a` as the input of
+//! `b`, whose output goes to `file`. A redirection replaces the pipe, so the next command
+//! gets no input.
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use super::tokenizer::Token;
+
+/// Output of a command to a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    pub file: String,
+    /// `>>` instead of `>`
+    pub append: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Stage {
+    /// Never empty
+    pub words: Vec<String>,
+    pub redirect: Option<Redirect>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Pipeline {
+    /// Empty for an empty line
+    pub stages: Vec<Stage>,
+    /// Ends with `&`
+    pub background: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// An operator where it isn't allowed, e.g. `&` in the middle of the line
+    Unexpected(Token),
+    /// `>` or `>>` without a file name
+    MissingFile,
+    /// Operators without a command, e.g. `a |` or `&`
+    MissingCommand,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Unexpected(token) => write!(f, "Unexpected {}", token),
+            ParseError::MissingFile => f.write_str("Missing file name after >"),
+            ParseError::MissingCommand => f.write_str("Missing command"),
+        }
+    }
+}
+
+/// # Errors
+/// If the operators don't form a pipeline.
+pub fn parse(tokens: Vec<Token>) -> Result<Pipeline, ParseError> {
+    let mut pipeline = Pipeline::default();
+    if tokens.is_empty() {
+        return Ok(pipeline);
+    }
+    let mut stage = Stage::default();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => stage.words.push(word),
+            Token::Pipe => {
+                if stage.words.is_empty() {
+                    return Err(ParseError::MissingCommand);
+                }
+                pipeline.stages.push(core::mem::take(&mut stage));
+            }
+            Token::Redirect | Token::Append => match tokens.next() {
+                Some(Token::Word(file)) => {
+                    stage.redirect = Some(Redirect {
+                        file,
+                        append: token == Token::Append,
+                    });
+                }
+                _ => return Err(ParseError::MissingFile),
+            },
+            Token::Background if tokens.peek().is_none() => pipeline.background = true,
+            Token::Background => return Err(ParseError::Unexpected(token)),
+        }
+    }
+    if stage.words.is_empty() {
+        return Err(ParseError::MissingCommand);
+    }
+    pipeline.stages.push(stage);
+    Ok(pipeline)
+}
@@ -0,0 +1,304 @@
+//! Scripts: sequences of commands with conditionals, loops and functions.
+//!
+//! Commands are separated by newlines or `;`, a `#` at the start of a word comments out the
+//! rest of the line and a backslash at the end of a line continues it.
+//!
+//! ```text
+//! # Exit status 0 is true
+//! if grep -c x file; then
+//!     set FOUND=yes
+//! elif help x; then set HELP=yes
+//! else
+//!     set FOUND=no
+//! fi
+//! for NAME in a "b c" $LIST; do greet $NAME; done
+//! while grep x file; do rm file; done
+//! greet() { help $1; }
+//! function greet { help $1; }
+//! ```
+//!
+//! Only the structure is parsed up front. Commands and the words of `for` are expanded when
+//! they run, so they see the variables set before.
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec, vec::Vec};
+use core::fmt;
+
+use super::tokenizer::{is_valid_name, TokenizeError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    /// A command line, which isn't expanded yet
+    Command(String),
+    If {
+        condition: String,
+        then: Vec<Statement>,
+        /// `elif` is an `if` within `else`
+        otherwise: Vec<Statement>,
+    },
+    For {
+        name: String,
+        /// Not expanded yet
+        words: String,
+        body: Vec<Statement>,
+    },
+    While {
+        condition: String,
+        body: Vec<Statement>,
+    },
+    /// Defines the function, which is called like a command
+    Function {
+        name: String,
+        body: Arc<Vec<Statement>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    Tokenize(TokenizeError),
+    /// A keyword where it isn't allowed, e.g. `fi` without `if`
+    Unexpected(String),
+    /// The script ended or another keyword came before this one, e.g. `if a; fi`
+    Missing(&'static str),
+    /// Of a `for` variable or a function
+    InvalidName(String),
+    /// More than `MAX_DEPTH` nested statements
+    TooDeep,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Tokenize(error) => error.fmt(f),
+            ScriptError::Unexpected(keyword) => write!(f, "Unexpected {}", keyword),
+            ScriptError::Missing(keyword) => write!(f, "Missing {}", keyword),
+            ScriptError::InvalidName(name) => write!(f, "Invalid name: {}", name),
+            ScriptError::TooDeep => f.write_str("Too many nested statements"),
+        }
+    }
+}
+
+impl From<TokenizeError> for ScriptError {
+    fn from(error: TokenizeError) -> Self {
+        ScriptError::Tokenize(error)
+    }
+}
+
+/// Of statements within each other, so parsing and running them doesn't overflow the stack.
+/// An `elif` counts as nested within its `if`.
+const MAX_DEPTH: usize = 16;
+
+/// Words, which only begin a command if they are quoted
+const KEYWORDS: &[&str] = &[
+    "if", "then", "elif", "else", "fi", "for", "do", "done", "while", "function", "{", "}",
+];
+
+/// # Errors
+/// If a quote isn't closed, the keywords don't match up or the statements are nested too
+/// deeply.
+pub fn parse(script: &str) -> Result<Vec<Statement>, ScriptError> {
+    let mut parser = Parser {
+        lines: split(script)?.into(),
+        depth: 0,
+    };
+    let (statements, _, _) = parser.block(&[])?;
+    Ok(statements)
+}
+
+/// Splits the script into command lines at unquoted newlines and `;`, without comments.
+fn split(script: &str) -> Result<Vec<String>, TokenizeError> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut chars = script.chars().peekable();
+    while let Some(character) = chars.next() {
+        match character {
+            '\n' | ';' => lines.push(core::mem::take(&mut line)),
+            '#' if line.chars().last().map_or(true, char::is_whitespace) => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(escaped) => {
+                    line.push('\\');
+                    line.push(escaped);
+                }
+                None => return Err(TokenizeError::TrailingEscape),
+            },
+            quote @ ('\'' | '"') => {
+                line.push(quote);
+                loop {
+                    match chars.next() {
+                        Some(c) if c == quote => break,
+                        Some('\\') if quote == '"' => {
+                            line.push('\\');
+                            line.extend(chars.next());
+                        }
+                        Some(c) => line.push(c),
+                        None => return Err(TokenizeError::UnterminatedQuote(quote)),
+                    }
+                }
+                line.push(quote);
+            }
+            c => line.push(c),
+        }
+    }
+    lines.push(line);
+    Ok(lines
+        .into_iter()
+        .map(|line| String::from(line.trim()))
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// The first word of the line and the rest
+fn keyword(line: &str) -> (&str, &str) {
+    match line.split_once(char::is_whitespace) {
+        Some((keyword, rest)) => (keyword, rest.trim_start()),
+        None => (line, ""),
+    }
+}
+
+struct Parser {
+    lines: VecDeque<String>,
+    /// Of the statement being parsed
+    depth: usize,
+}
+
+impl Parser {
+    /// Statements up to one of the keywords in `end`, or the end of the script without any.
+    /// Returns the keyword and the rest of its line.
+    fn block(
+        &mut self,
+        end: &[&'static str],
+    ) -> Result<(Vec<Statement>, &'static str, String), ScriptError> {
+        let mut statements = Vec::new();
+        while let Some(line) = self.lines.pop_front() {
+            let (first, rest) = keyword(&line);
+            if let Some(found) = end.iter().find(|candidate| **candidate == first) {
+                return Ok((statements, found, rest.into()));
+            }
+            let statement = match first {
+                "if" => self.nested(|parser| parser.parse_if(rest))?,
+                "for" => self.nested(|parser| parser.parse_for(rest))?,
+                "while" => self.nested(|parser| parser.parse_while(rest))?,
+                "function" => {
+                    let (name, rest) = keyword(rest);
+                    self.nested(|parser| parser.parse_function(name, rest))?
+                }
+                _ if KEYWORDS.contains(&first) => {
+                    return Err(ScriptError::Unexpected(first.into()))
+                }
+                _ => match first.strip_suffix("()") {
+                    Some(name) => self.nested(|parser| parser.parse_function(name, rest))?,
+                    None => Statement::Command(line),
+                },
+            };
+            statements.push(statement);
+        }
+        match end.first() {
+            Some(keyword) => Err(ScriptError::Missing(keyword)),
+            None => Ok((statements, "", String::new())),
+        }
+    }
+
+    /// Parses a statement within the current one
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Statement, ScriptError>,
+    ) -> Result<Statement, ScriptError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ScriptError::TooDeep);
+        }
+        self.depth += 1;
+        let statement = parse(self);
+        self.depth -= 1;
+        statement
+    }
+
+    /// Statements up to `end`, which must follow `start`
+    fn body(
+        &mut self,
+        start: &'static str,
+        end: &'static str,
+    ) -> Result<Vec<Statement>, ScriptError> {
+        self.expect(start)?;
+        Ok(self.block(&[end])?.0)
+    }
+
+    /// The next line must start with the keyword. A command may follow it on the same line,
+    /// e.g. `then set A=1`.
+    fn expect(&mut self, expected: &'static str) -> Result<(), ScriptError> {
+        match self.lines.pop_front() {
+            Some(line) if keyword(&line).0 == expected => {
+                self.continue_with(keyword(&line).1);
+                Ok(())
+            }
+            _ => Err(ScriptError::Missing(expected)),
+        }
+    }
+
+    /// Puts the rest of a keyword line back, as a line of its own
+    fn continue_with(&mut self, rest: &str) {
+        if !rest.is_empty() {
+            self.lines.push_front(rest.into());
+        }
+    }
+
+    fn parse_if(&mut self, condition: &str) -> Result<Statement, ScriptError> {
+        if condition.is_empty() {
+            return Err(ScriptError::Missing("condition"));
+        }
+        self.expect("then")?;
+        let (then, end, rest) = self.block(&["elif", "else", "fi"])?;
+        let otherwise = match end {
+            "elif" => vec![self.nested(|parser| parser.parse_if(&rest))?],
+            "else" => {
+                self.continue_with(&rest);
+                self.block(&["fi"])?.0
+            }
+            _ => Vec::new(),
+        };
+        Ok(Statement::If {
+            condition: condition.into(),
+            then,
+            otherwise,
+        })
+    }
+
+    fn parse_for(&mut self, rest: &str) -> Result<Statement, ScriptError> {
+        let (name, rest) = keyword(rest);
+        if !is_valid_name(name) {
+            return Err(ScriptError::InvalidName(name.into()));
+        }
+        let words = match keyword(rest) {
+            ("in", words) => String::from(words),
+            _ => return Err(ScriptError::Missing("in")),
+        };
+        Ok(Statement::For {
+            name: name.into(),
+            words,
+            body: self.body("do", "done")?,
+        })
+    }
+
+    fn parse_while(&mut self, condition: &str) -> Result<Statement, ScriptError> {
+        if condition.is_empty() {
+            return Err(ScriptError::Missing("condition"));
+        }
+        Ok(Statement::While {
+            condition: condition.into(),
+            body: self.body("do", "done")?,
+        })
+    }
+
+    /// `rest` may already hold the `{`
+    fn parse_function(&mut self, name: &str, rest: &str) -> Result<Statement, ScriptError> {
+        let name = name.strip_suffix("()").unwrap_or(name);
+        if !is_valid_name(name) {
+            return Err(ScriptError::InvalidName(name.into()));
+        }
+        self.continue_with(rest);
+        Ok(Statement::Function {
+            name: name.into(),
+            body: Arc::new(self.body("{", "}")?),
+        })
+    }
+}
@@ -0,0 +1,588 @@
+use super::*;
+use alloc::{boxed::Box, sync::Arc, vec};
+use core::future::Future;
+use futures_util::{future, pin_mut, FutureExt};
+
+#[test_case]
+fn tokenize_words() {
+    assert_eq!(tokenize("  help   foo ").unwrap(), ["help", "foo"]);
+    assert!(tokenize("   ").unwrap().is_empty());
+}
+
+#[test_case]
+fn tokenize_quotes_and_escapes() {
+    assert_eq!(
+        tokenize(r#"echo "a  b" 'c "d"' e\ f"#).unwrap(),
+        ["echo", "a  b", r#"c "d""#, "e f"]
+    );
+    assert_eq!(
+        tokenize(r#""\"\\\n" '\n' x""y"#).unwrap(),
+        [r#""\\n"#, r"\n", "xy"]
+    );
+    assert_eq!(tokenize(r#"'' """#).unwrap(), ["", ""]);
+}
+
+#[test_case]
+fn tokenize_errors() {
+    assert_eq!(
+        tokenize("echo 'a"),
+        Err(TokenizeError::UnterminatedQuote('\''))
+    );
+    assert_eq!(
+        tokenize("echo \"a"),
+        Err(TokenizeError::UnterminatedQuote('"'))
+    );
+    assert_eq!(tokenize("echo a\\"), Err(TokenizeError::TrailingEscape));
+}
+
+struct Greet;
+
+impl Command for Greet {
+    fn name(&self) -> &'static str {
+        "greet"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["hi"]
+    }
+
+    fn usage(&self) -> &'static str {
+        "greet <name>"
+    }
+
+    fn description(&self) -> &'static str {
+        "Fails without a name."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        _io: &'a mut Io,
+        args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(async move {
+            if args.len() == 1 {
+                Ok(())
+            } else {
+                Err(String::from("Usage: greet <name>"))
+            }
+        })
+    }
+
+    fn complete(&self, _args: &[&str], _word: &str) -> Vec<String> {
+        vec!["world".into()]
+    }
+}
+
+#[test_case]
+fn registry() {
+    register(Greet).unwrap();
+    assert_eq!(
+        register(Greet),
+        Err(RegisterError::NameTaken(String::from("greet")))
+    );
+    assert_eq!(find("hi").unwrap().name(), "greet");
+    assert_eq!(commands().iter().filter(|c| c.name() == "greet").count(), 1);
+    assert!(find("help").is_some());
+
+    assert_eq!(RegistryCompleter.complete(&["hi"], "w"), ["world"]);
+    assert!(RegistryCompleter
+        .complete(&[], "")
+        .contains(&String::from("greet")));
+
+    assert!(unregister("hi"));
+    assert!(find("greet").is_none());
+    assert!(!unregister("greet"));
+}
+
+#[test_case]
+fn exit_status() {
+    register(Greet).unwrap();
+    let mut shell = Shell::new();
+    let mut execute = |line| {
+        shell.execute(line).now_or_never().unwrap();
+        shell.status()
+    };
+    assert_eq!(execute("greet 'the world'"), 0);
+    assert_eq!(execute("greet"), 1);
+    assert_eq!(execute("no-such-command"), 127);
+    assert_eq!(execute("greet 'x"), 2);
+    unregister("greet");
+}
+
+#[test_case]
+fn expand_variables() {
+    let lookup = |name: &str| match name {
+        "A" => Some(String::from("x y")),
+        "EMPTY" => Some(String::new()),
+        "?" => Some(String::from("0")),
+        _ => None,
+    };
+    assert_eq!(
+        tokenize_with("echo $A ${A}z \"$A\" '$A' \\$A $? $", &lookup).unwrap(),
+        ["echo", "x y", "x yz", "x y", "$A", "$A", "0", "$"]
+    );
+    // Unquoted, empty values vanish
+    assert_eq!(
+        tokenize_with("a $EMPTY $UNSET \"$EMPTY\"", &lookup).unwrap(),
+        ["a", ""]
+    );
+    assert_eq!(
+        tokenize_with("${A", &lookup),
+        Err(TokenizeError::UnterminatedBrace)
+    );
+    assert_eq!(expand("[$A] '$?'", &lookup).unwrap(), "[x y] '0'");
+}
+
+#[test_case]
+fn variable_names() {
+    assert!(is_valid_name("PS1") && is_valid_name("_a"));
+    assert!(!is_valid_name("") && !is_valid_name("1A") && !is_valid_name("A-B"));
+}
+
+#[test_case]
+fn set_export_and_prompt() {
+    let mut shell = Shell::new();
+    assert_eq!(shell.prompt(), "> ");
+    let mut execute = |line| {
+        shell.execute(line).now_or_never().unwrap();
+        shell.status()
+    };
+    assert_eq!(execute("set GREETING=hello 'PS1=$GREETING $? > '"), 0);
+    assert_eq!(execute("set 1=one"), 1);
+    assert_eq!(execute("export NAME=world"), 0);
+    assert_eq!(execute("export GREETING"), 0);
+    assert_eq!(shell.prompt(), "hello 0 > ");
+    assert_eq!(shell.variables().get("NAME"), Some("world"));
+
+    let environment = shell.variables().environment();
+    let exported: Vec<_> = environment.iter().collect();
+    assert_eq!(exported, [("GREETING", "hello"), ("NAME", "world")]);
+
+    shell.execute("unset GREETING").now_or_never().unwrap();
+    assert_eq!(shell.variables().get("GREETING"), None);
+    assert!(!shell.variables().is_exported("GREETING"));
+}
+
+#[test_case]
+fn background_operator() {
+    let words = |word: &str| Token::Word(word.into());
+    assert_eq!(
+        lex("run a&", &|_| None).unwrap(),
+        [words("run"), words("a"), Token::Background]
+    );
+    assert_eq!(
+        lex("echo '&' \\& \"&\"", &|_| None).unwrap(),
+        [words("echo"), words("&"), words("&"), words("&")]
+    );
+    assert_eq!(tokenize("a & b").unwrap(), ["a", "&", "b"]);
+
+    let mut shell = Shell::new();
+    shell.execute("help & help").now_or_never().unwrap();
+    assert_eq!(shell.status(), 2);
+    assert!(shell.jobs().is_empty());
+}
+
+#[test_case]
+fn controlled_future() {
+    let control = Arc::new(Control::new(true));
+    let mut future = Controlled::new(futures_util::future::ready(1), control.clone());
+    assert!(control.stop());
+    assert_eq!(control.state(), ControlState::Stopped);
+    assert_eq!((&mut future).now_or_never(), None);
+    assert!(control.resume());
+    assert!(!control.resume());
+    assert_eq!(future.now_or_never(), Some(Some(1)));
+
+    let control = Arc::new(Control::new(false));
+    let future = Controlled::new(futures_util::future::pending::<()>(), control.clone());
+    assert!(!control.stop());
+    assert!(control.cancel());
+    assert!(!control.cancel());
+    assert_eq!(future.now_or_never(), Some(None));
+}
+
+/// Never finishes on its own
+struct Wait;
+
+impl Command for Wait {
+    fn name(&self) -> &'static str {
+        "wait-forever"
+    }
+
+    fn usage(&self) -> &'static str {
+        "wait-forever"
+    }
+
+    fn description(&self) -> &'static str {
+        "Waits for Ctrl+C."
+    }
+
+    fn run<'a>(
+        &'a self,
+        _shell: &'a mut Shell,
+        _io: &'a mut Io,
+        _args: &'a [String],
+    ) -> CommandFuture<'a> {
+        Box::pin(futures_util::future::pending())
+    }
+}
+
+#[test_case]
+fn interrupt_foreground() {
+    register(Wait).unwrap();
+    let mut shell = Shell::new();
+    let foreground = shell.foreground();
+    assert!(!foreground.interrupt());
+    {
+        let mut execute = Box::pin(shell.execute("wait-forever"));
+        assert!(execute.as_mut().now_or_never().is_none());
+        // Commands the shell runs itself can't be stopped
+        assert!(!foreground.stop());
+        assert!(foreground.interrupt());
+        assert!(execute.now_or_never().is_some());
+    }
+    assert_eq!(shell.status(), CANCELLED_STATUS);
+    assert!(!foreground.interrupt());
+    unregister("wait-forever");
+}
+
+/// Polls until the future is ready, as loops yield after each iteration
+fn finish<F: Future>(future: F) -> F::Output {
+    pin_mut!(future);
+    loop {
+        if let Some(output) = future.as_mut().now_or_never() {
+            return output;
+        }
+    }
+}
+
+/// Output of the line
+fn capture(shell: &mut Shell, line: &str) -> String {
+    let (writer, reader) = pipe();
+    let mut input = Input::Pipe(reader);
+    // Read concurrently, as the pipe is bounded
+    let execute = shell.execute_to(line, Output::Pipe(writer));
+    finish(future::join(execute, input.read_to_string())).1
+}
+
+#[test_case]
+fn parse_pipelines() {
+    let tokens = |line| lex(line, &|_| None).unwrap();
+    assert_eq!(
+        tokens("a|b>f>>g"),
+        [
+            Token::Word("a".into()),
+            Token::Pipe,
+            Token::Word("b".into()),
+            Token::Redirect,
+            Token::Word("f".into()),
+            Token::Append,
+            Token::Word("g".into())
+        ]
+    );
+
+    let pipeline = parse(tokens("a x | b >> f &")).unwrap();
+    assert!(pipeline.background);
+    assert_eq!(pipeline.stages.len(), 2);
+    assert_eq!(pipeline.stages[0].words, ["a", "x"]);
+    assert_eq!(pipeline.stages[0].redirect, None);
+    assert_eq!(
+        pipeline.stages[1].redirect,
+        Some(Redirect {
+            file: "f".into(),
+            append: true
+        })
+    );
+    assert_eq!(parse(tokens("")).unwrap(), Pipeline::default());
+
+    assert_eq!(parse(tokens("a |")), Err(ParseError::MissingCommand));
+    assert_eq!(parse(tokens("| a")), Err(ParseError::MissingCommand));
+    assert_eq!(parse(tokens("&")), Err(ParseError::MissingCommand));
+    assert_eq!(parse(tokens("a >")), Err(ParseError::MissingFile));
+    assert_eq!(parse(tokens("a > | b")), Err(ParseError::MissingFile));
+    assert_eq!(
+        parse(tokens("a & b")),
+        Err(ParseError::Unexpected(Token::Background))
+    );
+}
+
+#[test_case]
+fn pipes() {
+    let (writer, reader) = pipe();
+    let mut output = Output::Pipe(writer);
+    let mut input = Input::Pipe(reader);
+    assert_eq!(input.read_line().now_or_never(), None);
+    assert_eq!(output.write_line("one").now_or_never(), Some(()));
+    assert_eq!(output.write_str("two").now_or_never(), Some(()));
+    assert_eq!(input.read_line().now_or_never(), Some(Some("one".into())));
+    // Waits for the end of the line
+    assert_eq!(input.read_line().now_or_never(), None);
+    drop(output);
+    assert_eq!(input.read_line().now_or_never(), Some(Some("two".into())));
+    assert_eq!(input.read_line().now_or_never(), Some(None));
+
+    let mut input = Input::Text("a\n\nb".into());
+    assert_eq!(input.read_to_string().now_or_never().unwrap(), "a\n\nb\n");
+}
+
+#[test_case]
+fn bounded_pipes() {
+    let (writer, reader) = pipe();
+    let mut output = Output::Pipe(writer);
+    let mut input = Input::Pipe(reader);
+    let line = "x".repeat(PIPE_CAPACITY - 1);
+    assert_eq!(output.write_line(&line).now_or_never(), Some(()));
+    // Full, until the line is read
+    assert_eq!(output.write_str("y\n").now_or_never(), None);
+    assert_eq!(input.read_line().now_or_never(), Some(Some(line.clone())));
+    assert_eq!(output.write_str("y\n").now_or_never(), Some(()));
+
+    let closed = output.reader_closed();
+    pin_mut!(closed);
+    assert_eq!(closed.as_mut().now_or_never(), None);
+    drop(input);
+    assert_eq!(closed.now_or_never(), Some(()));
+    // Discarded instead of waiting
+    assert_eq!(output.write_line(&line).now_or_never(), Some(()));
+    assert_eq!(output.write_line(&line).now_or_never(), Some(()));
+}
+
+#[test_case]
+fn pipelines_and_filters() {
+    let mut shell = Shell::new();
+    let help = capture(&mut shell, "help");
+    assert!(help.contains("grep"));
+    assert!(!help.contains("Usage"));
+
+    let count = |pattern: &str| help.lines().filter(|line| line.contains(pattern)).count();
+    assert!(count("job") > 0);
+    assert_eq!(
+        capture(&mut shell, "help | grep job | wc -l").trim(),
+        count("job").to_string()
+    );
+    assert_eq!(capture(&mut shell, "help | head -n 2").lines().count(), 2);
+    assert_eq!(shell.status(), 0);
+    // The endless producer is stopped, once `head` is done
+    finish(shell.execute("f() { while true; do help; done; }"));
+    assert_eq!(capture(&mut shell, "f | head -n 1").lines().count(), 1);
+
+    // Fails silently without a match
+    assert_eq!(capture(&mut shell, "help | grep no-such-text"), "");
+    assert_eq!(shell.status(), 1);
+    assert_eq!(
+        capture(&mut shell, "help | grep -v -i JOB | grep -c job").trim(),
+        "0"
+    );
+    // Passes the input through, if the output isn't the terminal
+    assert_eq!(capture(&mut shell, "help | more"), help);
+}
+
+#[test_case]
+fn redirection() {
+    let mut shell = Shell::new();
+    assert_eq!(capture(&mut shell, "help help > test-redirect"), "");
+    assert_eq!(capture(&mut shell, "help reboot >> test-redirect"), "");
+    let contents = files::read("test-redirect").unwrap();
+    assert!(contents.starts_with("Usage: help"));
+    assert!(contents.contains("Usage: reboot"));
+
+    assert_eq!(
+        capture(&mut shell, "cat test-redirect | wc -l").trim(),
+        contents.lines().count().to_string()
+    );
+    assert_eq!(
+        capture(&mut shell, "head -n 1 test-redirect"),
+        "Usage: help\n"
+    );
+    // Replaces the pipe, so `wc` counts nothing
+    assert_eq!(
+        capture(&mut shell, "help > test-redirect | wc -l").trim(),
+        "0"
+    );
+    assert!(files::read("test-redirect").unwrap().contains("grep"));
+
+    assert_eq!(
+        capture(&mut shell, "ls | grep test-redirect"),
+        "test-redirect\n"
+    );
+    shell.execute("rm test-redirect").now_or_never().unwrap();
+    assert!(files::read("test-redirect").is_none());
+    shell.execute("cat test-redirect").now_or_never().unwrap();
+    assert_eq!(shell.status(), 1);
+}
+
+#[test_case]
+fn parse_scripts() {
+    let command = |line: &str| Statement::Command(line.into());
+    let script = "# comment\nif a 'x;y' # not a word\nthen b; else\n c\nfi\n\
+                  for X in 1 \\\n 2; do d $X; done\nf() { g; }";
+    assert_eq!(
+        parse_script(script).unwrap(),
+        [
+            Statement::If {
+                condition: "a 'x;y'".into(),
+                then: vec![command("b")],
+                otherwise: vec![command("c")],
+            },
+            Statement::For {
+                name: "X".into(),
+                words: "1  2".into(),
+                body: vec![command("d $X")],
+            },
+            Statement::Function {
+                name: "f".into(),
+                body: Arc::new(vec![command("g")]),
+            },
+        ]
+    );
+    assert_eq!(
+        parse_script("if a; then b; elif c; then d; fi").unwrap(),
+        [Statement::If {
+            condition: "a".into(),
+            then: vec![command("b")],
+            otherwise: vec![Statement::If {
+                condition: "c".into(),
+                then: vec![command("d")],
+                otherwise: vec![],
+            }],
+        }]
+    );
+    assert_eq!(
+        parse_script("function f\n{\ng\n}\nwhile a; do 'done'; done").unwrap(),
+        [
+            Statement::Function {
+                name: "f".into(),
+                body: Arc::new(vec![command("g")]),
+            },
+            Statement::While {
+                condition: "a".into(),
+                body: vec![command("'done'")],
+            },
+        ]
+    );
+
+    let error = |script| parse_script(script).unwrap_err();
+    assert_eq!(error("fi"), ScriptError::Unexpected("fi".into()));
+    assert_eq!(error("if a; then b"), ScriptError::Missing("fi"));
+    assert_eq!(error("if a; b; fi"), ScriptError::Missing("then"));
+    assert_eq!(error("while a; do b"), ScriptError::Missing("done"));
+    assert_eq!(error("for X a; do b; done"), ScriptError::Missing("in"));
+    assert_eq!(
+        error("for 1 in a; do b; done"),
+        ScriptError::InvalidName("1".into())
+    );
+    assert_eq!(
+        error("a 'b"),
+        ScriptError::Tokenize(TokenizeError::UnterminatedQuote('\''))
+    );
+
+    let nested = |levels| {
+        let script = "while a; do ".repeat(levels) + "b" + &"; done".repeat(levels);
+        parse_script(&script)
+    };
+    assert!(nested(4).is_ok());
+    assert_eq!(nested(1000), Err(ScriptError::TooDeep));
+    let elifs = String::from("if a; then b") + &"; elif a; then b".repeat(1000) + "; fi";
+    assert_eq!(parse_script(&elifs), Err(ScriptError::TooDeep));
+}
+
+#[test_case]
+fn run_scripts() {
+    let mut shell = Shell::new();
+    let variable = |shell: &Shell, name| shell.variables().get(name).map(String::from);
+
+    finish(shell.execute("set L=; for X in a 'b c' $UNSET; do set \"L=$L[$X]\"; done"));
+    assert_eq!(variable(&shell, "L").as_deref(), Some("[a][b c]"));
+
+    finish(shell.execute("if false; then set R=then; else set R=else; fi"));
+    assert_eq!(variable(&shell, "R").as_deref(), Some("else"));
+    finish(shell.execute("if test a = a; then set R=then; fi"));
+    assert_eq!(variable(&shell, "R").as_deref(), Some("then"));
+    // No branch ran
+    finish(shell.execute("if false; then true; fi"));
+    assert_eq!(shell.status(), 0);
+
+    finish(shell.execute("set N=x; while test $N != xxx; do set N=x$N; done"));
+    assert_eq!(variable(&shell, "N").as_deref(), Some("xxx"));
+
+    finish(shell.execute("greet() { set \"G=$# $1 $@\"; }; greet a 'b c'"));
+    assert!(shell.function("greet").is_some());
+    assert_eq!(variable(&shell, "G").as_deref(), Some("2 a a b c"));
+    finish(shell.execute("fail() { false; }; fail"));
+    assert_eq!(shell.status(), 1);
+    // Ends at the limit
+    finish(shell.execute("recurse() { recurse; }; recurse"));
+    assert_eq!(shell.status(), 1);
+
+    // All commands write to the same output
+    let usage = capture(&mut shell, "for C in help reboot; do help $C; done");
+    assert!(usage.contains("Usage: help") && usage.contains("Usage: reboot"));
+}
+
+#[test_case]
+fn test_and_source() {
+    let mut shell = Shell::new();
+    let mut execute = |line| {
+        finish(shell.execute(line));
+        shell.status()
+    };
+    assert_eq!(execute("test 2 -lt 10"), 0);
+    assert_eq!(execute("test a = b"), 1);
+    assert_eq!(execute("test ! -e no-such-file"), 0);
+    assert_eq!(execute("test -n ''"), 1);
+    assert_eq!(execute("test 1 -x 2"), 1);
+
+    files::write("test-source", "set \"S=$1\"\nfalse # fails\n");
+    // Fails like the last command
+    assert_eq!(execute("source test-source arg"), 1);
+    assert_eq!(execute("set \"T=[$1]\""), 0);
+    assert_eq!(execute(". no-such-file"), 1);
+    assert_eq!(shell.variables().get("S"), Some("arg"));
+    assert_eq!(shell.variables().get("T"), Some("[]"));
+    files::remove("test-source");
+}
+
+#[test_case]
+fn diagnostics() {
+    let mut shell = Shell::new();
+    assert!(capture(&mut shell, "uptime").starts_with("up "));
+
+    let cpuinfo = capture(&mut shell, "cpuinfo");
+    assert!(cpuinfo.lines().any(|line| line.len() > "vendor: ".len()));
+    // Required by x86_64
+    assert!(cpuinfo.contains("fpu") && cpuinfo.contains("sse2"));
+
+    // The host bridge of QEMU
+    let lspci = capture(&mut shell, "lspci");
+    assert!(lspci.starts_with("00:00.0 Host bridge: "));
+
+    crate::kprintln!("[TEST] diagnostics");
+    assert!(capture(&mut shell, "dmesg -c").contains("[TEST] diagnostics\n"));
+    assert!(!capture(&mut shell, "dmesg").contains("[TEST] diagnostics"));
+
+    assert_eq!(capture(&mut shell, "echo a 'b  c'"), "a b  c\n");
+    assert_eq!(capture(&mut shell, "echo -n a; echo"), "a\n");
+}
+
+#[test_case]
+fn clear_terminal() {
+    use crate::vga::{primitives::ScreenPos, VgaBuffer, STDOUT};
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        crate::println!("cleared");
+        let mut shell = Shell::new();
+        assert_eq!(capture(&mut shell, "clear"), "");
+        assert_eq!(shell.status(), 0);
+        // Not redirected
+        finish(shell.execute("clear"));
+        let size = STDOUT.lock().size();
+        for col in 0..size.cols {
+            let pos = ScreenPos {
+                row: size.rows - 2,
+                col,
+            };
+            assert_eq!(STDOUT.lock().read_at(pos).ascii_character, b' ');
+        }
+    });
+}
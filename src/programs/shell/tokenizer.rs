@@ -0,0 +1,219 @@
+//! Splits a command line into words.
+//!
+//! - Words are separated by whitespace
+//! - Single quotes keep everything literally
+//! - Double quotes keep whitespace, but a backslash still escapes `"`, `\` and `$`
+//! - Outside of quotes a backslash escapes any character
+//! - `$NAME` and `${NAME}` are replaced by the value of the variable, also within double
+//!   quotes. The value isn't split into words. Unquoted, an empty value yields no word.
+//! - `$?` is the exit status, `$1`, `$#` and `$@` are the arguments of a script or function
+//! - Unquoted `&`, `|`, `>` and `>>` are operators, which separate words
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, iter::Peekable, str::Chars};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenizeError {
+    UnterminatedQuote(char),
+    /// A backslash at the end of the line
+    TrailingEscape,
+    /// `${` without `}`
+    UnterminatedBrace,
+}
+
+impl fmt::Display for TokenizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizeError::UnterminatedQuote(quote) => write!(f, "Missing closing {}", quote),
+            TokenizeError::TrailingEscape => write!(f, "Nothing to escape at the end of the line"),
+            TokenizeError::UnterminatedBrace => write!(f, "Missing closing }}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Word(String),
+    /// `&`, which runs the command before it in the background
+    Background,
+    /// `|`
+    Pipe,
+    /// `>`, which writes the output to a file
+    Redirect,
+    /// `>>`, which appends the output to a file
+    Append,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => f.write_str(word),
+            Token::Background => f.write_str("&"),
+            Token::Pipe => f.write_str("|"),
+            Token::Redirect => f.write_str(">"),
+            Token::Append => f.write_str(">>"),
+        }
+    }
+}
+
+/// Tokenizes without any variables, so all of them expand to nothing.
+///
+/// # Errors
+/// If a quote or brace isn't closed or the line ends with a backslash.
+pub fn tokenize(line: &str) -> Result<Vec<String>, TokenizeError> {
+    tokenize_with(line, &|_| None)
+}
+
+/// Tokenizes and expands variables, whose values are returned by `lookup`. Operators are
+/// returned as words.
+///
+/// # Errors
+/// If a quote or brace isn't closed or the line ends with a backslash.
+pub fn tokenize_with(
+    line: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<String>, TokenizeError> {
+    let words = lex(line, lookup)?
+        .into_iter()
+        .map(|token| match token {
+            Token::Word(word) => word,
+            operator => operator.to_string(),
+        })
+        .collect();
+    Ok(words)
+}
+
+/// Splits the line into words and operators, see `tokenize_with`.
+///
+/// # Errors
+/// If a quote or brace isn't closed or the line ends with a backslash.
+pub fn lex(
+    line: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<Token>, TokenizeError> {
+    let mut tokens = Vec::new();
+    // `None` between words, so that `""` still yields an empty word
+    let mut word: Option<String> = None;
+    let mut chars = line.chars().peekable();
+    while let Some(character) = chars.next() {
+        match character {
+            c if c.is_whitespace() => tokens.extend(word.take().map(Token::Word)),
+            '&' | '|' | '>' => {
+                tokens.extend(word.take().map(Token::Word));
+                tokens.push(match character {
+                    '&' => Token::Background,
+                    '|' => Token::Pipe,
+                    _ if chars.next_if_eq(&'>').is_some() => Token::Append,
+                    _ => Token::Redirect,
+                });
+            }
+            '\\' => {
+                let escaped = chars.next().ok_or(TokenizeError::TrailingEscape)?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            '$' => {
+                let value = variable(&mut chars, lookup)?;
+                if !value.is_empty() {
+                    word.get_or_insert_with(String::new).push_str(&value);
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(TokenizeError::UnterminatedQuote('\'')),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(TokenizeError::UnterminatedQuote('"')),
+                        },
+                        Some('$') => word.push_str(&variable(&mut chars, lookup)?),
+                        Some(c) => word.push(c),
+                        None => return Err(TokenizeError::UnterminatedQuote('"')),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    tokens.extend(word.map(Token::Word));
+    Ok(tokens)
+}
+
+/// Expands the variables in `text`, which is otherwise taken literally, e.g. for the prompt.
+///
+/// # Errors
+/// If a brace isn't closed.
+pub fn expand(
+    text: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, TokenizeError> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(character) = chars.next() {
+        match character {
+            '$' => expanded.push_str(&variable(&mut chars, lookup)?),
+            c => expanded.push(c),
+        }
+    }
+    Ok(expanded)
+}
+
+/// Whether `name` can be used for a variable
+#[must_use]
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Value of the variable after a `$`. A `$` which isn't followed by a name is kept.
+fn variable(
+    chars: &mut Peekable<Chars>,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, TokenizeError> {
+    let mut name = String::new();
+    match chars.peek() {
+        Some('{') => {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(TokenizeError::UnterminatedBrace),
+                }
+            }
+        }
+        // Exit status of the previous command, number and list of the arguments
+        Some(&special @ ('?' | '#' | '@')) => {
+            chars.next();
+            name.push(special);
+        }
+        _ => {
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                name.push(c);
+            }
+            if name.is_empty() {
+                return Ok(String::from("$"));
+            }
+        }
+    }
+    Ok(lookup(&name).unwrap_or_default())
+}
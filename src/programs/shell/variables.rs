@@ -0,0 +1,121 @@
+//! Shell variables and the environment of tasks.
+//!
+//! Exported variables form the environment. The shell hands it to every command it runs,
+//! through the task-local `ENVIRONMENT`, so that code without access to the `Shell` can
+//! read it with `var`.
+use alloc::{collections::BTreeMap, string::String};
+use core::cell::RefCell;
+
+use super::tokenizer::is_valid_name;
+
+crate::task_local! {
+    /// Exported variables of the shell, which runs the task
+    pub static ENVIRONMENT: RefCell<Environment> = RefCell::new(Environment::default());
+}
+
+/// Value of an environment variable of the current task. `None` outside of tasks.
+#[must_use]
+pub fn var(name: &str) -> Option<String> {
+    ENVIRONMENT
+        .try_with(|environment| environment.borrow().get(name).map(String::from))
+        .ok()
+        .flatten()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Environment {
+    variables: BTreeMap<String, String>,
+}
+
+impl Environment {
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    /// Sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidName;
+
+#[derive(Debug, Clone, Default)]
+struct Variable {
+    value: String,
+    exported: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    variables: BTreeMap<String, Variable>,
+}
+
+impl Variables {
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables
+            .get(name)
+            .map(|variable| variable.value.as_str())
+    }
+
+    /// Keeps whether the variable is exported.
+    ///
+    /// # Errors
+    /// If `name` isn't a valid name, see `is_valid_name`.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), InvalidName> {
+        if !is_valid_name(name) {
+            return Err(InvalidName);
+        }
+        self.variables.entry(name.into()).or_default().value = value.into();
+        Ok(())
+    }
+
+    /// Returns `false`, if there was no such variable.
+    pub fn unset(&mut self, name: &str) -> bool {
+        self.variables.remove(name).is_some()
+    }
+
+    /// Adds the variable to the environment, creating it if necessary.
+    ///
+    /// # Errors
+    /// If `name` isn't a valid name.
+    pub fn export(&mut self, name: &str) -> Result<(), InvalidName> {
+        if !is_valid_name(name) {
+            return Err(InvalidName);
+        }
+        self.variables.entry(name.into()).or_default().exported = true;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.variables
+            .get(name)
+            .map_or(false, |variable| variable.exported)
+    }
+
+    /// Sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.variables
+            .iter()
+            .map(|(name, variable)| (name.as_str(), variable.value.as_str()))
+    }
+
+    /// The exported variables
+    #[must_use]
+    pub fn environment(&self) -> Environment {
+        Environment {
+            variables: self
+                .variables
+                .iter()
+                .filter(|(_, variable)| variable.exported)
+                .map(|(name, variable)| (name.clone(), variable.value.clone()))
+                .collect(),
+        }
+    }
+}
@@ -1,23 +1,43 @@
 #![allow(dead_code, unused_variables, non_snake_case)]
 use core::fmt;
 
-use futures_util::StreamExt;
+use futures_util::{
+    future::{self, Either},
+    pin_mut, StreamExt,
+};
 use lazy_static::lazy_static;
-use spin::Mutex;
 
+use crate::concurrency::Mutex;
+use crate::power::{self, Action};
 use crate::task::timer::TickStream;
 
 lazy_static! {
     static ref STATUS_LINE: Mutex<StatusLine<12>> = Mutex::new(StatusLine::new("<CBAS>"));
 }
 
-/// Periodically updates the status line
+/// Periodically updates the status line, until the machine is shut down
 pub async fn run() {
     let mut ticks = TickStream::new(16);
-    while let Some(()) = ticks.next().await {
-        let mut status_line = STATUS_LINE.lock();
-        status_line.tick();
-        crate::hal::hlt();
+    let shutdown = power::notified();
+    pin_mut!(shutdown);
+    loop {
+        match future::select(ticks.next(), shutdown.as_mut()).await {
+            Either::Left((Some(()), _)) => {
+                let mut status_line = STATUS_LINE.lock().await;
+                status_line.tick();
+            }
+            Either::Left((None, _)) => break,
+            Either::Right((action, _)) => {
+                let mut status_line = STATUS_LINE.lock().await;
+                status_line.set_name(match action {
+                    Action::Shutdown => "<SHUTDOWN>",
+                    Action::Reboot => "<REBOOT>",
+                    Action::Halt => "<HALTED>",
+                });
+                status_line.update();
+                break;
+            }
+        }
     }
 }
 
@@ -0,0 +1,70 @@
+//! Terminals, which interactive programs run on.
+//!
+//! - `Vga` reads the PS/2 keyboard and draws into the VGA views.
+//! - `Serial` reads and writes `SERIAL1` and speaks ANSI escape sequences, e.g. to a terminal
+//!   emulator or to a script on the host, which drives QEMU through `-serial stdio`.
+//!
+//! `Terminal` holds the input and is only used by the program reading it. `Console` is the
+//! output, which is cheap to copy to everything printing on behalf of the program.
+use alloc::string::String;
+use core::fmt::{self, Write};
+
+use crate::serial;
+use crate::task::{keyboard::ScancodeStream, serial::SerialKeyStream};
+use crate::vga::{STDERR, STDOUT};
+
+/// Clears the screen and moves the cursor to the top left
+const ANSI_CLEAR: &str = "\x1b[2J\x1b[H";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Console {
+    Vga,
+    Serial,
+}
+
+impl Console {
+    /// To `STDOUT` on the VGA console
+    pub fn print(self, text: &str) {
+        match self {
+            Console::Vga => STDOUT.lock().print(text),
+            Console::Serial => serial::write_terminal(text),
+        }
+    }
+
+    /// To `STDERR` on the VGA console
+    pub fn eprint(self, text: &str) {
+        match self {
+            Console::Vga => STDERR.lock().print(text),
+            Console::Serial => serial::write_terminal(text),
+        }
+    }
+
+    /// Like `eprintln!`
+    pub fn eprintln(self, args: fmt::Arguments) {
+        let mut text = String::new();
+        let _ = writeln!(text, "{}", args);
+        self.eprint(&text);
+    }
+
+    pub fn clear(self) {
+        match self {
+            Console::Vga => STDOUT.lock().clear(),
+            Console::Serial => serial::write_terminal(ANSI_CLEAR),
+        }
+    }
+}
+
+pub enum Terminal<'a> {
+    Vga(&'a mut ScancodeStream),
+    Serial(&'a mut SerialKeyStream),
+}
+
+impl Terminal<'_> {
+    #[must_use]
+    pub fn console(&self) -> Console {
+        match self {
+            Terminal::Vga(_) => Console::Vga,
+            Terminal::Serial(_) => Console::Serial,
+        }
+    }
+}
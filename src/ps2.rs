@@ -0,0 +1,549 @@
+//! Driver for the 8042 PS/2 controller.
+//!
+//! `init` runs once during boot, before interrupts are enabled, and talks to the controller
+//! by polling. Afterwards the keyboard interrupt handler reads all bytes: acknowledgements
+//! of queued device commands, like `set_leds`, are consumed here and everything else is a
+//! scancode.
+//!
+//! The keyboard is switched to scancode set 2 and the controller translates it to set 1,
+//! which is what `task::keyboard` decodes. Bytes of the mouse on the second port raise
+//! IRQ 12 and go to `task::mouse`.
+use core::hint::spin_loop;
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+
+use crate::concurrency::IrqSpinLock;
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// The output byte is from the second port
+const STATUS_AUX_OUTPUT: u8 = 1 << 5;
+
+/// Interrupts of the first port (IRQ 1)
+pub const CONFIG_FIRST_IRQ: u8 = 1 << 0;
+/// Interrupts of the second port (IRQ 12)
+pub const CONFIG_SECOND_IRQ: u8 = 1 << 1;
+pub const CONFIG_FIRST_CLOCK_DISABLED: u8 = 1 << 4;
+pub const CONFIG_SECOND_CLOCK_DISABLED: u8 = 1 << 5;
+/// Translation of scancode set 2 to set 1 for the first port
+pub const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const COMMAND_READ_CONFIG: u8 = 0x20;
+const COMMAND_WRITE_CONFIG: u8 = 0x60;
+const COMMAND_DISABLE_SECOND: u8 = 0xa7;
+const COMMAND_ENABLE_SECOND: u8 = 0xa8;
+const COMMAND_TEST_SECOND: u8 = 0xa9;
+const COMMAND_SELF_TEST: u8 = 0xaa;
+const COMMAND_TEST_FIRST: u8 = 0xab;
+const COMMAND_DISABLE_FIRST: u8 = 0xad;
+const COMMAND_ENABLE_FIRST: u8 = 0xae;
+/// Sends the next data byte to the second port
+const COMMAND_WRITE_SECOND: u8 = 0xd4;
+/// Pulses the first output line, which is wired to the reset line of the CPU
+const COMMAND_PULSE_RESET: u8 = 0xfe;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+const DEVICE_SET_LEDS: u8 = 0xed;
+const DEVICE_SCANCODE_SET: u8 = 0xf0;
+const DEVICE_SET_TYPEMATIC: u8 = 0xf3;
+/// Same command as `DEVICE_SET_TYPEMATIC`, for mice
+const DEVICE_SET_SAMPLE_RATE: u8 = 0xf3;
+const DEVICE_GET_ID: u8 = 0xf2;
+const DEVICE_ENABLE_SCANNING: u8 = 0xf4;
+const DEVICE_DISABLE_SCANNING: u8 = 0xf5;
+const DEVICE_SET_DEFAULTS: u8 = 0xf6;
+const DEVICE_RESET: u8 = 0xff;
+
+const DEVICE_ACK: u8 = 0xfa;
+const DEVICE_RESEND: u8 = 0xfe;
+const DEVICE_RESET_PASSED: u8 = 0xaa;
+/// Id of a mouse, which reports the scroll wheel in a fourth packet byte
+const MOUSE_ID_WHEEL: u8 = 3;
+
+/// Status polls until a byte is given up on. A poll takes roughly a microsecond.
+const TIMEOUT: usize = 1_000_000;
+/// Resends of a device command, before it is dropped
+const MAX_RETRIES: u8 = 3;
+
+static CONTROLLER: IrqSpinLock<Controller> = IrqSpinLock::new(Controller::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The controller or device didn't answer in time. Also the case, if there is no
+    /// controller at all.
+    Timeout,
+    /// With the returned code
+    SelfTestFailed(u8),
+    PortTestFailed {
+        port: u8,
+        code: u8,
+    },
+    /// The device answered a command with something else than an acknowledgement
+    UnexpectedResponse {
+        command: u8,
+        response: u8,
+    },
+}
+
+/// Ports, which passed their interface test and whose device was initialised
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Ports {
+    /// Usually the keyboard
+    pub first: bool,
+    /// Usually the mouse
+    pub second: bool,
+}
+
+/// Lock indicators of the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Leds {
+    pub scroll_lock: bool,
+    pub num_lock: bool,
+    pub caps_lock: bool,
+}
+
+impl Leds {
+    #[must_use]
+    pub fn bits(self) -> u8 {
+        u8::from(self.scroll_lock)
+            | (u8::from(self.num_lock) << 1)
+            | (u8::from(self.caps_lock) << 2)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RepeatDelay {
+    Ms250 = 0,
+    Ms500 = 1,
+    Ms750 = 2,
+    Ms1000 = 3,
+}
+
+/// How fast a held key repeats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Typematic {
+    /// Until the first repetition
+    pub delay: RepeatDelay,
+    /// From 0 (30 per second) to 31 (2 per second)
+    pub rate: u8,
+}
+
+impl Typematic {
+    #[must_use]
+    pub fn bits(self) -> u8 {
+        ((self.delay as u8) << 5) | self.rate.min(31)
+    }
+}
+
+impl Default for Typematic {
+    /// 10.9 repetitions per second after half a second, the default after a reset
+    fn default() -> Self {
+        Self {
+            delay: RepeatDelay::Ms500,
+            rate: 0x0b,
+        }
+    }
+}
+
+/// Device commands of the first port, which are sent one byte after another, whenever
+/// the previous byte was acknowledged
+struct CommandQueue {
+    bytes: [u8; 16],
+    head: usize,
+    len: usize,
+    in_flight: Option<u8>,
+    retries: u8,
+}
+
+impl CommandQueue {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; 16],
+            head: 0,
+            len: 0,
+            in_flight: None,
+            retries: 0,
+        }
+    }
+
+    fn push(&mut self, command: &[u8]) -> bool {
+        if self.len + command.len() > self.bytes.len() {
+            return false;
+        }
+        for &byte in command {
+            self.bytes[(self.head + self.len) % self.bytes.len()] = byte;
+            self.len += 1;
+        }
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.bytes[self.head];
+        self.head = (self.head + 1) % self.bytes.len();
+        self.len -= 1;
+        Some(byte)
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+        self.in_flight = None;
+        self.retries = 0;
+    }
+}
+
+struct Controller {
+    data: Port<u8>,
+    status: PortReadOnly<u8>,
+    command: PortWriteOnly<u8>,
+    ports: Ports,
+    initialised: bool,
+    mouse_wheel: bool,
+    queue: CommandQueue,
+}
+
+impl Controller {
+    const fn new() -> Self {
+        Self {
+            data: Port::new(DATA_PORT),
+            status: PortReadOnly::new(STATUS_PORT),
+            command: PortWriteOnly::new(COMMAND_PORT),
+            ports: Ports {
+                first: false,
+                second: false,
+            },
+            initialised: false,
+            mouse_wheel: false,
+            queue: CommandQueue::new(),
+        }
+    }
+
+    fn status(&mut self) -> u8 {
+        unsafe { self.status.read() }
+    }
+
+    fn wait_until(&mut self, ready: impl Fn(u8) -> bool) -> Result<(), Error> {
+        for _ in 0..TIMEOUT {
+            if ready(self.status()) {
+                return Ok(());
+            }
+            spin_loop();
+        }
+        Err(Error::Timeout)
+    }
+
+    fn write_command(&mut self, command: u8) -> Result<(), Error> {
+        self.wait_until(|status| status & STATUS_INPUT_FULL == 0)?;
+        unsafe { self.command.write(command) };
+        Ok(())
+    }
+
+    fn write_data(&mut self, byte: u8) -> Result<(), Error> {
+        self.wait_until(|status| status & STATUS_INPUT_FULL == 0)?;
+        unsafe { self.data.write(byte) };
+        Ok(())
+    }
+
+    fn read_data(&mut self) -> Result<u8, Error> {
+        self.wait_until(|status| status & STATUS_OUTPUT_FULL != 0)?;
+        Ok(unsafe { self.data.read() })
+    }
+
+    /// Discards stale bytes, e.g. keys pressed during boot
+    fn flush(&mut self) {
+        while self.status() & STATUS_OUTPUT_FULL != 0 {
+            unsafe { self.data.read() };
+        }
+    }
+
+    fn read_config(&mut self) -> Result<u8, Error> {
+        self.write_command(COMMAND_READ_CONFIG)?;
+        self.read_data()
+    }
+
+    fn write_config(&mut self, config: u8) -> Result<(), Error> {
+        self.write_command(COMMAND_WRITE_CONFIG)?;
+        self.write_data(config)
+    }
+
+    /// Sends a byte to the keyboard and waits for the acknowledgement, by polling
+    fn keyboard_command(&mut self, byte: u8) -> Result<(), Error> {
+        self.device_command(false, byte)
+    }
+
+    /// Like `keyboard_command`, but for the device on the second port
+    fn mouse_command(&mut self, byte: u8) -> Result<(), Error> {
+        self.device_command(true, byte)
+    }
+
+    fn device_command(&mut self, second: bool, byte: u8) -> Result<(), Error> {
+        for _ in 0..=MAX_RETRIES {
+            if second {
+                self.write_command(COMMAND_WRITE_SECOND)?;
+            }
+            self.write_data(byte)?;
+            match self.read_data()? {
+                DEVICE_ACK => return Ok(()),
+                DEVICE_RESEND => continue,
+                response => {
+                    return Err(Error::UnexpectedResponse {
+                        command: byte,
+                        response,
+                    })
+                }
+            }
+        }
+        Err(Error::UnexpectedResponse {
+            command: byte,
+            response: DEVICE_RESEND,
+        })
+    }
+
+    fn test_port(&mut self, port: u8, command: u8) -> Result<(), Error> {
+        self.write_command(command)?;
+        match self.read_data()? {
+            PORT_TEST_PASSED => Ok(()),
+            code => Err(Error::PortTestFailed { port, code }),
+        }
+    }
+
+    fn init(&mut self) -> Result<(), Error> {
+        self.write_command(COMMAND_DISABLE_FIRST)?;
+        self.write_command(COMMAND_DISABLE_SECOND)?;
+        self.flush();
+
+        let mut config = self.read_config()?;
+        // The clock of the second port stays disabled, if there is none
+        let dual_channel = config & CONFIG_SECOND_CLOCK_DISABLED != 0;
+        config &= !(CONFIG_FIRST_IRQ | CONFIG_SECOND_IRQ);
+        config |= CONFIG_TRANSLATION;
+        self.write_config(config)?;
+
+        self.write_command(COMMAND_SELF_TEST)?;
+        match self.read_data()? {
+            SELF_TEST_PASSED => {}
+            code => return Err(Error::SelfTestFailed(code)),
+        }
+        // Some controllers reset on a self test
+        self.write_config(config)?;
+
+        let second_exists = dual_channel && {
+            self.write_command(COMMAND_ENABLE_SECOND)?;
+            let exists = self.read_config()? & CONFIG_SECOND_CLOCK_DISABLED == 0;
+            self.write_command(COMMAND_DISABLE_SECOND)?;
+            exists
+        };
+
+        let first = self.test_port(1, COMMAND_TEST_FIRST).is_ok();
+        let mut second = second_exists && self.test_port(2, COMMAND_TEST_SECOND).is_ok();
+        if first {
+            self.write_command(COMMAND_ENABLE_FIRST)?;
+            self.init_keyboard()?;
+            config |= CONFIG_FIRST_IRQ;
+        }
+        if second {
+            self.write_command(COMMAND_ENABLE_SECOND)?;
+            // A missing mouse shouldn't take the keyboard with it
+            match self.init_mouse() {
+                Ok(()) => config |= CONFIG_SECOND_IRQ,
+                Err(error) => {
+                    println!("[WARNING] PS/2 mouse: {:?}", error);
+                    self.write_command(COMMAND_DISABLE_SECOND)?;
+                    second = false;
+                }
+            }
+        }
+        self.flush();
+        self.write_config(config)?;
+        self.ports = Ports { first, second };
+        Ok(())
+    }
+
+    fn init_keyboard(&mut self) -> Result<(), Error> {
+        self.keyboard_command(DEVICE_DISABLE_SCANNING)?;
+        self.keyboard_command(DEVICE_RESET)?;
+        match self.read_data()? {
+            DEVICE_RESET_PASSED => {}
+            response => {
+                return Err(Error::UnexpectedResponse {
+                    command: DEVICE_RESET,
+                    response,
+                })
+            }
+        }
+        self.keyboard_command(DEVICE_SCANCODE_SET)?;
+        self.keyboard_command(2)?;
+        self.keyboard_command(DEVICE_SET_TYPEMATIC)?;
+        self.keyboard_command(Typematic::default().bits())?;
+        self.keyboard_command(DEVICE_SET_LEDS)?;
+        self.keyboard_command(Leds::default().bits())?;
+        self.keyboard_command(DEVICE_ENABLE_SCANNING)
+    }
+
+    /// Resets the mouse and enables the scroll wheel, if it has one
+    fn init_mouse(&mut self) -> Result<(), Error> {
+        self.mouse_command(DEVICE_RESET)?;
+        match self.read_data()? {
+            DEVICE_RESET_PASSED => {}
+            response => {
+                return Err(Error::UnexpectedResponse {
+                    command: DEVICE_RESET,
+                    response,
+                })
+            }
+        }
+        // Followed by the device id
+        self.read_data()?;
+        self.mouse_command(DEVICE_SET_DEFAULTS)?;
+        // The magic sequence of the IntelliMouse, which switches to 4 byte packets
+        for rate in [200, 100, 80] {
+            self.mouse_command(DEVICE_SET_SAMPLE_RATE)?;
+            self.mouse_command(rate)?;
+        }
+        self.mouse_command(DEVICE_GET_ID)?;
+        self.mouse_wheel = self.read_data()? == MOUSE_ID_WHEEL;
+        self.mouse_command(DEVICE_ENABLE_SCANNING)
+    }
+
+    fn send_next(&mut self) {
+        if self.queue.in_flight.is_some() {
+            return;
+        }
+        if let Some(byte) = self.queue.pop() {
+            if self.write_data(byte).is_ok() {
+                self.queue.in_flight = Some(byte);
+            } else {
+                kprintln!("[WARNING] PS/2 keyboard doesn't accept commands");
+                self.queue.clear();
+            }
+        }
+    }
+
+    fn queue_command(&mut self, command: &[u8]) {
+        if !self.ports.first {
+            return;
+        }
+        if self.queue.push(command) {
+            self.send_next();
+        } else {
+            kprintln!("[WARNING] PS/2 command queue full");
+        }
+    }
+}
+
+/// Runs the self tests and enables the keyboard, the mouse and their interrupts.
+///
+/// Must run before interrupts are enabled.
+pub fn init() {
+    println!("Initialising PS/2 controller ...");
+    let mut controller = CONTROLLER.lock();
+    match controller.init() {
+        Ok(()) => controller.initialised = true,
+        Err(error) => println!("[WARNING] PS/2 controller: {:?}", error),
+    }
+}
+
+#[must_use]
+pub fn is_initialised() -> bool {
+    CONTROLLER.lock().initialised
+}
+
+/// Resets the machine through the reset line. Returns, if the controller doesn't react.
+pub fn pulse_reset_line() {
+    let _ = CONTROLLER.lock().write_command(COMMAND_PULSE_RESET);
+}
+
+/// Ports which work. Both are disabled, if the initialisation failed.
+#[must_use]
+pub fn ports() -> Ports {
+    CONTROLLER.lock().ports
+}
+
+/// Reads the configuration byte, see the `CONFIG_*` constants.
+///
+/// # Errors
+/// If the controller doesn't answer.
+pub fn config() -> Result<u8, Error> {
+    CONTROLLER.lock().read_config()
+}
+
+/// Sets the lock indicators. The command is sent in the background.
+pub fn set_leds(leds: Leds) {
+    CONTROLLER
+        .lock()
+        .queue_command(&[DEVICE_SET_LEDS, leds.bits()]);
+}
+
+/// Sets the repeat delay and rate of held keys. The command is sent in the background.
+pub fn set_typematic(typematic: Typematic) {
+    CONTROLLER
+        .lock()
+        .queue_command(&[DEVICE_SET_TYPEMATIC, typematic.bits()]);
+}
+
+/// Whether the mouse sends 4 byte packets, with the movement of the scroll wheel
+#[must_use]
+pub fn mouse_has_wheel() -> bool {
+    CONTROLLER.lock().mouse_wheel
+}
+
+/// Whether keyboard commands still wait for their acknowledgement
+#[must_use]
+pub fn commands_pending() -> bool {
+    let controller = CONTROLLER.lock();
+    controller.queue.in_flight.is_some() || controller.queue.len != 0
+}
+
+/// Reads the byte, which caused the keyboard interrupt. Returns `None` for responses to
+/// commands and spurious interrupts, e.g. after the byte was already read by polling.
+pub(crate) fn read_keyboard_byte() -> Option<u8> {
+    let mut controller = CONTROLLER.lock();
+    // Mouse bytes are left for its own interrupt
+    if controller.status() & (STATUS_OUTPUT_FULL | STATUS_AUX_OUTPUT) != STATUS_OUTPUT_FULL {
+        return None;
+    }
+    let byte = unsafe { controller.data.read() };
+    let sent = match controller.queue.in_flight {
+        Some(sent) => sent,
+        None => return Some(byte),
+    };
+    match byte {
+        DEVICE_ACK => {
+            controller.queue.in_flight = None;
+            controller.queue.retries = 0;
+            controller.send_next();
+            None
+        }
+        DEVICE_RESEND if controller.queue.retries < MAX_RETRIES => {
+            controller.queue.retries += 1;
+            if controller.write_data(sent).is_err() {
+                controller.queue.clear();
+            }
+            None
+        }
+        DEVICE_RESEND => {
+            kprintln!("[WARNING] PS/2 keyboard rejected command {:#x}", sent);
+            controller.queue.clear();
+            None
+        }
+        _ => Some(byte),
+    }
+}
+
+/// Reads the byte, which caused the mouse interrupt
+pub(crate) fn read_mouse_byte() -> Option<u8> {
+    let mut controller = CONTROLLER.lock();
+    let ready = STATUS_OUTPUT_FULL | STATUS_AUX_OUTPUT;
+    if controller.status() & ready != ready {
+        return None;
+    }
+    Some(unsafe { controller.data.read() })
+}
@@ -1,24 +1,57 @@
+//! Serial ports.
+//!
+//! `SERIAL1` (COM1) prints to the host, e.g. the results of the tests. Received bytes raise
+//! IRQ 4, whose handler passes them on to `task::serial`.
+use crate::concurrency::IrqSpinLock;
 use lazy_static::lazy_static;
-use spin::Mutex;
 use uart_16550::SerialPort;
+use x86_64::instructions::port::Port;
+
+const COM1: u16 = 0x3F8;
+const INTERRUPT_ENABLE: u16 = COM1 + 1;
+const LINE_STATUS: u16 = COM1 + 5;
+/// Interrupt enable bit for received data
+const RECEIVED_DATA: u8 = 1;
+/// Line status bit, set while a received byte waits to be read
+const DATA_READY: u8 = 1;
 
 lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
+    pub static ref SERIAL1: IrqSpinLock<SerialPort> = {
+        let mut serial_port = unsafe { SerialPort::new(COM1) };
         serial_port.init();
-        Mutex::new(serial_port)
+        unsafe { Port::<u8>::new(INTERRUPT_ENABLE).write(RECEIVED_DATA) };
+        IrqSpinLock::new(serial_port)
     };
 }
+
+/// The next received byte of `SERIAL1`, without waiting for one
+pub(crate) fn try_receive() -> Option<u8> {
+    let mut serial = SERIAL1.lock();
+    let line_status = unsafe { Port::<u8>::new(LINE_STATUS).read() };
+    if line_status & DATA_READY == 0 {
+        return None;
+    }
+    Some(serial.receive())
+}
+
+/// Writes to `SERIAL1` like to a terminal, which needs `\r\n` to start a new line.
+pub fn write_terminal(text: &str) {
+    let mut serial = SERIAL1.lock();
+    for byte in text.bytes() {
+        if byte == b'\n' {
+            serial.send(b'\r');
+        }
+        serial.send(byte);
+    }
+}
+
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
-    interrupts::without_interrupts(|| {
-        SERIAL1
-            .lock()
-            .write_fmt(args)
-            .expect("Printing to serial failed");
-    });
+    SERIAL1
+        .lock()
+        .write_fmt(args)
+        .expect("Printing to serial failed");
 }
 
 /// Prints to the host through the serial interface.
@@ -0,0 +1,108 @@
+//! Local APIC of the executing CPU. Used to identify CPUs and to send inter-processor
+//! interrupts (IPIs). External interrupts are still routed through the 8259 PICs.
+use core::{
+    hint::spin_loop,
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use x86_64::PhysAddr;
+
+use crate::memory;
+
+/// Spurious interrupts don't need an end of interrupt, but a handler.
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+/// Wakes a CPU from `hlt`, e.g. when a task was queued for it
+pub const WAKEUP_VECTOR: u8 = 0xf0;
+/// Asks a CPU to report the task it is stuck in
+pub const WATCHDOG_VECTOR: u8 = 0xf1;
+/// Stops a CPU for good, e.g. before a shutdown
+pub const PARK_VECTOR: u8 = 0xf2;
+
+const REG_ID: u64 = 0x20;
+const REG_EOI: u64 = 0xb0;
+const REG_SPURIOUS: u64 = 0xf0;
+const REG_ICR_LOW: u64 = 0x300;
+const REG_ICR_HIGH: u64 = 0x310;
+
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+const ICR_MODE_INIT: u32 = 0b101 << 8;
+const ICR_MODE_STARTUP: u32 = 0b110 << 8;
+
+/// Virtual address of the registers, 0 until `init` is called
+static BASE: AtomicU64 = AtomicU64::new(0);
+
+/// Maps the registers. The physical address is the same for all CPUs.
+///
+/// # Panics
+/// If the registers could not be mapped.
+pub fn init(address: PhysAddr) {
+    let base = memory::map_physical_region(address, 4096).expect("Local APIC could be mapped");
+    BASE.store(base.as_u64(), Ordering::Release);
+}
+
+#[must_use]
+pub fn is_initialised() -> bool {
+    BASE.load(Ordering::Acquire) != 0
+}
+
+fn read(register: u64) -> u32 {
+    let base = BASE.load(Ordering::Acquire);
+    debug_assert_ne!(base, 0, "Local APIC is initialised");
+    unsafe { read_volatile((base + register) as *const u32) }
+}
+
+fn write(register: u64, value: u32) {
+    let base = BASE.load(Ordering::Acquire);
+    debug_assert_ne!(base, 0, "Local APIC is initialised");
+    unsafe { write_volatile((base + register) as *mut u32, value) }
+}
+
+/// Enables the local APIC of the executing CPU, so that it can receive IPIs.
+pub fn enable() {
+    write(
+        REG_SPURIOUS,
+        read(REG_SPURIOUS) | SPURIOUS_APIC_ENABLE | u32::from(SPURIOUS_VECTOR),
+    );
+}
+
+/// APIC id of the executing CPU
+#[must_use]
+pub fn id() -> u32 {
+    read(REG_ID) >> 24
+}
+
+/// Signals the end of an interrupt, which was sent by the local APIC (e.g. an IPI).
+pub fn end_of_interrupt() {
+    write(REG_EOI, 0);
+}
+
+fn send(apic_id: u32, command: u32) {
+    // An interrupt handler sending an IPI in between would overwrite the destination
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        write(REG_ICR_HIGH, apic_id << 24);
+        write(REG_ICR_LOW, command);
+        while read(REG_ICR_LOW) & ICR_DELIVERY_PENDING != 0 {
+            spin_loop();
+        }
+    });
+}
+
+/// Resets the CPU, which then waits for a startup IPI.
+pub fn send_init(apic_id: u32) {
+    send(apic_id, ICR_MODE_INIT | ICR_LEVEL_ASSERT);
+}
+
+/// Starts the CPU in real mode at the physical address `page << 12`.
+pub fn send_startup(apic_id: u32, page: u8) {
+    send(
+        apic_id,
+        ICR_MODE_STARTUP | ICR_LEVEL_ASSERT | u32::from(page),
+    );
+}
+
+/// Sends the interrupt `vector` to the CPU.
+pub fn send_ipi(apic_id: u32, vector: u8) {
+    send(apic_id, ICR_LEVEL_ASSERT | u32::from(vector));
+}
@@ -0,0 +1,179 @@
+//! Symmetric multiprocessing.
+//!
+//! The bootstrap processor (BSP) finds the other CPUs in the ACPI MADT and starts each of
+//! them with an INIT-SIPI-SIPI sequence. Every application processor (AP) gets its own
+//! stack, GDT, TSS and per-CPU area, loads the shared IDT and then runs an executor worker.
+//! Kernel threads and external interrupts stay on the BSP.
+use alloc::{boxed::Box, vec, vec::Vec};
+use bootloader::BootInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::RwLock;
+
+use crate::{acpi::Madt, gdt, memory, println, task::timer};
+
+pub mod apic;
+mod per_cpu;
+mod trampoline;
+
+pub use per_cpu::{cpu_id, current, enter_critical, exit_critical, in_task_context, PerCpu};
+
+/// CPUs are tracked in 64 bit masks, further ones are not started
+pub const MAX_CPUS: usize = 64;
+const AP_STACK_SIZE: usize = 64 * 1024;
+/// Timer ticks to wait for an AP to report back after the startup IPIs
+const AP_STARTUP_TIMEOUT_TICKS: u64 = 20;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CpuInfo {
+    /// Logical id, as returned by `cpu_id`
+    pub id: usize,
+    pub apic_id: u32,
+    pub online: bool,
+}
+
+static CPUS: RwLock<Vec<CpuInfo>> = RwLock::new(Vec::new());
+static ONLINE: AtomicUsize = AtomicUsize::new(1);
+/// Set by a starting AP once it no longer needs the trampoline
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Sets up the per-CPU area of the bootstrap processor. Must run before anything else.
+pub fn init_bsp() {
+    per_cpu::init_bsp(cpuid_apic_id());
+}
+
+/// Starts all application processors. Needs memory management and the timer interrupt.
+pub fn init(boot_info: &'static BootInfo) {
+    println!("Starting application processors ...");
+    let bsp = CpuInfo {
+        id: 0,
+        apic_id: current().apic_id(),
+        online: true,
+    };
+    let madt = match Madt::parse() {
+        Some(madt) => madt,
+        None => {
+            println!("No ACPI MADT found, running on a single CPU");
+            *CPUS.write() = vec![bsp];
+            return;
+        }
+    };
+    apic::init(madt.local_apic_address);
+    apic::enable();
+
+    let mut cpus = vec![bsp];
+    let aps: Vec<u32> = madt
+        .processors
+        .iter()
+        .map(|apic_id| u32::from(*apic_id))
+        .filter(|apic_id| *apic_id != bsp.apic_id)
+        .take(MAX_CPUS - 1)
+        .collect();
+    if !aps.is_empty() {
+        match memory::free_low_frame(&boot_info.memory_map) {
+            Some(frame) if memory::identity_map_low_frame(frame).is_ok() => {
+                let trampoline = trampoline::Trampoline::install(frame);
+                for apic_id in aps {
+                    let id = cpus.len();
+                    let online = start_ap(&trampoline, id, apic_id);
+                    if !online {
+                        println!("CPU {} (APIC id {}) did not start", id, apic_id);
+                    }
+                    cpus.push(CpuInfo {
+                        id,
+                        apic_id,
+                        online,
+                    });
+                }
+            }
+            _ => println!("No memory below 1 MiB for the AP trampoline"),
+        }
+    }
+    let total = cpus.len();
+    *CPUS.write() = cpus;
+    println!("{} of {} CPUs online", online_count(), total);
+}
+
+/// Starts a single AP and waits until it runs on its own stack.
+fn start_ap(trampoline: &trampoline::Trampoline, id: usize, apic_id: u32) -> bool {
+    let stack: &'static mut [u8] = Box::leak(vec![0; AP_STACK_SIZE].into_boxed_slice());
+    // The trampoline calls the entry point, which expects an aligned stack before the call
+    let stack_top = (stack.as_ptr() as u64 + AP_STACK_SIZE as u64) & !0xf;
+    trampoline.set_parameters(stack_top, ap_entry, id);
+    AP_STARTED.store(false, Ordering::SeqCst);
+
+    apic::send_init(apic_id);
+    wait_ticks(1);
+    for _ in 0..2 {
+        apic::send_startup(apic_id, trampoline.vector());
+        wait_ticks(1);
+        if AP_STARTED.load(Ordering::Acquire) {
+            return true;
+        }
+    }
+    let deadline = timer::ticks() + AP_STARTUP_TIMEOUT_TICKS;
+    while timer::ticks() < deadline {
+        if AP_STARTED.load(Ordering::Acquire) {
+            return true;
+        }
+        crate::hal::hlt();
+    }
+    false
+}
+
+/// Waits for at least `ticks` full timer periods.
+fn wait_ticks(ticks: u64) {
+    let deadline = timer::ticks() + ticks + 1;
+    while timer::ticks() < deadline {
+        crate::hal::hlt();
+    }
+}
+
+extern "C" fn ap_entry(id: usize) -> ! {
+    per_cpu::init_ap(id, apic::id());
+    gdt::init_ap_gdt();
+    crate::exceptions::load_idt();
+    apic::enable();
+    ONLINE.fetch_add(1, Ordering::AcqRel);
+    AP_STARTED.store(true, Ordering::Release);
+    x86_64::instructions::interrupts::enable();
+    crate::task::executor::run_worker()
+}
+
+/// APIC id of the executing CPU, without needing the local APIC to be mapped
+fn cpuid_apic_id() -> u32 {
+    #[allow(unused_unsafe)]
+    let leaf = unsafe { core::arch::x86_64::__cpuid(1) };
+    leaf.ebx >> 24
+}
+
+/// Number of running CPUs, including the bootstrap processor
+#[must_use]
+pub fn online_count() -> usize {
+    ONLINE.load(Ordering::Acquire)
+}
+
+/// APIC id of the CPU with the logical id `cpu`
+#[must_use]
+pub fn apic_id(cpu: usize) -> Option<u32> {
+    CPUS.read().get(cpu).map(|info| info.apic_id)
+}
+
+/// All CPUs found at boot
+#[must_use]
+pub fn cpus() -> Vec<CpuInfo> {
+    CPUS.read().clone()
+}
+
+/// Stops all other CPUs for good, wherever they are. A CPU holding an `IrqSpinLock` stops
+/// once it releases it.
+pub fn park_others() {
+    if !apic::is_initialised() {
+        return;
+    }
+    let current = cpu_id();
+    for cpu in cpus() {
+        if cpu.online && cpu.id != current {
+            apic::send_ipi(cpu.apic_id, apic::PARK_VECTOR);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Data private to each CPU, found through the `GS` base register.
+use alloc::boxed::Box;
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use x86_64::{registers::model_specific::GsBase, VirtAddr};
+
+/// Set once the bootstrap processor has a valid `GS` base. Before that, every access
+/// returns the values of the bootstrap processor.
+static GS_READY: AtomicBool = AtomicBool::new(false);
+
+static mut BSP_PER_CPU: PerCpu = PerCpu {
+    this: core::ptr::null(),
+    id: 0,
+    apic_id: 0,
+    critical_depth: AtomicUsize::new(0),
+};
+
+#[repr(C)]
+pub struct PerCpu {
+    /// Points to itself, so a reference can be created from `gs:0`
+    this: *const PerCpu,
+    /// Logical id, 0 is the bootstrap processor
+    id: usize,
+    apic_id: u32,
+    /// Held `IrqSpinLock`s plus running interrupt handlers
+    critical_depth: AtomicUsize,
+}
+
+impl PerCpu {
+    #[must_use]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    #[must_use]
+    pub fn apic_id(&self) -> u32 {
+        self.apic_id
+    }
+}
+
+/// Sets up the area of the bootstrap processor. Doesn't allocate, so it can run first.
+pub(crate) fn init_bsp(apic_id: u32) {
+    unsafe {
+        BSP_PER_CPU.this = core::ptr::addr_of!(BSP_PER_CPU);
+        BSP_PER_CPU.apic_id = apic_id;
+        GsBase::write(VirtAddr::from_ptr(core::ptr::addr_of!(BSP_PER_CPU)));
+    }
+    GS_READY.store(true, Ordering::Release);
+}
+
+/// Allocates and installs the area of an application processor.
+pub(crate) fn init_ap(id: usize, apic_id: u32) {
+    let area = Box::into_raw(Box::new(PerCpu {
+        this: core::ptr::null(),
+        id,
+        apic_id,
+        critical_depth: AtomicUsize::new(0),
+    }));
+    unsafe { (*area).this = area };
+    GsBase::write(VirtAddr::from_ptr(area));
+}
+
+/// Logical id of the executing CPU. The bootstrap processor has id 0.
+#[inline]
+#[must_use]
+pub fn cpu_id() -> usize {
+    if !GS_READY.load(Ordering::Acquire) {
+        return 0;
+    }
+    let id: usize;
+    unsafe {
+        asm!("mov {}, gs:[8]", out(reg) id, options(nostack, readonly, preserves_flags));
+    }
+    id
+}
+
+/// Area of the executing CPU
+#[must_use]
+pub fn current() -> &'static PerCpu {
+    if !GS_READY.load(Ordering::Acquire) {
+        return unsafe { &*core::ptr::addr_of!(BSP_PER_CPU) };
+    }
+    let this: *const PerCpu;
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) this, options(nostack, readonly, preserves_flags));
+        &*this
+    }
+}
+
+/// Called when the executing CPU takes an `IrqSpinLock` or enters an interrupt handler.
+#[inline]
+pub fn enter_critical() {
+    current().critical_depth.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Undoes `enter_critical`.
+#[inline]
+pub fn exit_critical() {
+    current().critical_depth.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// The executing CPU neither holds an `IrqSpinLock` nor runs an interrupt handler. Only
+/// then the interrupted or panicking code may be abandoned, without leaving a lock held by
+/// the CPU or an interrupt unacknowledged.
+#[must_use]
+pub fn in_task_context() -> bool {
+    current().critical_depth.load(Ordering::Relaxed) == 0
+}
@@ -0,0 +1,169 @@
+//! Startup code for application processors.
+//!
+//! A startup IPI starts the CPU in 16 bit real mode at a page below 1 MiB. The trampoline
+//! is copied there, switches to protected mode, enables paging with the page tables of the
+//! bootstrap processor, switches to long mode and calls the entry point with its own stack.
+//! The page must be identity mapped, as paging gets enabled while executing from it.
+use core::{arch::global_asm, ptr::addr_of};
+use x86_64::{registers::control::Cr3, structures::paging::PhysFrame};
+
+use crate::memory::phys_to_virt;
+
+global_asm!(
+    r#"
+.pushsection .text.cbos_ap_trampoline, "ax"
+.global cbos_ap_trampoline_start
+.global cbos_ap_trampoline_params
+.global cbos_ap_trampoline_end
+.code16
+cbos_ap_trampoline_start:
+    cli
+    cld
+    # ebx = physical address of the trampoline, which starts at cs:0
+    xorl %ebx, %ebx
+    movw %cs, %bx
+    shll $4, %ebx
+    movw %cs, %ax
+    movw %ax, %ds
+    # Patch the linear addresses, which depend on where we got copied to
+    leal (.Lap_gdt - cbos_ap_trampoline_start)(%ebx), %eax
+    movl %eax, (.Lap_gdt_ptr - cbos_ap_trampoline_start + 2)
+    leal (.Lap_protected_mode - cbos_ap_trampoline_start)(%ebx), %eax
+    movl %eax, (.Lap_protected_mode_jump - cbos_ap_trampoline_start)
+    leal (.Lap_long_mode - cbos_ap_trampoline_start)(%ebx), %eax
+    movl %eax, (.Lap_long_mode_jump - cbos_ap_trampoline_start)
+    lgdtl (.Lap_gdt_ptr - cbos_ap_trampoline_start)
+    movl %cr0, %eax
+    orl $1, %eax
+    movl %eax, %cr0
+    ljmpl *(.Lap_protected_mode_jump - cbos_ap_trampoline_start)
+
+.code32
+.Lap_protected_mode:
+    movw $0x10, %ax
+    movw %ax, %ds
+    movw %ax, %es
+    movw %ax, %ss
+    # PAE
+    movl %cr4, %eax
+    orl $(1 << 5), %eax
+    movl %eax, %cr4
+    movl (cbos_ap_trampoline_params - cbos_ap_trampoline_start)(%ebx), %eax
+    movl %eax, %cr3
+    # EFER: long mode and no-execute
+    movl $0xc0000080, %ecx
+    rdmsr
+    orl $((1 << 8) | (1 << 11)), %eax
+    wrmsr
+    # Paging and write protection
+    movl %cr0, %eax
+    orl $((1 << 31) | (1 << 16)), %eax
+    movl %eax, %cr0
+    ljmpl *(.Lap_long_mode_jump - cbos_ap_trampoline_start)(%ebx)
+
+.code64
+.Lap_long_mode:
+    xorw %ax, %ax
+    movw %ax, %ds
+    movw %ax, %es
+    movw %ax, %ss
+    movq (cbos_ap_trampoline_params - cbos_ap_trampoline_start + 8)(%rbx), %rsp
+    movq (cbos_ap_trampoline_params - cbos_ap_trampoline_start + 24)(%rbx), %rdi
+    movq (cbos_ap_trampoline_params - cbos_ap_trampoline_start + 16)(%rbx), %rax
+    callq *%rax
+.Lap_halt:
+    hlt
+    jmp .Lap_halt
+
+.balign 8
+.Lap_gdt:
+    .quad 0
+    .quad 0x00cf9a000000ffff
+    .quad 0x00cf92000000ffff
+    .quad 0x00af9a000000ffff
+.Lap_gdt_ptr:
+    .word .Lap_gdt_ptr - .Lap_gdt - 1
+    .long 0
+.Lap_protected_mode_jump:
+    .long 0
+    .word 0x08
+.Lap_long_mode_jump:
+    .long 0
+    .word 0x18
+.balign 8
+cbos_ap_trampoline_params:
+    .quad 0, 0, 0, 0
+cbos_ap_trampoline_end:
+.popsection
+"#,
+    options(att_syntax)
+);
+
+extern "C" {
+    static cbos_ap_trampoline_start: u8;
+    static cbos_ap_trampoline_params: u8;
+    static cbos_ap_trampoline_end: u8;
+}
+
+/// Layout of `cbos_ap_trampoline_params`
+#[repr(C)]
+struct Parameters {
+    cr3: u64,
+    stack_top: u64,
+    entry: u64,
+    cpu_id: u64,
+}
+
+pub struct Trampoline {
+    frame: PhysFrame,
+}
+
+impl Trampoline {
+    /// Copies the trampoline to `frame`, which must be identity mapped and below 1 MiB.
+    pub fn install(frame: PhysFrame) -> Self {
+        let (start, end) = unsafe {
+            (
+                addr_of!(cbos_ap_trampoline_start) as usize,
+                addr_of!(cbos_ap_trampoline_end) as usize,
+            )
+        };
+        assert!(end - start <= 4096, "AP trampoline fits into a page");
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                start as *const u8,
+                phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(),
+                end - start,
+            );
+        }
+        Self { frame }
+    }
+
+    /// Page number, which is sent with the startup IPI
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn vector(&self) -> u8 {
+        (self.frame.start_address().as_u64() >> 12) as u8
+    }
+
+    /// Sets what the next started CPU runs. `entry` gets `cpu_id` as argument.
+    ///
+    /// # Panics
+    /// If the level 4 page table is above 4 GiB, as it is loaded in protected mode.
+    pub fn set_parameters(&self, stack_top: u64, entry: extern "C" fn(usize) -> !, cpu_id: usize) {
+        let cr3 = Cr3::read().0.start_address().as_u64();
+        assert!(cr3 < 1 << 32, "Page table is reachable from protected mode");
+        let offset = unsafe {
+            addr_of!(cbos_ap_trampoline_params) as usize
+                - addr_of!(cbos_ap_trampoline_start) as usize
+        };
+        let params = (phys_to_virt(self.frame.start_address()) + offset).as_mut_ptr::<Parameters>();
+        unsafe {
+            params.write_volatile(Parameters {
+                cr3,
+                stack_top,
+                entry: entry as usize as u64,
+                cpu_id: cpu_id as u64,
+            });
+        }
+    }
+}
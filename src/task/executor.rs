@@ -1,170 +1,653 @@
-//! TODO:
-//! - Either add lifetime to Spawner or make it otherwise aware that the
-//!   executor is no longer alive
-//! - Add threading and work stealing
-use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
-use conquer_once::spin::OnceCell;
-use core::task::{Context, Poll, Waker};
-use crossbeam_queue::ArrayQueue;
+//! Work-stealing executor, which runs on every CPU.
+//!
+//! Every CPU has a local run queue. Woken tasks go to the queue of the CPU which polled
+//! them last, spawned tasks to a shared injector queue. An idle CPU first takes tasks from
+//! the injector, then steals half of the queue of a peer, and finally sleeps in `hlt` until
+//! an IPI signals new work.
+//!
+//! There can be multiple executors, e.g. one for drivers and one for user programs. Named
+//! executors and the global one are run by all application processors, tasks are spawned
+//! onto them with `spawn_on` and `spawn`. `Executor::run` additionally runs an executor on
+//! the calling CPU. Spawners only hold a weak reference and fail once their executor is
+//! dropped.
+//!
+//! Spawning returns a `JoinHandle`, which resolves once the task finished. With panic
+//! isolation enabled, a panicking task fails with `JoinError::Panicked` instead of halting
+//! the kernel.
+use alloc::{
+    borrow::Cow,
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    task::Wake,
+    vec::Vec,
+};
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+};
+use crossbeam_queue::{ArrayQueue, SegQueue};
+use futures_util::task::AtomicWaker;
 use spin::{Mutex, RwLock};
+use x86_64::instructions::interrupts::without_interrupts;
 
 use super::{Task, TaskId};
+use crate::smp::{self, MAX_CPUS};
 
+mod abort;
+pub mod watchdog;
+
+pub use abort::{abort_panicking_task, panic_isolation, set_panic_isolation};
+pub(super) use abort::{current_slot as current_poller_slot, MAX_POLLERS};
+
+/// Capacity of every local run queue. Further tasks overflow into the injector queue.
 const MAX_AMOUNT_OF_QUEUED_TASKS: usize = 128;
 
-pub static _GLOBAL_SPAWNER: OnceCell<Mutex<Spawner>> = OnceCell::uninit();
+/// Target of `spawn`
+static GLOBAL_SPAWNER: RwLock<Option<Spawner>> = RwLock::new(None);
+/// Executors run by the application processors, i.e. the named and the global one
+static REGISTRY: RwLock<Vec<Weak<Shared>>> = RwLock::new(Vec::new());
+/// Incremented on every change of the registry, so workers know when to reload it
+static REGISTRY_GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// CPUs waiting in `hlt` for new tasks
+static SLEEPING: AtomicU64 = AtomicU64::new(0);
 
-pub struct Executor {
-    /// Fast search and continuation of a task
-    tasks: Arc<RwLock<BTreeMap<TaskId, Task>>>,
-    /// Shared with wakers, which push their task onto it
-    task_queue: Arc<ArrayQueue<TaskId>>,
-    /// Allows reuse of wakers and ?
-    waker_cache: BTreeMap<TaskId, Waker>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// There is no global executor or none with the given name
+    NotFound,
+    /// The executor was dropped
+    Gone,
+}
+
+/// Why a task did not run to completion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError {
+    /// The task panicked, with the panic message
+    Panicked(String),
+    /// Killed by the watchdog
+    Killed,
+    /// The executor was dropped
+    Cancelled,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panicked(message) => write!(f, "panicked: {}", message),
+            JoinError::Killed => f.write_str("killed by the watchdog"),
+            JoinError::Cancelled => f.write_str("cancelled"),
+        }
+    }
 }
 
-/// A global spawner must be installed beforehand with `Executor::set_global_spawner`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterError {
+    /// A living executor already has this name
+    NameTaken,
+    /// A living executor is already the global one
+    GlobalAlreadySet,
+}
+
+/// Spawns onto the executor installed with `Executor::set_global_spawner`.
+///
+/// # Errors
+/// If there is no global executor or it was dropped.
+pub fn spawn(task: Task) -> Result<JoinHandle, SpawnError> {
+    GLOBAL_SPAWNER
+        .read()
+        .as_ref()
+        .ok_or(SpawnError::NotFound)?
+        .spawn(task)
+}
+
+/// Spawns onto the executor with the given name.
 ///
-/// # Panics
-/// If the global spawner is not set.
-pub fn spawn(task: Task) {
-    _GLOBAL_SPAWNER
-        .try_get()
-        .expect("Global `Spawner` is set")
-        .lock()
-        .spawn(task);
+/// # Errors
+/// If there is no living executor with this name.
+pub fn spawn_on(executor: &str, task: Task) -> Result<JoinHandle, SpawnError> {
+    let shared = registered()
+        .into_iter()
+        .find(|shared| shared.name == Some(executor))
+        .ok_or(SpawnError::NotFound)?;
+    Spawner {
+        shared: Arc::downgrade(&shared),
+    }
+    .spawn(task)
+}
+
+/// Living executors of the registry
+fn registered() -> Vec<Arc<Shared>> {
+    REGISTRY
+        .read()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter(|shared| shared.alive.load(Ordering::Acquire))
+        .collect()
+}
+
+/// Adds or removes executors. Wakes all sleeping CPUs, so they pick up the change.
+fn modify_registry(f: impl FnOnce(&mut Vec<Weak<Shared>>)) {
+    without_interrupts(|| {
+        let mut registry = REGISTRY.write();
+        registry.retain(|shared| shared.strong_count() > 0);
+        f(&mut registry);
+    });
+    REGISTRY_GENERATION.fetch_add(1, Ordering::SeqCst);
+    wake_sleeping(SLEEPING.load(Ordering::SeqCst) & !(1 << smp::cpu_id()));
+}
+
+/// Sends a wakeup IPI to every CPU in the mask.
+fn wake_sleeping(mut cpus: u64) {
+    if !smp::apic::is_initialised() {
+        return;
+    }
+    while cpus != 0 {
+        let cpu = cpus.trailing_zeros() as usize;
+        cpus &= cpus - 1;
+        if let Some(apic_id) = smp::apic_id(cpu) {
+            smp::apic::send_ipi(apic_id, smp::apic::WAKEUP_VECTOR);
+        }
+    }
+}
+
+/// Runs the registered executors on an application processor.
+pub fn run_worker() -> ! {
+    let mut generation = usize::MAX;
+    let mut workers: Vec<Worker> = Vec::new();
+    loop {
+        let current = REGISTRY_GENERATION.load(Ordering::SeqCst);
+        if current != generation {
+            generation = current;
+            // Drop the old workers first, as they unregister this CPU
+            workers.clear();
+            workers.extend(registered().into_iter().map(Worker::new));
+        }
+        for worker in &workers {
+            worker.run_ready_tasks();
+        }
+        sleep_if_idle(&workers, generation);
+    }
+}
+
+/// Waits for an IPI or interrupt, unless one of the executors has queued tasks or the
+/// registry changed.
+fn sleep_if_idle(workers: &[Worker], generation: usize) {
+    use x86_64::instructions::interrupts::{self, enable_and_hlt};
+    let bit = 1 << smp::cpu_id();
+    interrupts::disable();
+    SLEEPING.fetch_or(bit, Ordering::SeqCst);
+    // Check if a interrupt or another CPU queues a new task inbetween. Wakers check
+    // `SLEEPING` after queueing, so either we see the task or they send an IPI.
+    if workers
+        .iter()
+        .any(|worker| worker.shared.has_queued_tasks())
+        || REGISTRY_GENERATION.load(Ordering::SeqCst) != generation
+    {
+        interrupts::enable();
+    } else {
+        enable_and_hlt();
+    }
+    SLEEPING.fetch_and(!bit, Ordering::SeqCst);
+}
+
+/// Scheduling state of a task, which makes sure that it is queued at most once and polled
+/// by a single CPU at a time.
+mod state {
+    pub const IDLE: u8 = 0;
+    pub const SCHEDULED: u8 = 1;
+    pub const RUNNING: u8 = 2;
+    /// Woken while running, so it must be polled again
+    pub const NOTIFIED: u8 = 3;
+    pub const DONE: u8 = 4;
+}
+
+struct TaskCell {
+    id: TaskId,
+    name: Cow<'static, str>,
+    state: AtomicU8,
+    /// CPU, which polled the task last. Woken tasks are queued there.
+    home: AtomicUsize,
+    task: Mutex<Option<Task>>,
+    executor: Weak<Shared>,
+    /// Set once the task finished or failed
+    outcome: Mutex<Option<Result<(), JoinError>>>,
+    join_waker: AtomicWaker,
+}
+
+impl TaskCell {
+    /// Removes the task from its executor and notifies the `JoinHandle`. The future must
+    /// already be dropped or abandoned.
+    fn finish(&self, outcome: Result<(), JoinError>) {
+        self.state.store(state::DONE, Ordering::Release);
+        if let Some(shared) = self.executor.upgrade() {
+            shared.tasks.write().remove(&self.id);
+        }
+        *self.outcome.lock() = Some(outcome);
+        self.join_waker.wake();
+    }
+}
+
+impl Wake for TaskCell {
+    #[inline]
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut current = self.state.load(Ordering::Acquire);
+        loop {
+            let next = match current {
+                state::IDLE => state::SCHEDULED,
+                state::RUNNING => state::NOTIFIED,
+                _ => return,
+            };
+            match self.state.compare_exchange_weak(
+                current,
+                next,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+        // A running task gets queued again by its worker
+        if current == state::IDLE {
+            if let Some(shared) = self.executor.upgrade() {
+                shared.schedule(self.clone());
+            }
+        }
+    }
+}
+
+struct Shared {
+    name: Option<&'static str>,
+    /// Cleared once the `Executor` is dropped
+    alive: AtomicBool,
+    /// All unfinished tasks
+    tasks: RwLock<BTreeMap<TaskId, Arc<TaskCell>>>,
+    /// Newly spawned tasks and overflow of the local queues
+    injector: SegQueue<Arc<TaskCell>>,
+    /// Indexed by CPU id
+    local_queues: Vec<ArrayQueue<Arc<TaskCell>>>,
+    /// CPUs running a worker of this executor
+    workers: AtomicU64,
+}
+
+impl Shared {
+    fn new(name: Option<&'static str>) -> Arc<Self> {
+        let cpus = smp::cpus().len().clamp(1, MAX_CPUS);
+        Arc::new(Self {
+            name,
+            alive: AtomicBool::new(true),
+            tasks: RwLock::new(BTreeMap::new()),
+            injector: SegQueue::new(),
+            local_queues: (0..cpus)
+                .map(|_| ArrayQueue::new(MAX_AMOUNT_OF_QUEUED_TASKS))
+                .collect(),
+            workers: AtomicU64::new(0),
+        })
+    }
+
+    fn is_worker(&self, cpu: usize) -> bool {
+        cpu < MAX_CPUS && self.workers.load(Ordering::SeqCst) & (1 << cpu) != 0
+    }
+
+    /// Queues a task on its home CPU and makes sure that some worker will run it.
+    fn schedule(&self, cell: Arc<TaskCell>) {
+        let home = cell.home.load(Ordering::Relaxed);
+        match self.local_queues.get(home).filter(|_| self.is_worker(home)) {
+            Some(queue) => {
+                if let Err(cell) = queue.push(cell) {
+                    self.injector.push(cell);
+                }
+            }
+            None => self.injector.push(cell),
+        }
+        self.wake_worker(home);
+    }
+
+    /// Sends a wakeup IPI to `preferred` or, if it is busy, to any other sleeping worker,
+    /// which can then steal the task.
+    fn wake_worker(&self, preferred: usize) {
+        let sleeping = SLEEPING.load(Ordering::SeqCst)
+            & self.workers.load(Ordering::SeqCst)
+            & !(1 << smp::cpu_id());
+        if sleeping == 0 {
+            return;
+        }
+        if preferred < MAX_CPUS && sleeping & (1 << preferred) != 0 {
+            wake_sleeping(1 << preferred);
+        } else {
+            wake_sleeping(1 << sleeping.trailing_zeros());
+        }
+    }
+
+    fn has_queued_tasks(&self) -> bool {
+        !self.injector.is_empty() || self.local_queues.iter().any(|queue| !queue.is_empty())
+    }
+}
+
+/// Runs tasks of an executor on the executing CPU
+struct Worker {
+    shared: Arc<Shared>,
+    cpu: usize,
+}
+
+impl Worker {
+    fn new(shared: Arc<Shared>) -> Self {
+        let cpu = smp::cpu_id();
+        assert!(cpu < MAX_CPUS, "At most {} CPUs are supported", MAX_CPUS);
+        shared.workers.fetch_or(1 << cpu, Ordering::SeqCst);
+        Self { shared, cpu }
+    }
+
+    fn local_queue(&self) -> Option<&ArrayQueue<Arc<TaskCell>>> {
+        self.shared.local_queues.get(self.cpu)
+    }
+
+    /// Local queue first, then the injector and at last the queues of other CPUs.
+    fn next_task(&self) -> Option<Arc<TaskCell>> {
+        if let Some(cell) = self.local_queue().and_then(ArrayQueue::pop) {
+            return Some(cell);
+        }
+        if let Some(cell) = self.shared.injector.pop() {
+            return Some(cell);
+        }
+        self.steal()
+    }
+
+    /// Takes half of the tasks of the first non-empty peer queue.
+    fn steal(&self) -> Option<Arc<TaskCell>> {
+        let queues = &self.shared.local_queues;
+        let peers = (1..queues.len()).map(|offset| (self.cpu + offset) % queues.len());
+        for peer in peers {
+            let victim = &queues[peer];
+            let first = match victim.pop() {
+                Some(cell) => cell,
+                None => continue,
+            };
+            if let Some(local) = self.local_queue() {
+                for _ in 0..victim.len() / 2 {
+                    match victim.pop() {
+                        Some(cell) => {
+                            if let Err(cell) = local.push(cell) {
+                                self.shared.injector.push(cell);
+                            }
+                        }
+                        None => break,
+                    }
+                }
+            }
+            return Some(first);
+        }
+        None
+    }
+
+    fn run_ready_tasks(&self) {
+        while let Some(cell) = self.next_task() {
+            self.run_task(&cell);
+        }
+    }
+
+    fn run_task(&self, cell: &Arc<TaskCell>) {
+        if cell
+            .state
+            .compare_exchange(
+                state::SCHEDULED,
+                state::RUNNING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            // Cancelled while queued
+            return;
+        }
+        cell.home.store(self.cpu, Ordering::Relaxed);
+        let waker = Waker::from(cell.clone());
+        let mut context = Context::from_waker(&waker);
+        let finished = {
+            let _running = abort::enter(cell);
+            match cell.task.lock().as_mut() {
+                Some(task) => task.poll(&mut context).is_ready(),
+                None => true,
+            }
+        };
+        if finished {
+            // Drop the future, even if wakers keep the cell alive. Without a future it was
+            // cancelled in the meantime, which already finished it.
+            if cell.task.lock().take().is_some() {
+                cell.finish(Ok(()));
+            }
+            return;
+        }
+        let woken = match cell.state.compare_exchange(
+            state::RUNNING,
+            state::IDLE,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => false,
+            // Woken while it was polled, unless it was cancelled to DONE. That can still
+            // happen before it is queued again.
+            Err(actual) => {
+                actual == state::NOTIFIED
+                    && cell
+                        .state
+                        .compare_exchange(
+                            state::NOTIFIED,
+                            state::SCHEDULED,
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                        )
+                        .is_ok()
+            }
+        };
+        if woken {
+            self.shared.schedule(cell.clone());
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.shared
+            .workers
+            .fetch_and(!(1 << self.cpu), Ordering::SeqCst);
+    }
+}
+
+/// Owns the tasks. Dropping it drops all unfinished tasks and makes its spawners fail.
+pub struct Executor {
+    shared: Arc<Shared>,
 }
 
 impl Executor {
+    /// Creates an executor, which only runs where `run` is called, until it is made the
+    /// global one.
     #[allow(clippy::new_without_default)]
     #[must_use]
     pub fn new() -> Self {
         Executor {
-            tasks: Arc::new(RwLock::new(BTreeMap::new())),
-            task_queue: Arc::new(ArrayQueue::new(MAX_AMOUNT_OF_QUEUED_TASKS)),
-            waker_cache: BTreeMap::new(),
+            shared: Shared::new(None),
+        }
+    }
+
+    /// Creates an executor, which is run by all application processors and can be found
+    /// by `spawn_on`.
+    ///
+    /// # Errors
+    /// If a living executor already has this name.
+    pub fn named(name: &'static str) -> Result<Self, RegisterError> {
+        if registered().iter().any(|shared| shared.name == Some(name)) {
+            return Err(RegisterError::NameTaken);
         }
+        let executor = Executor {
+            shared: Shared::new(Some(name)),
+        };
+        let shared = Arc::downgrade(&executor.shared);
+        modify_registry(|registry| registry.push(shared));
+        Ok(executor)
+    }
+
+    #[must_use]
+    pub fn name(&self) -> Option<&'static str> {
+        self.shared.name
+    }
+
+    /// Number of unfinished tasks
+    #[must_use]
+    pub fn task_count(&self) -> usize {
+        self.shared.tasks.read().len()
     }
 
     #[must_use]
     pub fn get_spawner(&self) -> Spawner {
         Spawner {
-            tasks: self.tasks.clone(),
-            task_queue: self.task_queue.clone(),
+            shared: Arc::downgrade(&self.shared),
         }
     }
 
+    /// Makes this the target of `spawn`. Application processors start running its tasks.
+    ///
     /// # Errors
-    /// If the global spawner is already set.
-    pub fn set_global_spawner(&mut self) -> Result<(), conquer_once::TryInitError> {
-        _GLOBAL_SPAWNER.try_init_once(|| Mutex::new(self.get_spawner()))
-    }
-
-    /// Runs until all queued tasks finish.
-    pub fn run(&mut self) {
-        while !self.tasks.read().is_empty() {
-            self.run_ready_task();
-            self.sleep_if_idle();
+    /// If another living executor is the global one.
+    pub fn set_global_spawner(&mut self) -> Result<(), RegisterError> {
+        without_interrupts(|| {
+            let mut global = GLOBAL_SPAWNER.write();
+            if global.as_ref().map_or(false, Spawner::is_alive) {
+                return Err(RegisterError::GlobalAlreadySet);
+            }
+            *global = Some(self.get_spawner());
+            Ok(())
+        })?;
+        if self.shared.name.is_none() {
+            let shared = Arc::downgrade(&self.shared);
+            modify_registry(|registry| registry.push(shared));
         }
+        Ok(())
     }
 
-    fn sleep_if_idle(&self) {
-        use x86_64::instructions::interrupts::{self, enable_and_hlt};
-        interrupts::disable();
-        // Check if a interrupt queues a new task inbetween
-        if self.task_queue.is_empty() {
-            enable_and_hlt();
-        } else {
-            interrupts::enable();
+    /// Runs on the calling CPU until all spawned tasks finish. Application processors
+    /// help, if the executor is named or the global one.
+    pub fn run(&mut self) {
+        let workers = [Worker::new(self.shared.clone())];
+        while !self.shared.tasks.read().is_empty() {
+            workers[0].run_ready_tasks();
+            sleep_if_idle(&workers, REGISTRY_GENERATION.load(Ordering::SeqCst));
         }
     }
+}
 
-    fn run_ready_task(&mut self) {
-        let Self {
-            tasks,
-            task_queue,
-            waker_cache,
-        } = self;
-
-        while let Some(id) = task_queue.pop() {
-            let mut tasks = tasks.write();
-            let task = match tasks.get_mut(&id) {
-                Some(task) => task,
-                None => continue, // Task finished already
-            };
-            let waker = waker_cache
-                .entry(id)
-                .or_insert_with(|| TaskWaker::new(id, task_queue.clone()));
-            let mut context = Context::from_waker(waker);
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    tasks.remove(&id);
-                    waker_cache.remove(&id);
-                }
-                Poll::Pending => {}
+impl Drop for Executor {
+    fn drop(&mut self) {
+        self.shared.alive.store(false, Ordering::Release);
+        let this = Arc::as_ptr(&self.shared);
+        without_interrupts(|| {
+            let mut global = GLOBAL_SPAWNER.write();
+            if global
+                .as_ref()
+                .map_or(false, |spawner| spawner.shared.as_ptr() == this)
+            {
+                *global = None;
             }
+        });
+        modify_registry(|registry| registry.retain(|shared| shared.as_ptr() != this));
+        // Workers on other CPUs can still hold the shared state for a moment
+        let tasks = core::mem::take(&mut *self.shared.tasks.write());
+        for cell in tasks.values() {
+            cell.state.store(state::DONE, Ordering::Release);
+            cell.task.lock().take();
+            cell.finish(Err(JoinError::Cancelled));
         }
     }
 }
 
+/// Spawns tasks onto an executor, as long as it is alive.
 #[derive(Clone)]
 pub struct Spawner {
-    tasks: Arc<RwLock<BTreeMap<TaskId, Task>>>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    shared: Weak<Shared>,
 }
 
-// Requires tasks
 impl Spawner {
+    /// Queues the task on the injector queue, from where any idle worker can take it.
+    ///
+    /// # Errors
+    /// If the executor was dropped.
+    ///
     /// # Panics
-    /// Panics if the amount of queued tasks exceeds `MAX_AMOUNT_OF_QUEUED_TASKS`
-    pub fn spawn(&mut self, task: Task) {
+    /// If a task with the same id was already spawned.
+    pub fn spawn(&self, task: Task) -> Result<JoinHandle, SpawnError> {
+        let shared = self
+            .shared
+            .upgrade()
+            .filter(|shared| shared.alive.load(Ordering::Acquire))
+            .ok_or(SpawnError::Gone)?;
         let id = task.id;
+        let cell = Arc::new(TaskCell {
+            id,
+            name: task.name.clone(),
+            state: AtomicU8::new(state::SCHEDULED),
+            home: AtomicUsize::new(smp::cpu_id()),
+            task: Mutex::new(Some(task)),
+            executor: self.shared.clone(),
+            outcome: Mutex::new(None),
+            join_waker: AtomicWaker::new(),
+        });
         assert!(
-            self.tasks.write().insert(id, task).is_none(),
+            shared.tasks.write().insert(id, cell.clone()).is_none(),
             "Task with {:?} already exists",
             id
         );
-        assert!(
-            self.task_queue.push(id).is_ok(),
-            "Max amount of queued tasks reached: {}",
-            MAX_AMOUNT_OF_QUEUED_TASKS
-        );
+        shared.injector.push(cell.clone());
+        shared.wake_worker(smp::cpu_id());
+        Ok(JoinHandle { cell })
+    }
+
+    /// Whether the executor still accepts tasks
+    #[must_use]
+    pub fn is_alive(&self) -> bool {
+        self.shared
+            .upgrade()
+            .map_or(false, |shared| shared.alive.load(Ordering::Acquire))
     }
 }
 
-struct TaskWaker {
-    task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+/// Resolves once the task finished. Dropping it detaches the task.
+pub struct JoinHandle {
+    cell: Arc<TaskCell>,
 }
 
-impl TaskWaker {
-    #[allow(clippy::new_ret_no_self)]
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(Self {
-            task_id,
-            task_queue,
-        }))
+impl JoinHandle {
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.cell.outcome.lock().is_some()
     }
 
-    /// # Panics
-    /// Panics if the amount of queued tasks exceeds `MAX_AMOUNT_OF_QUEUED_TASKS`
-    fn wake_task(&self) {
-        assert!(
-            self.task_queue.push(self.task_id).is_ok(),
-            "Max amount of queued tasks reached: {}",
-            MAX_AMOUNT_OF_QUEUED_TASKS
-        );
+    /// Outcome of the task, if it finished
+    #[must_use]
+    pub fn try_join(&self) -> Option<Result<(), JoinError>> {
+        self.cell.outcome.lock().clone()
     }
 }
 
-impl Wake for TaskWaker {
-    #[inline]
-    fn wake(self: Arc<Self>) {
-        self.wake_task();
-    }
+impl Future for JoinHandle {
+    type Output = Result<(), JoinError>;
 
-    #[inline]
-    fn wake_by_ref(self: &Arc<Self>) {
-        self.wake_task();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if let Some(outcome) = self.try_join() {
+            return Poll::Ready(outcome);
+        }
+        self.cell.join_waker.register(cx.waker());
+        // Check again, as the task could have finished before the waker was registered
+        match self.try_join() {
+            Some(outcome) => Poll::Ready(outcome),
+            None => Poll::Pending,
+        }
     }
 }
@@ -0,0 +1,252 @@
+//! Abandoning the task, which is polled by the executing thread.
+//!
+//! The kernel aborts on panic and can't unwind, so a task is stopped by leaving its poll
+//! behind: the poller continues on its restart stack in `restart_worker`, which removes the
+//! task from its executor and runs the worker loop again. A poller is a CPU or, on the
+//! bootstrap processor, a kernel thread. Its restart stack is allocated on its first poll
+//! with panic isolation or `watchdog::Mode::Kill` enabled and reused by every restart.
+//!
+//! Only code in task context is abandoned, i.e. neither in an interrupt handler nor while
+//! holding an `IrqSpinLock`. Everything else the task locked stays locked, and its future
+//! is leaked. A poller inside `Executor::run` doesn't return from it afterwards, as that
+//! stack is abandoned as well.
+use alloc::{string::ToString, sync::Arc, vec};
+use core::{
+    arch::asm,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use x86_64::{structures::idt::InterruptStackFrame, VirtAddr};
+
+use super::{watchdog, JoinError, TaskCell};
+use crate::task::local;
+use crate::{
+    concurrency::{
+        thread::{self, ThreadId},
+        IrqSpinLock,
+    },
+    serial_println,
+    smp::{self, MAX_CPUS},
+};
+
+const RESTART_STACK_SIZE: usize = 64 * 1024;
+/// Every CPU plus kernel threads, which poll tasks on the bootstrap processor
+pub(in crate::task) const MAX_POLLERS: usize = MAX_CPUS + 16;
+
+static PANIC_ISOLATION: AtomicBool = AtomicBool::new(false);
+
+/// Polls tasks. Threads only run on the bootstrap processor, so every other CPU is a single
+/// poller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Poller {
+    pub(super) cpu: usize,
+    /// Only on the bootstrap processor
+    pub(super) thread: Option<ThreadId>,
+}
+
+impl Poller {
+    /// The executing one. In an interrupt handler, the interrupted one.
+    pub(super) fn current() -> Self {
+        let cpu = smp::cpu_id();
+        Self {
+            cpu,
+            thread: (cpu == 0).then(thread::current_id),
+        }
+    }
+}
+
+pub(super) struct Running {
+    pub(super) cell: Arc<TaskCell>,
+    /// Timer ticks, while the poller was running, counted by the watchdog
+    pub(super) ticks: u64,
+    /// Set by the watchdog, so a stall is only reported once
+    pub(super) reported: bool,
+}
+
+pub(super) struct Slot {
+    /// Bound on the first poll and never released, as thread ids aren't reused either
+    pub(super) poller: Option<Poller>,
+    pub(super) running: Option<Running>,
+    /// Taken by `restart_worker`
+    abandoned: Option<(Arc<TaskCell>, JoinError)>,
+    /// Aligned top of the stack for `restart_worker`, 0 if not allocated yet
+    restart_stack: u64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: IrqSpinLock<Slot> = IrqSpinLock::new(Slot {
+    poller: None,
+    running: None,
+    abandoned: None,
+    restart_stack: 0,
+});
+/// Task polled by each poller. Application processors use the slot of their CPU id, the
+/// threads of the bootstrap processor slot 0 and the slots after the CPUs.
+pub(super) static POLLERS: [IrqSpinLock<Slot>; MAX_POLLERS] = [EMPTY_SLOT; MAX_POLLERS];
+
+/// Catch panics of tasks, instead of halting the kernel. The task fails with
+/// `JoinError::Panicked` and the rest of the system keeps running. Off by default, so
+/// tests still fail on the first panic.
+pub fn set_panic_isolation(enabled: bool) {
+    PANIC_ISOLATION.store(enabled, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn panic_isolation() -> bool {
+    PANIC_ISOLATION.load(Ordering::Relaxed)
+}
+
+/// Slots, which `poller` can use
+fn candidates(poller: Poller) -> impl Iterator<Item = usize> {
+    let (first, others) = if poller.cpu == 0 {
+        (0, MAX_CPUS..MAX_POLLERS)
+    } else {
+        (poller.cpu, 0..0)
+    };
+    core::iter::once(first).chain(others)
+}
+
+/// Slot bound to `poller`
+pub(super) fn find(poller: Poller) -> Option<usize> {
+    candidates(poller).find(|&index| POLLERS[index].lock().poller == Some(poller))
+}
+
+/// Slot of the executing poller, or of the interrupted one in an interrupt handler. `None`
+/// if it never polled a task.
+pub(in crate::task) fn current_slot() -> Option<usize> {
+    find(Poller::current())
+}
+
+/// Slot bound to `poller`, which binds a free one on the first call. `None` if all slots
+/// are taken by other threads.
+fn find_or_bind(poller: Poller) -> Option<usize> {
+    find(poller).or_else(|| {
+        candidates(poller).find(|&index| {
+            let mut slot = POLLERS[index].lock();
+            if slot.poller.is_none() {
+                slot.poller = Some(poller);
+                true
+            } else {
+                false
+            }
+        })
+    })
+}
+
+/// Records the poll of `cell` by the executing poller, until the guard is dropped.
+pub(super) fn enter(cell: &Arc<TaskCell>) -> RunningGuard {
+    let index = match find_or_bind(Poller::current()) {
+        Some(index) => index,
+        // Too many threads polling, so this one can't be abandoned
+        None => {
+            return RunningGuard {
+                index: None,
+                previous: None,
+            }
+        }
+    };
+    let abandonable = panic_isolation() || watchdog::mode() == watchdog::Mode::Kill;
+    // Only this poller writes its stack, so it can be allocated without holding the lock
+    if abandonable && POLLERS[index].lock().restart_stack == 0 {
+        let stack_top = new_stack();
+        POLLERS[index].lock().restart_stack = stack_top;
+    }
+    let running = Running {
+        cell: cell.clone(),
+        ticks: 0,
+        reported: false,
+    };
+    // A nested executor could already poll a task here
+    let previous = POLLERS[index].lock().running.replace(running);
+    RunningGuard {
+        index: Some(index),
+        previous,
+    }
+}
+
+pub(super) struct RunningGuard {
+    index: Option<usize>,
+    previous: Option<Running>,
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        if let Some(index) = self.index {
+            // Dropped after the lock is released
+            let _finished =
+                core::mem::replace(&mut POLLERS[index].lock().running, self.previous.take());
+        }
+    }
+}
+
+/// Takes the task polled by the executing poller and remembers it for `restart_worker`.
+/// Returns the restart stack to continue on.
+fn abandon(error: JoinError) -> Option<u64> {
+    let index = find(Poller::current())?;
+    let mut slot = POLLERS[index].lock();
+    if slot.restart_stack == 0 {
+        return None;
+    }
+    let running = slot.running.take()?;
+    slot.abandoned = Some((running.cell, error));
+    Some(slot.restart_stack)
+}
+
+/// Stack top for `restart_worker`, aligned as if it was called
+fn new_stack() -> u64 {
+    let stack: &'static mut [u8] = vec![0; RESTART_STACK_SIZE].leak();
+    ((stack.as_ptr() as u64 + RESTART_STACK_SIZE as u64) & !0xf) - 8
+}
+
+/// Returns from the interrupt into `restart_worker`, instead of the interrupted task. Must
+/// only be called from handlers of maskable interrupts, after the end of interrupt was
+/// sent. The interrupted code had interrupts enabled, so it is in task context.
+pub(super) fn abandon_from_interrupt(stack_frame: &mut InterruptStackFrame, error: JoinError) {
+    let stack_top = match abandon(error) {
+        Some(stack_top) => stack_top,
+        None => return,
+    };
+    unsafe {
+        stack_frame.as_mut().update(|frame| {
+            frame.instruction_pointer = VirtAddr::new(restart_worker as usize as u64);
+            frame.stack_pointer = VirtAddr::new(stack_top);
+        });
+    }
+}
+
+/// Called by the panic handler. Abandons the panicking task, if panic isolation is enabled
+/// and the panic happened in task context while polling a task. Returns otherwise.
+pub fn abort_panicking_task(info: &PanicInfo) {
+    if !panic_isolation() || !smp::in_task_context() {
+        return;
+    }
+    let stack_top = match abandon(JoinError::Panicked(info.to_string())) {
+        Some(stack_top) => stack_top,
+        None => return,
+    };
+    unsafe {
+        asm!(
+            "mov rsp, {}",
+            "jmp {}",
+            in(reg) stack_top,
+            sym restart_worker,
+            options(noreturn)
+        );
+    }
+}
+
+extern "C" fn restart_worker() -> ! {
+    // A panic could have happened with interrupts disabled
+    x86_64::instructions::interrupts::enable();
+    // The abandoned polls never leave their task-locals
+    local::clear_current();
+    let abandoned =
+        find(Poller::current()).and_then(|index| POLLERS[index].lock().abandoned.take());
+    if let Some((cell, error)) = abandoned {
+        serial_println!("task '{}' ({:?}) failed: {}", cell.name, cell.id, error);
+        cell.finish(Err(error));
+        // The abandoned poll still holds the lock of the future, so it must never be dropped
+        core::mem::forget(cell);
+    }
+    super::run_worker()
+}
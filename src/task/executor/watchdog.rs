@@ -0,0 +1,171 @@
+//! Watchdog for tasks, which don't yield.
+//!
+//! A single poll must not stall its CPU, as other tasks in its local queue can't run in
+//! the meantime. The timer interrupt of the bootstrap processor counts the ticks of every
+//! poll. A poll of a kernel thread only counts, while its thread is running, so preempted
+//! threads don't stall. Once a poll exceeds the threshold, the stalled poller reports the
+//! task and a backtrace to the serial port. Other CPUs are interrupted with an IPI to do so.
+//!
+//! In `Mode::Kill` the task is abandoned afterwards and fails with `JoinError::Killed`. The
+//! task and everything it locked are leaked, so only use it for debugging. Backtraces
+//! follow the frame pointers, which the kernel is built with.
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+};
+use x86_64::structures::idt::InterruptStackFrame;
+
+use super::{
+    abort::{self, Poller, POLLERS},
+    JoinError,
+};
+use crate::{serial_println, smp};
+
+/// About two seconds with the default PIT frequency of 18.2 Hz
+const DEFAULT_THRESHOLD_TICKS: u64 = 36;
+const MAX_BACKTRACE_DEPTH: usize = 32;
+/// Frame pointers further apart are considered corrupted
+const MAX_FRAME_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Mode {
+    Off = 0,
+    /// Report to the serial port
+    Report = 1,
+    /// Report and abandon the task
+    Kill = 2,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Report as u8);
+static THRESHOLD_TICKS: AtomicU64 = AtomicU64::new(DEFAULT_THRESHOLD_TICKS);
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        0 => Mode::Off,
+        1 => Mode::Report,
+        _ => Mode::Kill,
+    }
+}
+
+/// Sets after how many timer ticks a single poll counts as stalled.
+pub fn set_threshold(ticks: u64) {
+    THRESHOLD_TICKS.store(ticks.max(1), Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn threshold() -> u64 {
+    THRESHOLD_TICKS.load(Ordering::Relaxed)
+}
+
+/// Frame pointer of the interrupted code. Must be inlined into the interrupt handler, whose
+/// prologue saved it.
+#[allow(clippy::inline_always)]
+#[inline(always)]
+#[must_use]
+pub fn interrupted_frame_pointer() -> u64 {
+    let rbp: u64;
+    unsafe {
+        asm!("mov {}, [rbp]", out(reg) rbp, options(nostack, readonly, preserves_flags));
+    }
+    rbp
+}
+
+/// Called by the timer interrupt of the bootstrap processor.
+pub(crate) fn on_timer_tick(stack_frame: &mut InterruptStackFrame, frame_pointer: u64) {
+    if mode() == Mode::Off {
+        return;
+    }
+    let threshold = threshold();
+    let interrupted = Poller::current();
+    for slot in &POLLERS {
+        let cpu = {
+            let mut slot = slot.lock();
+            let poller = match slot.poller {
+                // Other CPUs don't run threads, so their poller is always running
+                Some(poller) if poller.cpu != interrupted.cpu || poller == interrupted => poller,
+                _ => continue,
+            };
+            match slot.running.as_mut() {
+                Some(running) if !running.reported => {
+                    running.ticks += 1;
+                    if running.ticks <= threshold {
+                        continue;
+                    }
+                    running.reported = true;
+                }
+                _ => continue,
+            }
+            poller.cpu
+        };
+        // Only the interrupted thread is stalled on this CPU
+        if cpu == interrupted.cpu {
+            handle_stall(stack_frame, frame_pointer);
+        } else if let Some(apic_id) = smp::apic_id(cpu) {
+            smp::apic::send_ipi(apic_id, smp::apic::WATCHDOG_VECTOR);
+        }
+    }
+}
+
+/// Reports the stalled task polled by the interrupted poller and kills it, if configured.
+pub(crate) fn handle_stall(stack_frame: &mut InterruptStackFrame, frame_pointer: u64) {
+    let cpu = smp::cpu_id();
+    let running = abort::find(Poller::current()).and_then(|index| {
+        let slot = POLLERS[index].lock();
+        slot.running
+            .as_ref()
+            .filter(|running| running.reported)
+            .map(|running| (running.cell.clone(), running.ticks))
+    });
+    let (cell, ticks) = match running {
+        Some(running) => running,
+        // Finished in the meantime, maybe another task is polled already
+        None => return,
+    };
+    serial_println!(
+        "watchdog: task '{}' ({:?}) on CPU {} did not yield for {} ticks",
+        cell.name,
+        cell.id,
+        cpu,
+        ticks
+    );
+    print_backtrace(
+        stack_frame.instruction_pointer.as_u64(),
+        stack_frame.stack_pointer.as_u64(),
+        frame_pointer,
+    );
+    if mode() == Mode::Kill {
+        abort::abandon_from_interrupt(stack_frame, JoinError::Killed);
+    }
+}
+
+fn print_backtrace(instruction_pointer: u64, mut stack_pointer: u64, mut frame_pointer: u64) {
+    serial_println!("backtrace:");
+    serial_println!("  0: {:#x}", instruction_pointer);
+    for depth in 1..MAX_BACKTRACE_DEPTH {
+        // The chain must stay on the stack and grow towards its top
+        if frame_pointer % 8 != 0
+            || frame_pointer < stack_pointer
+            || frame_pointer - stack_pointer > MAX_FRAME_SIZE
+        {
+            break;
+        }
+        let (next, return_address) = unsafe {
+            (
+                *(frame_pointer as *const u64),
+                *((frame_pointer + 8) as *const u64),
+            )
+        };
+        if return_address == 0 {
+            break;
+        }
+        serial_println!("{:3}: {:#x}", depth, return_address);
+        stack_pointer = frame_pointer;
+        frame_pointer = next;
+    }
+}
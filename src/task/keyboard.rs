@@ -1,15 +1,36 @@
+//! Keyboard input.
+//!
+//! The interrupt handler only queues raw scancodes. They are decoded into `KeyEvent`s with
+//! the key code, whether it was pressed, released or repeated, and the modifier state.
+//! Any number of `KeyEventStream`s may subscribe to them: monitors receive all events, the
+//! others only while they have the focus. Global hotkeys are handled before either.
+//! `ScancodeStream` is layered on top and yields decoded characters of the current `Layout`.
 use core::{
+    future::Future,
     pin::Pin,
-    task::{Context, Poll, Waker},
+    task::{Context, Poll},
 };
 
-use alloc::string::String;
+use alloc::sync::Arc;
 use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
-use futures_util::{task::AtomicWaker, Stream, StreamExt};
-use lazy_static::lazy_static;
+use futures_util::Stream;
 use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
-use spin::Mutex;
+
+use crate::{fw_cfg, ps2};
+
+pub use dispatch::{
+    focus_next, focused, register_hotkey, set_focus, unregister_hotkey, Hotkey, HotkeyId,
+    SubscriberId,
+};
+pub use layout::{layout, set_layout, Layout, UnknownLayout};
+pub use pc_keyboard::KeyCode;
+
+mod dispatch;
+mod layout;
+
+#[cfg(test)]
+mod tests;
 
 /// Meaning the queue has a size > 128 * 8 ~ 1kiB
 const SCANCODE_QUEUE_SIZE: usize = 128;
@@ -17,101 +38,337 @@ const SCANCODE_QUEUE_SIZE: usize = 128;
 /// `OnceCell` lets us initialize us wehnever we wan't, meaning not during an interrupt.
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 
-/// For `ScancodeStream`
-static WAKER: AtomicWaker = AtomicWaker::new();
-
-lazy_static! {
-    pub static ref KEYBOARD_STATE: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-        Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)
-    );
-}
-
 pub(crate) fn add_scancode(scancode: u8) {
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if queue.push(scancode).is_err() {
             kprintln!("[ERROR] scancode queue full");
         } else {
-            WAKER.wake();
+            dispatch::wake_all();
         }
     } else {
         kprintln!("[WARNING] scancode queue not initialized");
     }
 }
 
-pub struct ScancodeStream {
-    _prevent_construction: (),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Pressed,
+    Released,
+    /// Sent by the keyboard while the key is held down
+    Repeated,
 }
 
-impl ScancodeStream {
-    #[allow(clippy::new_without_default)]
+/// State of the modifier keys after an event. Lock keys toggle on every press.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub left_ctrl: bool,
+    pub right_ctrl: bool,
+    pub left_alt: bool,
+    /// AltGr on most european layouts
+    pub right_alt: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl Modifiers {
     #[must_use]
-    pub fn new() -> Self {
-        SCANCODE_QUEUE
-            .try_init_once(|| ArrayQueue::new(SCANCODE_QUEUE_SIZE))
-            .expect("ScancodeStream::new() may only be called once");
-        ScancodeStream {
-            _prevent_construction: (),
+    pub fn shift(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+
+    #[must_use]
+    pub fn ctrl(&self) -> bool {
+        self.left_ctrl || self.right_ctrl
+    }
+
+    #[must_use]
+    pub fn alt(&self) -> bool {
+        self.left_alt || self.right_alt
+    }
+
+    #[must_use]
+    pub fn leds(&self) -> ps2::Leds {
+        ps2::Leds {
+            scroll_lock: self.scroll_lock,
+            num_lock: self.num_lock,
+            caps_lock: self.caps_lock,
         }
     }
 
-    /// Make sure the waker is installed if returning `Poll::Pending`
-    #[inline]
-    fn ret_pending(waker: &Waker) -> Poll<Option<DecodedKey>> {
-        WAKER.register(waker);
-        Poll::Pending
+    /// Shift, Ctrl, Alt and the lock keys, which don't produce a character
+    #[must_use]
+    pub fn is_modifier(code: KeyCode) -> bool {
+        matches!(
+            code,
+            KeyCode::ShiftLeft
+                | KeyCode::ShiftRight
+                | KeyCode::ControlLeft
+                | KeyCode::ControlRight
+                | KeyCode::AltLeft
+                | KeyCode::AltRight
+                | KeyCode::CapsLock
+                | KeyCode::NumpadLock
+                | KeyCode::ScrollLock
+        )
+    }
+
+    /// As expected by the `pc_keyboard` layouts
+    fn to_pc_keyboard(self) -> pc_keyboard::Modifiers {
+        pc_keyboard::Modifiers {
+            lshift: self.left_shift,
+            rshift: self.right_shift,
+            lctrl: self.left_ctrl,
+            rctrl: self.right_ctrl,
+            numlock: self.num_lock,
+            capslock: self.caps_lock,
+            alt_gr: self.right_alt,
+        }
+    }
+
+    /// Updates the state, if `code` is a modifier key.
+    fn update(&mut self, code: KeyCode, state: KeyState) {
+        let pressed = state != KeyState::Released;
+        let held = match code {
+            KeyCode::ShiftLeft => &mut self.left_shift,
+            KeyCode::ShiftRight => &mut self.right_shift,
+            KeyCode::ControlLeft => &mut self.left_ctrl,
+            KeyCode::ControlRight => &mut self.right_ctrl,
+            KeyCode::AltLeft => &mut self.left_alt,
+            KeyCode::AltRight => &mut self.right_alt,
+            KeyCode::CapsLock | KeyCode::NumpadLock | KeyCode::ScrollLock => {
+                if state == KeyState::Pressed {
+                    let lock = match code {
+                        KeyCode::CapsLock => &mut self.caps_lock,
+                        KeyCode::NumpadLock => &mut self.num_lock,
+                        _ => &mut self.scroll_lock,
+                    };
+                    *lock = !*lock;
+                }
+                return;
+            }
+            _ => return,
+        };
+        *held = pressed;
     }
 }
 
-impl Stream for ScancodeStream {
-    type Item = DecodedKey;
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        // SCANCODE_QUEUE is guaranteed to be initialized by the constructor
-        let queue = SCANCODE_QUEUE.try_get().unwrap();
-        let scancode: u8 = match queue.pop() {
-            Some(scancode) => scancode,
-            None => return ScancodeStream::ret_pending(cx.waker()),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// Including the change caused by this event
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    /// Pressed or repeated
+    #[must_use]
+    pub fn is_down(&self) -> bool {
+        self.state != KeyState::Released
+    }
+}
+
+/// Position of `code` in a bitmap of keys
+fn key_bit(code: KeyCode) -> (usize, u64) {
+    (code as usize / 64 % 4, 1 << (code as usize % 64))
+}
+
+/// Turns scancodes into `KeyEvent`s
+struct KeyDecoder {
+    /// Only used to decode scancode set 1, the layout doesn't matter
+    scancodes: Keyboard<layouts::Us104Key, ScancodeSet1>,
+    modifiers: Modifiers,
+    /// Bitmap of the held keys, indexed by `KeyCode`
+    held: [u64; 4],
+}
+
+impl KeyDecoder {
+    fn new() -> Self {
+        Self {
+            scancodes: Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore),
+            modifiers: Modifiers::default(),
+            held: [0; 4],
+        }
+    }
+
+    fn add_scancode(&mut self, scancode: u8) -> Option<KeyEvent> {
+        let event = match self.scancodes.add_byte(scancode) {
+            Ok(Some(event)) => event,
+            _ => return None,
         };
-        let decoded = match decode_key(scancode) {
-            Some(key) => key,
-            None => return ScancodeStream::ret_pending(cx.waker()),
+        let code = event.code;
+        let (word, bit) = key_bit(code);
+        let state = if event.state == pc_keyboard::KeyState::Up {
+            self.held[word] &= !bit;
+            KeyState::Released
+        } else if self.held[word] & bit != 0 {
+            KeyState::Repeated
+        } else {
+            self.held[word] |= bit;
+            KeyState::Pressed
         };
+        self.modifiers.update(code, state);
+        Some(KeyEvent {
+            code,
+            state,
+            modifiers: self.modifiers,
+        })
+    }
+}
+
+/// Raw key events. Any number of streams may exist.
+pub struct KeyEventStream {
+    subscriber: Arc<dispatch::Subscriber>,
+}
 
-        Poll::Ready(Some(decoded))
+impl KeyEventStream {
+    /// Receives events while it has the focus. The first stream gets it automatically.
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        KeyEventStream {
+            subscriber: dispatch::subscribe(false),
+        }
+    }
+
+    /// Receives all events, except hotkeys, regardless of the focus.
+    #[must_use]
+    pub fn monitor() -> Self {
+        KeyEventStream {
+            subscriber: dispatch::subscribe(true),
+        }
+    }
+
+    #[must_use]
+    pub fn id(&self) -> SubscriberId {
+        self.subscriber.id
+    }
+
+    /// Takes the focus. Returns `false` for monitors.
+    pub fn focus(&self) -> bool {
+        set_focus(self.id())
+    }
+
+    #[must_use]
+    pub fn has_focus(&self) -> bool {
+        focused() == Some(self.id())
+    }
+
+    /// Decodes new scancodes without taking the events of this stream, so that hotkeys and
+    /// other subscribers are served while its owner is busy, e.g. a shell running a
+    /// command. The events stay queued. Never resolves.
+    #[must_use]
+    pub fn pump(&self) -> Pump<'_> {
+        Pump { stream: self }
+    }
+
+    fn next_event(&mut self) -> Option<KeyEvent> {
+        dispatch::pump();
+        self.subscriber.events.pop()
     }
 }
 
-fn decode_key(scancode: u8) -> Option<DecodedKey> {
-    let mut keyboard = KEYBOARD_STATE.lock();
-    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-        if let Some(key) = keyboard.process_keyevent(key_event) {
-            return Some(key);
+impl Drop for KeyEventStream {
+    fn drop(&mut self) {
+        dispatch::unsubscribe(self.id());
+    }
+}
+
+impl Stream for KeyEventStream {
+    type Item = KeyEvent;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(event) = self.next_event() {
+            return Poll::Ready(Some(event));
+        }
+        self.subscriber.waker.register(cx.waker());
+        // Check again, as a scancode could have arrived before the waker was registered
+        match self.next_event() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => Poll::Pending,
         }
     }
-    None
 }
 
-/// Returns a string with all inputs until a Enter key is pressed or the maximal
-/// amount of characters, given by `max_len`, is reached.
-pub async fn get_and_print_line(kb: &mut ScancodeStream, max_len: usize) -> String {
-    use pc_keyboard::DecodedKey::{RawKey, Unicode};
+/// Future of `KeyEventStream::pump`
+pub struct Pump<'a> {
+    stream: &'a KeyEventStream,
+}
 
-    let mut line = String::with_capacity(10);
-    loop {
-        while let Some(key) = kb.next().await {
-            match key {
-                Unicode('\n') => {
-                    return line;
-                }
-                Unicode(character) => {
-                    print!("{}", character);
-                    line.push(character);
-                }
-                RawKey(k) => {
-                    kprintln!("{:?}", k);
-                } //RawKey(_) => {}
-            }
-            if line.len() > max_len {
-                return line;
+impl Future for Pump<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        self.stream.subscriber.waker.register(cx.waker());
+        dispatch::pump();
+        Poll::Pending
+    }
+}
+
+/// Boot parameter with the keyboard layout, e.g. `-fw_cfg name=opt/cbos/keymap,string=de`
+const KEYMAP_PARAMETER: &str = "opt/cbos/keymap";
+
+/// Prepares the scancode queue and applies the boot parameter `opt/cbos/keymap`.
+pub fn init() {
+    SCANCODE_QUEUE.init_once(|| ArrayQueue::new(SCANCODE_QUEUE_SIZE));
+    if let Some(name) = fw_cfg::string(KEYMAP_PARAMETER) {
+        match name.parse() {
+            Ok(layout) => set_layout(layout),
+            Err(UnknownLayout) => println!("Unknown keymap '{}', using {}", name, layout()),
+        }
+    }
+}
+
+/// Decoded characters of pressed and repeated keys, e.g. `Unicode('A')` for Shift+A.
+/// Keys without a character are returned as `RawKey`, modifier keys not at all. Decoding
+/// uses the modifiers of the event, so they are right even if they changed while another
+/// stream had the focus.
+pub struct ScancodeStream {
+    events: KeyEventStream,
+}
+
+impl ScancodeStream {
+    /// Receives characters while it has the focus, see `KeyEventStream::new`.
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::from_events(KeyEventStream::new())
+    }
+
+    #[must_use]
+    pub fn from_events(events: KeyEventStream) -> Self {
+        ScancodeStream { events }
+    }
+
+    #[must_use]
+    pub fn events(&self) -> &KeyEventStream {
+        &self.events
+    }
+}
+
+/// Character of a pressed or repeated key with the current layout
+fn decode(event: KeyEvent) -> Option<DecodedKey> {
+    if !event.is_down() || Modifiers::is_modifier(event.code) {
+        return None;
+    }
+    let modifiers = event.modifiers.to_pc_keyboard();
+    Some(layout::map_keycode(layout(), event.code, &modifiers))
+}
+
+impl Stream for ScancodeStream {
+    type Item = DecodedKey;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            let event = match Pin::new(&mut self.events).poll_next(cx) {
+                Poll::Ready(Some(event)) => event,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(key) = decode(event) {
+                return Poll::Ready(Some(key));
             }
         }
     }
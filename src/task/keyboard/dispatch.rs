@@ -0,0 +1,287 @@
+//! Fans key events out to all subscribers.
+//!
+//! There is no dedicated task: whichever subscriber is polled first decodes the queued
+//! scancodes. Global hotkeys are checked before anything is delivered. The remaining events
+//! go to all monitors and to the subscriber with the focus, e.g. the visible terminal.
+use alloc::{sync::Arc, vec::Vec};
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+use lazy_static::lazy_static;
+
+use super::{key_bit, KeyCode, KeyDecoder, KeyEvent, KeyState, SCANCODE_QUEUE};
+use crate::{concurrency::IrqSpinLock, ps2};
+
+/// Events of a subscriber, which weren't read yet
+const EVENT_QUEUE_SIZE: usize = 64;
+
+lazy_static! {
+    static ref DISPATCHER: IrqSpinLock<Dispatcher> = IrqSpinLock::new(Dispatcher::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SubscriberId(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HotkeyId(u64);
+
+/// Key combination, which is handled before the event is dispatched. The modifiers have to
+/// match exactly, left and right are not distinguished.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    pub code: KeyCode,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl Hotkey {
+    /// Without modifiers
+    #[must_use]
+    pub const fn new(code: KeyCode) -> Self {
+        Self {
+            code,
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn ctrl(mut self) -> Self {
+        self.ctrl = true;
+        self
+    }
+
+    #[must_use]
+    pub const fn alt(mut self) -> Self {
+        self.alt = true;
+        self
+    }
+
+    #[must_use]
+    pub const fn shift(mut self) -> Self {
+        self.shift = true;
+        self
+    }
+
+    fn matches(&self, event: &KeyEvent) -> bool {
+        event.is_down()
+            && event.code == self.code
+            && event.modifiers.ctrl() == self.ctrl
+            && event.modifiers.alt() == self.alt
+            && event.modifiers.shift() == self.shift
+    }
+}
+
+type HotkeyHandler = Arc<dyn Fn(&KeyEvent) + Send + Sync>;
+
+/// Where an event goes
+enum Route {
+    Subscribers,
+    Hotkey(HotkeyHandler),
+    /// Release of a hotkey. Nobody saw the press, so nobody needs the release.
+    Nowhere,
+}
+
+pub(super) struct Subscriber {
+    pub(super) id: SubscriberId,
+    /// Receives all events, regardless of the focus
+    monitor: bool,
+    pub(super) events: ArrayQueue<KeyEvent>,
+    pub(super) waker: AtomicWaker,
+}
+
+struct Dispatcher {
+    decoder: KeyDecoder,
+    /// In the order of subscription, which is also the order `focus_next` cycles through
+    subscribers: Vec<Arc<Subscriber>>,
+    focus: Option<SubscriberId>,
+    hotkeys: Vec<(HotkeyId, Hotkey, HotkeyHandler)>,
+    /// Keys whose press triggered a hotkey. Their release is swallowed as well.
+    swallowed: [u64; 4],
+    next_id: u64,
+}
+
+impl Dispatcher {
+    fn new() -> Self {
+        Self {
+            decoder: KeyDecoder::new(),
+            subscribers: Vec::new(),
+            focus: None,
+            hotkeys: Vec::new(),
+            swallowed: [0; 4],
+            next_id: 0,
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn decode(&mut self, scancode: u8) -> Option<KeyEvent> {
+        let leds = self.decoder.modifiers.leds();
+        let event = self.decoder.add_scancode(scancode)?;
+        if event.modifiers.leds() != leds {
+            ps2::set_leds(event.modifiers.leds());
+        }
+        Some(event)
+    }
+
+    fn route(&mut self, event: &KeyEvent) -> Route {
+        let (word, bit) = key_bit(event.code);
+        if event.state == KeyState::Released {
+            let swallowed = self.swallowed[word] & bit != 0;
+            self.swallowed[word] &= !bit;
+            return if swallowed {
+                Route::Nowhere
+            } else {
+                Route::Subscribers
+            };
+        }
+        let handler = self
+            .hotkeys
+            .iter()
+            .find(|(_, hotkey, _)| hotkey.matches(event))
+            .map(|(_, _, handler)| handler.clone());
+        match handler {
+            Some(handler) => {
+                self.swallowed[word] |= bit;
+                Route::Hotkey(handler)
+            }
+            None => Route::Subscribers,
+        }
+    }
+
+    fn dispatch(&self, event: KeyEvent) {
+        for subscriber in &self.subscribers {
+            if !subscriber.monitor && Some(subscriber.id) != self.focus {
+                continue;
+            }
+            if subscriber.events.push(event).is_err() {
+                kprintln!("[WARNING] key event queue of {:?} full", subscriber.id);
+            } else {
+                subscriber.waker.wake();
+            }
+        }
+    }
+
+    fn focusable(&self) -> impl Iterator<Item = SubscriberId> + '_ {
+        self.subscribers
+            .iter()
+            .filter(|subscriber| !subscriber.monitor)
+            .map(|subscriber| subscriber.id)
+    }
+
+    /// The next focusable subscriber after the focused one, wrapping around
+    fn next_focus(&self) -> Option<SubscriberId> {
+        let mut focusable = self.focusable().skip_while(|id| Some(*id) != self.focus);
+        focusable
+            .nth(1)
+            .or_else(|| self.focusable().find(|id| Some(*id) != self.focus))
+            .or(self.focus)
+    }
+}
+
+pub(super) fn subscribe(monitor: bool) -> Arc<Subscriber> {
+    let mut dispatcher = DISPATCHER.lock();
+    let subscriber = Arc::new(Subscriber {
+        id: SubscriberId(dispatcher.next_id()),
+        monitor,
+        events: ArrayQueue::new(EVENT_QUEUE_SIZE),
+        waker: AtomicWaker::new(),
+    });
+    if !monitor && dispatcher.focus.is_none() {
+        dispatcher.focus = Some(subscriber.id);
+    }
+    dispatcher.subscribers.push(subscriber.clone());
+    subscriber
+}
+
+pub(super) fn unsubscribe(id: SubscriberId) {
+    let mut dispatcher = DISPATCHER.lock();
+    if dispatcher.focus == Some(id) {
+        let next = dispatcher.next_focus().filter(|next| *next != id);
+        dispatcher.focus = next;
+    }
+    dispatcher
+        .subscribers
+        .retain(|subscriber| subscriber.id != id);
+}
+
+/// Wakes all subscribers, called by the interrupt handler for new scancodes
+pub(super) fn wake_all() {
+    for subscriber in &DISPATCHER.lock().subscribers {
+        subscriber.waker.wake();
+    }
+}
+
+/// Decodes all queued scancodes and delivers the events. Hotkey handlers run afterwards,
+/// without holding the lock.
+pub(super) fn pump() {
+    let queue = match SCANCODE_QUEUE.try_get() {
+        Ok(queue) => queue,
+        Err(_) => return,
+    };
+    let mut triggered = Vec::new();
+    {
+        let mut dispatcher = DISPATCHER.lock();
+        while let Some(scancode) = queue.pop() {
+            if let Some(event) = dispatcher.decode(scancode) {
+                match dispatcher.route(&event) {
+                    Route::Subscribers => dispatcher.dispatch(event),
+                    Route::Hotkey(handler) => triggered.push((handler, event)),
+                    Route::Nowhere => {}
+                }
+            }
+        }
+    }
+    for (handler, event) in triggered {
+        handler(&event);
+    }
+}
+
+/// Calls `handler` instead of dispatching the key combination. If several handlers are
+/// registered for the same combination, the first one wins.
+pub fn register_hotkey(
+    hotkey: Hotkey,
+    handler: impl Fn(&KeyEvent) + Send + Sync + 'static,
+) -> HotkeyId {
+    let mut dispatcher = DISPATCHER.lock();
+    let id = HotkeyId(dispatcher.next_id());
+    dispatcher.hotkeys.push((id, hotkey, Arc::new(handler)));
+    id
+}
+
+/// Returns `false`, if the hotkey wasn't registered.
+pub fn unregister_hotkey(id: HotkeyId) -> bool {
+    let mut dispatcher = DISPATCHER.lock();
+    let count = dispatcher.hotkeys.len();
+    dispatcher.hotkeys.retain(|(hotkey, _, _)| *hotkey != id);
+    dispatcher.hotkeys.len() != count
+}
+
+/// Subscriber, which receives the events. Monitors are never focused.
+#[must_use]
+pub fn focused() -> Option<SubscriberId> {
+    DISPATCHER.lock().focus
+}
+
+/// Returns `false`, if `id` isn't subscribed or is a monitor.
+pub fn set_focus(id: SubscriberId) -> bool {
+    let mut dispatcher = DISPATCHER.lock();
+    if dispatcher.focusable().any(|focusable| focusable == id) {
+        dispatcher.focus = Some(id);
+        true
+    } else {
+        false
+    }
+}
+
+/// Moves the focus to the next subscriber, e.g. to switch terminals.
+pub fn focus_next() -> Option<SubscriberId> {
+    let mut dispatcher = DISPATCHER.lock();
+    dispatcher.focus = dispatcher.next_focus();
+    dispatcher.focus
+}
@@ -0,0 +1,105 @@
+//! Keyboard layouts, selectable at runtime.
+use core::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use pc_keyboard::{layouts, DecodedKey, HandleControl, KeyCode, KeyboardLayout, Modifiers};
+
+static CURRENT: AtomicU8 = AtomicU8::new(Layout::Us104Key as u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Layout {
+    Us104Key = 0,
+    Uk105Key = 1,
+    De105Key = 2,
+    Azerty = 3,
+    Dvorak = 4,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 5] = [
+        Layout::Us104Key,
+        Layout::Uk105Key,
+        Layout::De105Key,
+        Layout::Azerty,
+        Layout::Dvorak,
+    ];
+
+    /// Short name, as accepted by `keymap`
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Us104Key => "us",
+            Layout::Uk105Key => "uk",
+            Layout::De105Key => "de",
+            Layout::Azerty => "azerty",
+            Layout::Dvorak => "dvorak",
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|layout| *layout as u8 == value)
+            .unwrap_or(Layout::Us104Key)
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownLayout;
+
+impl FromStr for Layout {
+    type Err = UnknownLayout;
+
+    /// Accepts the short name or the name of the `pc_keyboard` layout, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Self::ALL
+            .into_iter()
+            .find(|layout| {
+                s.eq_ignore_ascii_case(layout.name())
+                    || s.eq_ignore_ascii_case(match layout {
+                        Layout::Us104Key => "Us104Key",
+                        Layout::Uk105Key => "Uk105Key",
+                        Layout::De105Key => "De105Key",
+                        Layout::Azerty => "Azerty",
+                        Layout::Dvorak => "Dvorak104Key",
+                    })
+            })
+            .ok_or(UnknownLayout)
+    }
+}
+
+/// Layout used to decode characters from now on
+pub fn set_layout(layout: Layout) {
+    CURRENT.store(layout as u8, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn layout() -> Layout {
+    Layout::from_u8(CURRENT.load(Ordering::Relaxed))
+}
+
+/// Character of `code` with the given modifiers, or a `RawKey` without one. Ctrl+A to
+/// Ctrl+Z are decoded to the control characters 0x01 to 0x1a, like in a terminal.
+///
+/// Stateless, so all streams decode with the same modifiers, regardless of which one had
+/// the focus when they changed.
+pub(super) fn map_keycode(layout: Layout, code: KeyCode, modifiers: &Modifiers) -> DecodedKey {
+    let ctrl = HandleControl::MapLettersToUnicode;
+    match layout {
+        Layout::Us104Key => layouts::Us104Key::map_keycode(code, modifiers, ctrl),
+        Layout::Uk105Key => layouts::Uk105Key::map_keycode(code, modifiers, ctrl),
+        Layout::De105Key => layouts::De105Key::map_keycode(code, modifiers, ctrl),
+        Layout::Azerty => layouts::Azerty::map_keycode(code, modifiers, ctrl),
+        Layout::Dvorak => layouts::Dvorak104Key::map_keycode(code, modifiers, ctrl),
+    }
+}
@@ -0,0 +1,158 @@
+use super::*;
+use alloc::vec::Vec;
+
+/// Scancode set 1: the release code is the press code with the highest bit set
+const SHIFT_LEFT: u8 = 0x2a;
+const KEY_A: u8 = 0x1e;
+const CAPS_LOCK: u8 = 0x3a;
+const RELEASED: u8 = 0x80;
+
+#[test_case]
+fn press_repeat_release() {
+    let mut decoder = KeyDecoder::new();
+    let states: Vec<KeyState> = [KEY_A, KEY_A, KEY_A | RELEASED]
+        .into_iter()
+        .filter_map(|scancode| decoder.add_scancode(scancode))
+        .map(|event| event.state)
+        .collect();
+    assert_eq!(
+        states,
+        [KeyState::Pressed, KeyState::Repeated, KeyState::Released]
+    );
+}
+
+#[test_case]
+fn modifiers_are_tracked() {
+    let mut decoder = KeyDecoder::new();
+    let shift = decoder.add_scancode(SHIFT_LEFT).unwrap();
+    assert_eq!(shift.code, KeyCode::ShiftLeft);
+    assert!(shift.modifiers.shift());
+
+    let a = decoder.add_scancode(KEY_A).unwrap();
+    assert_eq!(a.code, KeyCode::A);
+    assert!(a.modifiers.left_shift && !a.modifiers.ctrl());
+
+    let released = decoder.add_scancode(SHIFT_LEFT | RELEASED).unwrap();
+    assert!(!released.modifiers.shift());
+}
+
+#[test_case]
+fn lock_keys_toggle_on_press() {
+    let mut decoder = KeyDecoder::new();
+    decoder.add_scancode(CAPS_LOCK);
+    // Holding the key must not toggle it again
+    decoder.add_scancode(CAPS_LOCK);
+    assert!(decoder.modifiers.caps_lock);
+    decoder.add_scancode(CAPS_LOCK | RELEASED);
+    assert!(decoder.modifiers.caps_lock);
+    decoder.add_scancode(CAPS_LOCK);
+    assert!(!decoder.modifiers.caps_lock);
+}
+
+#[test_case]
+fn layouts_parse_by_name() {
+    for layout in Layout::ALL {
+        assert_eq!(layout.name().parse(), Ok(layout));
+    }
+    assert_eq!("Dvorak104Key".parse(), Ok(Layout::Dvorak));
+    assert_eq!("DE".parse(), Ok(Layout::De105Key));
+    assert_eq!("qwertz".parse::<Layout>(), Err(UnknownLayout));
+}
+
+#[test_case]
+fn layout_changes_decoding() {
+    const KEY_Y: u8 = 0x15;
+    let decode = |layout| {
+        let mut decoder = KeyDecoder::new();
+        let event = decoder.add_scancode(KEY_Y).unwrap();
+        let modifiers = event.modifiers.to_pc_keyboard();
+        layout::map_keycode(layout, event.code, &modifiers)
+    };
+    assert_eq!(decode(Layout::Us104Key), DecodedKey::Unicode('y'));
+    assert_eq!(decode(Layout::De105Key), DecodedKey::Unicode('z'));
+}
+
+#[test_case]
+fn decoding_uses_the_modifiers_of_the_event() {
+    let mut decoder = KeyDecoder::new();
+    let events: Vec<KeyEvent> = [SHIFT_LEFT, KEY_A, SHIFT_LEFT | RELEASED, KEY_A]
+        .into_iter()
+        .filter_map(|scancode| decoder.add_scancode(scancode))
+        .collect();
+    let keys: Vec<DecodedKey> = events.into_iter().filter_map(decode).collect();
+    assert_eq!(keys, [DecodedKey::Unicode('A'), DecodedKey::Unicode('a')]);
+
+    // A held Caps Lock toggles once, like its LED
+    let events: Vec<KeyEvent> = [CAPS_LOCK, CAPS_LOCK, KEY_A]
+        .into_iter()
+        .filter_map(|scancode| decoder.add_scancode(scancode))
+        .collect();
+    assert!(events[1].modifiers.leds().caps_lock);
+    let keys: Vec<DecodedKey> = events.into_iter().filter_map(decode).collect();
+    assert_eq!(keys, [DecodedKey::Unicode('A')]);
+}
+
+#[test_case]
+fn focused_stream_receives_events() {
+    let mut first = KeyEventStream::new();
+    let mut second = KeyEventStream::new();
+    let mut monitor = KeyEventStream::monitor();
+    assert!(first.has_focus() && !second.has_focus());
+    assert!(!monitor.focus());
+
+    add_scancode(KEY_A);
+    assert_eq!(first.next_event().map(|event| event.code), Some(KeyCode::A));
+    assert_eq!(second.next_event(), None);
+
+    assert!(second.focus());
+    add_scancode(KEY_A | RELEASED);
+    assert_eq!(first.next_event(), None);
+    let released = second.next_event().map(|event| event.state);
+    assert_eq!(released, Some(KeyState::Released));
+
+    let states: Vec<KeyState> = core::iter::from_fn(|| monitor.next_event())
+        .map(|event| event.state)
+        .collect();
+    assert_eq!(states, [KeyState::Pressed, KeyState::Released]);
+}
+
+#[test_case]
+fn focus_moves_on() {
+    let first = KeyEventStream::new();
+    let second = KeyEventStream::new();
+    let _monitor = KeyEventStream::monitor();
+    assert_eq!(focus_next(), Some(second.id()));
+    // Wraps around and skips the monitor
+    assert_eq!(focus_next(), Some(first.id()));
+    drop(first);
+    assert!(second.has_focus());
+    drop(second);
+    assert_eq!(focused(), None);
+}
+
+#[test_case]
+fn hotkeys_are_handled_first() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    const F12: u8 = 0x58;
+    static TRIGGERED: AtomicUsize = AtomicUsize::new(0);
+
+    let hotkey = register_hotkey(Hotkey::new(KeyCode::F12), |_| {
+        TRIGGERED.fetch_add(1, Ordering::Relaxed);
+    });
+    let mut stream = KeyEventStream::new();
+    add_scancode(F12);
+    add_scancode(F12 | RELEASED);
+    // Neither the press nor the release is dispatched
+    assert_eq!(stream.next_event(), None);
+    assert_eq!(TRIGGERED.load(Ordering::Relaxed), 1);
+
+    assert!(unregister_hotkey(hotkey));
+    assert!(!unregister_hotkey(hotkey));
+    add_scancode(F12);
+    add_scancode(F12 | RELEASED);
+    assert_eq!(
+        stream.next_event().map(|event| event.code),
+        Some(KeyCode::F12)
+    );
+    assert_eq!(TRIGGERED.load(Ordering::Relaxed), 1);
+}
@@ -0,0 +1,155 @@
+//! Task-local storage, e.g. for the working directory or the terminal of a program.
+//!
+//! Every `Task` owns the values of its keys. The executor makes them reachable while it
+//! polls the task, so `LocalKey::with` finds the values of the currently running task.
+//! Values are created lazily from the initialiser of the key, unless the task was spawned
+//! with `Task::with_local`. Interrupt handlers see the task they interrupted, so don't use
+//! task-locals there.
+//!
+//! The current task is tracked per poller of the executor, i.e. per CPU and, on the
+//! bootstrap processor, per kernel thread, as a thread can be preempted in the middle of a
+//! poll.
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::{
+    any::Any,
+    fmt, ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use super::executor::{current_poller_slot, MAX_POLLERS};
+
+/// Declares task-local statics, similar to `thread_local!`.
+///
+/// ```ignore
+/// task_local! {
+///     static COUNTER: Cell<usize> = Cell::new(0);
+/// }
+/// COUNTER.with(|counter| counter.set(counter.get() + 1));
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::task_local!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::task_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])*
+        $vis static $name: $crate::task::LocalKey<$t> = {
+            fn init() -> $t {
+                $init
+            }
+            $crate::task::LocalKey::new(init)
+        };
+    };
+}
+
+/// Locals of the task, which each poller polls, indexed like the slots of the pollers
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_TASK: AtomicPtr<TaskLocals> = AtomicPtr::new(ptr::null_mut());
+static CURRENT: [AtomicPtr<TaskLocals>; MAX_POLLERS] = [NO_TASK; MAX_POLLERS];
+
+/// Of the executing poller. `None` if it never polled a task.
+fn current() -> Option<&'static AtomicPtr<TaskLocals>> {
+    current_poller_slot().map(|index| &CURRENT[index])
+}
+
+/// Forgets the locals of the executing poller, whose polls were abandoned without dropping
+/// their `EnterGuard`.
+pub(crate) fn clear_current() {
+    if let Some(slot) = current() {
+        slot.store(ptr::null_mut(), Ordering::Release);
+    }
+}
+
+/// Returned by `LocalKey::try_with` outside of a task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessError;
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("task-local accessed outside of a task")
+    }
+}
+
+/// Key of a task-local value, created by `task_local!`. Use `Cell` or `RefCell` for values,
+/// which change.
+pub struct LocalKey<T: 'static> {
+    init: fn() -> T,
+}
+
+impl<T: Send + 'static> LocalKey<T> {
+    #[doc(hidden)]
+    #[must_use]
+    pub const fn new(init: fn() -> T) -> Self {
+        Self { init }
+    }
+
+    /// Statics have a unique address
+    fn id(&'static self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Calls `f` with the value of the current task.
+    ///
+    /// # Panics
+    /// If called outside of a task.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        self.try_with(f)
+            .expect("Task-local is accessed from within a task")
+    }
+
+    /// # Errors
+    /// If called outside of a task.
+    pub fn try_with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+        let locals = current().map_or(ptr::null_mut(), |slot| slot.load(Ordering::Acquire));
+        if locals.is_null() {
+            return Err(AccessError);
+        }
+        // SAFETY: The pointer is valid while the task is polled. Values are boxed, so
+        // inserting other keys during `f` doesn't move them, and they are never removed
+        // while the task is alive.
+        let value: *const T = unsafe {
+            (*locals)
+                .values
+                .entry(self.id())
+                .or_insert_with(|| Box::new((self.init)()))
+                .downcast_ref::<T>()
+                .expect("Task-local has the type of its key")
+        };
+        Ok(f(unsafe { &*value }))
+    }
+}
+
+/// Values of all keys used by a task
+#[derive(Default)]
+pub(crate) struct TaskLocals {
+    values: BTreeMap<usize, Box<dyn Any + Send>>,
+}
+
+impl TaskLocals {
+    pub(crate) fn insert<T: Send + 'static>(&mut self, key: &'static LocalKey<T>, value: T) {
+        self.values.insert(key.id(), Box::new(value));
+    }
+
+    /// Makes the values reachable until the guard is dropped. Not on a kernel thread, which
+    /// found no free poller slot.
+    pub(crate) fn enter(&mut self) -> EnterGuard {
+        let slot = current();
+        let previous = slot.map_or(ptr::null_mut(), |slot| slot.swap(self, Ordering::AcqRel));
+        EnterGuard { slot, previous }
+    }
+}
+
+pub(crate) struct EnterGuard {
+    slot: Option<&'static AtomicPtr<TaskLocals>>,
+    previous: *mut TaskLocals,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            slot.store(self.previous, Ordering::Release);
+        }
+    }
+}
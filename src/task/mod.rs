@@ -1,4 +1,4 @@
-use alloc::boxed::Box;
+use alloc::{borrow::Cow, boxed::Box};
 use core::sync::atomic::{AtomicU64, Ordering};
 use core::{
     future::Future,
@@ -7,31 +7,85 @@ use core::{
 };
 
 pub mod executor;
+mod local;
+
+pub use local::{AccessError, LocalKey};
 
 // async-ified system ressources:
 pub mod keyboard;
+pub mod mouse;
+pub mod serial;
 pub mod timer;
 
 // Primitives
 
 pub struct Task {
     id: TaskId,
+    name: Cow<'static, str>,
     future: Pin<Box<dyn Future<Output = ()> + Send + Sync>>,
+    locals: local::TaskLocals,
 }
 
 impl Task {
     pub fn new(future: impl Future<Output = ()> + 'static + Send + Sync) -> Task {
         Task {
             id: TaskId::new(),
+            name: Cow::Borrowed("unnamed"),
             future: Box::pin(future),
+            locals: local::TaskLocals::default(),
         }
     }
 
+    /// Name shown in diagnostics, e.g. by the watchdog
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Starts the task with `value` for the task-local `key`, instead of its initialiser.
+    #[must_use]
+    pub fn with_local<T: Send + 'static>(mut self, key: &'static LocalKey<T>, value: T) -> Self {
+        self.locals.insert(key, value);
+        self
+    }
+
+    /// Task-locals are reachable while the task is polled
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        let _locals = self.locals.enter();
         self.future.as_mut().poll(context)
     }
 }
 
+/// Lets the other tasks run, e.g. between iterations of a long loop
+#[must_use = "futures do nothing unless polled"]
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+/// Future of `yield_now`, which is pending once
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct TaskId(u64);
 
@@ -0,0 +1,171 @@
+//! Mouse input.
+//!
+//! Like the keyboard, the interrupt handler only queues raw bytes. `MouseEventStream`
+//! assembles them into packets and decodes them into `MouseEvent`s.
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::{task::AtomicWaker, Stream};
+
+use crate::ps2;
+
+#[cfg(test)]
+mod tests;
+
+/// Enough for 64 packets
+const BYTE_QUEUE_SIZE: usize = 256;
+
+/// Always set in the first byte of a packet, used to find the start of one
+const PACKET_ALWAYS_ONE: u8 = 1 << 3;
+const PACKET_LEFT: u8 = 1 << 0;
+const PACKET_RIGHT: u8 = 1 << 1;
+const PACKET_MIDDLE: u8 = 1 << 2;
+const PACKET_X_SIGN: u8 = 1 << 4;
+const PACKET_Y_SIGN: u8 = 1 << 5;
+const PACKET_X_OVERFLOW: u8 = 1 << 6;
+const PACKET_Y_OVERFLOW: u8 = 1 << 7;
+
+static BYTE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+
+/// For `MouseEventStream`
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+pub(crate) fn add_byte(byte: u8) {
+    if let Ok(queue) = BYTE_QUEUE.try_get() {
+        if queue.push(byte).is_err() {
+            kprintln!("[ERROR] mouse queue full");
+        } else {
+            WAKER.wake();
+        }
+    }
+    // Without a stream nobody is interested in the mouse
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Buttons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Movement to the right
+    pub dx: i16,
+    /// Movement down, like rows on the screen
+    pub dy: i16,
+    /// Positive when scrolling down. Always 0 for mice without a wheel.
+    pub wheel: i8,
+    /// Held buttons
+    pub buttons: Buttons,
+}
+
+/// Turns bytes into `MouseEvent`s
+struct PacketDecoder {
+    packet: [u8; 4],
+    len: usize,
+    /// 3, or 4 with a scroll wheel
+    size: usize,
+}
+
+impl PacketDecoder {
+    fn new(wheel: bool) -> Self {
+        Self {
+            packet: [0; 4],
+            len: 0,
+            size: if wheel { 4 } else { 3 },
+        }
+    }
+
+    fn add_byte(&mut self, byte: u8) -> Option<MouseEvent> {
+        if self.len == 0 && byte & PACKET_ALWAYS_ONE == 0 {
+            // Out of sync, e.g. a byte got lost
+            return None;
+        }
+        self.packet[self.len] = byte;
+        self.len += 1;
+        if self.len < self.size {
+            return None;
+        }
+        self.len = 0;
+        Some(Self::decode(self.packet, self.size))
+    }
+
+    fn decode(packet: [u8; 4], size: usize) -> MouseEvent {
+        let flags = packet[0];
+        // 9 bit two's complement, with the sign in the flags
+        let axis = |value: u8, sign: u8, overflow: u8| {
+            if flags & overflow != 0 {
+                0
+            } else if flags & sign != 0 {
+                i16::from(value) - 256
+            } else {
+                i16::from(value)
+            }
+        };
+        MouseEvent {
+            dx: axis(packet[1], PACKET_X_SIGN, PACKET_X_OVERFLOW),
+            dy: -axis(packet[2], PACKET_Y_SIGN, PACKET_Y_OVERFLOW),
+            wheel: if size == 4 {
+                i8::from_ne_bytes([packet[3]])
+            } else {
+                0
+            },
+            buttons: Buttons {
+                left: flags & PACKET_LEFT != 0,
+                right: flags & PACKET_RIGHT != 0,
+                middle: flags & PACKET_MIDDLE != 0,
+            },
+        }
+    }
+}
+
+/// Mouse events. Only one may exist, as it consumes the bytes of the interrupt handler.
+pub struct MouseEventStream {
+    decoder: PacketDecoder,
+}
+
+impl MouseEventStream {
+    /// # Panics
+    /// If called twice.
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        BYTE_QUEUE
+            .try_init_once(|| ArrayQueue::new(BYTE_QUEUE_SIZE))
+            .expect("MouseEventStream::new() may only be called once");
+        MouseEventStream {
+            decoder: PacketDecoder::new(ps2::mouse_has_wheel()),
+        }
+    }
+
+    fn next_event(&mut self) -> Option<MouseEvent> {
+        // BYTE_QUEUE is guaranteed to be initialized by the constructor
+        let queue = BYTE_QUEUE.try_get().unwrap();
+        while let Some(byte) = queue.pop() {
+            if let Some(event) = self.decoder.add_byte(byte) {
+                return Some(event);
+            }
+        }
+        None
+    }
+}
+
+impl Stream for MouseEventStream {
+    type Item = MouseEvent;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(event) = self.next_event() {
+            return Poll::Ready(Some(event));
+        }
+        WAKER.register(cx.waker());
+        // Check again, as a byte could have arrived before the waker was registered
+        match self.next_event() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => Poll::Pending,
+        }
+    }
+}
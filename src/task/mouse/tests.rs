@@ -0,0 +1,50 @@
+use super::*;
+
+#[test_case]
+fn movement_and_buttons() {
+    let mut decoder = PacketDecoder::new(false);
+    assert_eq!(decoder.add_byte(PACKET_ALWAYS_ONE | PACKET_LEFT), None);
+    assert_eq!(decoder.add_byte(5), None);
+    let event = decoder.add_byte(3).unwrap();
+    assert_eq!((event.dx, event.dy, event.wheel), (5, -3, 0));
+    assert!(event.buttons.left && !event.buttons.right && !event.buttons.middle);
+}
+
+#[test_case]
+fn negative_movement() {
+    let mut decoder = PacketDecoder::new(false);
+    decoder.add_byte(PACKET_ALWAYS_ONE | PACKET_X_SIGN | PACKET_Y_SIGN);
+    decoder.add_byte(0xff);
+    let event = decoder.add_byte(0xfe).unwrap();
+    assert_eq!((event.dx, event.dy), (-1, 2));
+}
+
+#[test_case]
+fn overflow_is_ignored() {
+    let mut decoder = PacketDecoder::new(false);
+    decoder.add_byte(PACKET_ALWAYS_ONE | PACKET_X_OVERFLOW);
+    decoder.add_byte(0x80);
+    let event = decoder.add_byte(1).unwrap();
+    assert_eq!((event.dx, event.dy), (0, -1));
+}
+
+#[test_case]
+fn wheel_packets() {
+    let mut decoder = PacketDecoder::new(true);
+    for byte in [PACKET_ALWAYS_ONE | PACKET_MIDDLE, 0, 0] {
+        assert_eq!(decoder.add_byte(byte), None);
+    }
+    let event = decoder.add_byte(0xff).unwrap();
+    assert_eq!(event.wheel, -1);
+    assert!(event.buttons.middle);
+}
+
+#[test_case]
+fn resynchronises() {
+    let mut decoder = PacketDecoder::new(false);
+    // Not the start of a packet
+    assert_eq!(decoder.add_byte(0x02), None);
+    decoder.add_byte(PACKET_ALWAYS_ONE | PACKET_RIGHT);
+    decoder.add_byte(1);
+    assert!(decoder.add_byte(1).unwrap().buttons.right);
+}
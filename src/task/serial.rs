@@ -0,0 +1,237 @@
+//! Serial input.
+//!
+//! Like the keyboard, the interrupt handler only queues raw bytes. `SerialKeyStream`
+//! decodes them into the `DecodedKey`s of the keyboard, so programs can handle both alike.
+//! Terminals send UTF-8 and ANSI escape sequences for keys without a character, e.g.
+//! `ESC [ A` for the up arrow.
+use alloc::collections::VecDeque;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::{future::poll_fn, task::AtomicWaker, Stream};
+use pc_keyboard::{DecodedKey, KeyCode};
+
+#[cfg(test)]
+mod tests;
+
+/// Enough for a pasted line
+const BYTE_QUEUE_SIZE: usize = 512;
+/// Keys kept by `wait_for`, later ones are dropped
+const TYPE_AHEAD_SIZE: usize = 512;
+
+const ESCAPE: u8 = 0x1b;
+/// Sent by most terminals for Backspace
+const DELETE: u8 = 0x7f;
+const BACKSPACE: char = '\u{8}';
+
+static BYTE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+
+/// For `SerialKeyStream`
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+pub(crate) fn add_byte(byte: u8) {
+    if let Ok(queue) = BYTE_QUEUE.try_get() {
+        if queue.push(byte).is_err() {
+            kprintln!("[ERROR] serial queue full");
+        } else {
+            WAKER.wake();
+        }
+    }
+    // Without a stream nobody reads from the serial port
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After `ESC`
+    Escape,
+    /// After `ESC [` or `ESC O`, with the numeric parameter so far
+    Sequence(u8),
+    /// Of a multi-byte UTF-8 character, with the bytes still missing
+    Utf8(usize),
+}
+
+/// Turns bytes into keys
+struct KeyDecoder {
+    state: State,
+    utf8: [u8; 4],
+    utf8_len: usize,
+    /// `\r\n` is a single Enter
+    after_carriage_return: bool,
+}
+
+impl KeyDecoder {
+    fn new() -> Self {
+        Self {
+            state: State::Ground,
+            utf8: [0; 4],
+            utf8_len: 0,
+            after_carriage_return: false,
+        }
+    }
+
+    fn add_byte(&mut self, byte: u8) -> Option<DecodedKey> {
+        let after_carriage_return = core::mem::replace(&mut self.after_carriage_return, false);
+        match self.state {
+            State::Ground => self.ground(byte, after_carriage_return),
+            State::Escape => {
+                self.state = match byte {
+                    b'[' | b'O' => State::Sequence(0),
+                    _ => State::Ground,
+                };
+                None
+            }
+            State::Sequence(parameter) => {
+                if byte.is_ascii_digit() {
+                    self.state =
+                        State::Sequence(parameter.saturating_mul(10).saturating_add(byte - b'0'));
+                    return None;
+                }
+                self.state = State::Ground;
+                let code = match (byte, parameter) {
+                    (b'A', _) => KeyCode::ArrowUp,
+                    (b'B', _) => KeyCode::ArrowDown,
+                    (b'C', _) => KeyCode::ArrowRight,
+                    (b'D', _) => KeyCode::ArrowLeft,
+                    (b'H', _) | (b'~', 1 | 7) => KeyCode::Home,
+                    (b'F', _) | (b'~', 4 | 8) => KeyCode::End,
+                    (b'~', 3) => KeyCode::Delete,
+                    // E.g. function keys
+                    _ => return None,
+                };
+                Some(DecodedKey::RawKey(code))
+            }
+            State::Utf8(missing) => {
+                if byte & 0b1100_0000 != 0b1000_0000 {
+                    // Not a continuation byte, so the character is dropped
+                    self.state = State::Ground;
+                    return self.ground(byte, after_carriage_return);
+                }
+                self.utf8[self.utf8_len] = byte;
+                self.utf8_len += 1;
+                if missing > 1 {
+                    self.state = State::Utf8(missing - 1);
+                    return None;
+                }
+                self.state = State::Ground;
+                core::str::from_utf8(&self.utf8[..self.utf8_len])
+                    .ok()
+                    .and_then(|text| text.chars().next())
+                    .map(DecodedKey::Unicode)
+            }
+        }
+    }
+
+    fn ground(&mut self, byte: u8, after_carriage_return: bool) -> Option<DecodedKey> {
+        let missing = match byte {
+            ESCAPE => {
+                self.state = State::Escape;
+                return None;
+            }
+            b'\r' => {
+                self.after_carriage_return = true;
+                return Some(DecodedKey::Unicode('\n'));
+            }
+            b'\n' if after_carriage_return => return None,
+            DELETE => return Some(DecodedKey::Unicode(BACKSPACE)),
+            0..=0x7f => return Some(DecodedKey::Unicode(char::from(byte))),
+            0b1100_0000..=0b1101_1111 => 1,
+            0b1110_0000..=0b1110_1111 => 2,
+            0b1111_0000..=0b1111_0111 => 3,
+            // A stray continuation byte or invalid
+            _ => return None,
+        };
+        self.utf8[0] = byte;
+        self.utf8_len = 1;
+        self.state = State::Utf8(missing);
+        None
+    }
+}
+
+/// Keys typed on the terminal connected to `SERIAL1`. Only one may exist, as it consumes
+/// the bytes of the interrupt handler.
+pub struct SerialKeyStream {
+    decoder: KeyDecoder,
+    /// Received while waiting for other keys, yielded before new ones
+    type_ahead: VecDeque<DecodedKey>,
+}
+
+impl SerialKeyStream {
+    /// # Panics
+    /// If called twice.
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        BYTE_QUEUE
+            .try_init_once(|| ArrayQueue::new(BYTE_QUEUE_SIZE))
+            .expect("SerialKeyStream::new() may only be called once");
+        SerialKeyStream {
+            decoder: KeyDecoder::new(),
+            type_ahead: VecDeque::new(),
+        }
+    }
+
+    /// Resolves with the first of the `keys` typed, e.g. Ctrl+C while a command runs. Other
+    /// keys are yielded by the stream afterwards, so input sent ahead isn't lost.
+    pub fn wait_for<'a>(&'a mut self, keys: &'a [char]) -> impl Future<Output = char> + 'a {
+        poll_fn(move |cx| {
+            if let Some(key) = self.type_ahead_until(keys) {
+                return Poll::Ready(key);
+            }
+            WAKER.register(cx.waker());
+            // Check again, as a byte could have arrived before the waker was registered
+            match self.type_ahead_until(keys) {
+                Some(key) => Poll::Ready(key),
+                None => Poll::Pending,
+            }
+        })
+    }
+
+    fn type_ahead_until(&mut self, keys: &[char]) -> Option<char> {
+        while let Some(key) = self.decode_next() {
+            match key {
+                DecodedKey::Unicode(character) if keys.contains(&character) => {
+                    return Some(character)
+                }
+                key if self.type_ahead.len() < TYPE_AHEAD_SIZE => self.type_ahead.push_back(key),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn next_key(&mut self) -> Option<DecodedKey> {
+        self.type_ahead.pop_front().or_else(|| self.decode_next())
+    }
+
+    fn decode_next(&mut self) -> Option<DecodedKey> {
+        // BYTE_QUEUE is guaranteed to be initialized by the constructor
+        let queue = BYTE_QUEUE.try_get().unwrap();
+        while let Some(byte) = queue.pop() {
+            if let Some(key) = self.decoder.add_byte(byte) {
+                return Some(key);
+            }
+        }
+        None
+    }
+}
+
+impl Stream for SerialKeyStream {
+    type Item = DecodedKey;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(key) = self.next_key() {
+            return Poll::Ready(Some(key));
+        }
+        WAKER.register(cx.waker());
+        // Check again, as a byte could have arrived before the waker was registered
+        match self.next_key() {
+            Some(key) => Poll::Ready(Some(key)),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use super::*;
+use alloc::vec::Vec;
+
+fn decode(bytes: &[u8]) -> Vec<DecodedKey> {
+    let mut decoder = KeyDecoder::new();
+    bytes
+        .iter()
+        .filter_map(|byte| decoder.add_byte(*byte))
+        .collect()
+}
+
+#[test_case]
+fn characters_and_enter() {
+    assert_eq!(
+        decode(b"a\r\nb\n\x7f"),
+        [
+            DecodedKey::Unicode('a'),
+            DecodedKey::Unicode('\n'),
+            DecodedKey::Unicode('b'),
+            DecodedKey::Unicode('\n'),
+            DecodedKey::Unicode(BACKSPACE),
+        ]
+    );
+    assert_eq!(
+        decode("ä€".as_bytes()),
+        [DecodedKey::Unicode('ä'), DecodedKey::Unicode('€')]
+    );
+    // The truncated character is dropped
+    assert_eq!(decode(b"\xc3x"), [DecodedKey::Unicode('x')]);
+}
+
+#[test_case]
+fn escape_sequences() {
+    assert_eq!(
+        decode(b"\x1b[A\x1bOB\x1b[3~\x1b[1~\x1b[F\x1b[15~x"),
+        [
+            DecodedKey::RawKey(KeyCode::ArrowUp),
+            DecodedKey::RawKey(KeyCode::ArrowDown),
+            DecodedKey::RawKey(KeyCode::Delete),
+            DecodedKey::RawKey(KeyCode::Home),
+            DecodedKey::RawKey(KeyCode::End),
+            // F5 is ignored
+            DecodedKey::Unicode('x'),
+        ]
+    );
+}
@@ -3,21 +3,62 @@ use core::{
     pin::Pin,
     sync::atomic::{AtomicU64, Ordering},
     task::{Context, Poll, Waker},
+    time::Duration,
 };
-use futures_util::{task::AtomicWaker, Stream};
+use futures_util::{future::poll_fn, task::AtomicWaker, Stream};
+
+use crate::concurrency::{Waiter, WakerList};
 
 // FIXME: Use lockfree (fixed size) array to store wakers of multiple timers
 //const MAX_AMOUNT_OF_CONCURRENT_TIMERS: usize = 64;
 static TIMER_COUNTER: AtomicU64 = AtomicU64::new(0);
 static TIMER_WAKER: OnceCell<AtomicWaker> = OnceCell::uninit();
 //static TIMER_WAKERS: OnceCell<ArrayQueue<Option<AtomicWaker>>> = OnceCell::uninit();
+/// Tasks in `sleep`, which check their deadline on every tick
+static SLEEPERS: WakerList = WakerList::new();
+/// Timer ticks since the timer interrupt got enabled.
+pub fn ticks() -> u64 {
+    TIMER_COUNTER.load(Ordering::Relaxed)
+}
+
+/// Input clock of the PIT, whose default divider of 65536 gives about 18.2 ticks per second
+const PIT_FREQUENCY_HZ: u64 = 1_193_182;
+const PIT_DIVIDER: u64 = 65_536;
+
+/// Time since the timer interrupt got enabled.
+#[must_use]
+pub fn uptime() -> Duration {
+    Duration::from_millis(ticks() * PIT_DIVIDER * 1000 / PIT_FREQUENCY_HZ)
+}
+
 pub(crate) fn tick() {
     TIMER_COUNTER.fetch_add(1, Ordering::Relaxed);
+    SLEEPERS.wake_all();
     if let Ok(waker) = TIMER_WAKER.try_get() {
         waker.wake();
     }
 }
 
+/// Resolves after `ticks` timer ticks
+pub async fn sleep(ticks: u64) {
+    let deadline = self::ticks() + ticks;
+    let mut waiter = Waiter::new(&SLEEPERS);
+    poll_fn(|cx| loop {
+        if self::ticks() >= deadline {
+            waiter.cancel();
+            return Poll::Ready(());
+        }
+        if !waiter.register(cx.waker()) {
+            // The deadline could have passed before registering
+            if self::ticks() >= deadline {
+                continue;
+            }
+            return Poll::Pending;
+        }
+    })
+    .await;
+}
+
 pub struct TickStream {
     ticks: u64,
     // Atomic so that we can store it in the `Stream` impl, as we don't have a &mut,
@@ -11,7 +11,6 @@
 //! - Exposes kprintln*! for the lower part for os messages.
 //! - Exposes `set_status_line()` to set the top bar (1 line)
 use core::fmt::{Arguments, Write};
-use x86_64::instructions::interrupts::without_interrupts;
 
 static ROWS_FOR_STATUS: u8 = 1;
 static ROWS_FOR_PROG: u8 = 20;
@@ -31,8 +30,9 @@ mod tests;
 
 use area::Area;
 use primitives::{Color, ColorCode};
-use view::View;
+pub use view::View;
 
+use crate::concurrency::IrqSpinLock;
 use lazy_static::lazy_static;
 use spin::Mutex;
 pub use traits::*;
@@ -59,36 +59,36 @@ lazy_static! {
 // === Setup views ===
 //
 lazy_static! {
-    static ref STATUS: Mutex<View> = {
+    static ref STATUS: IrqSpinLock<View> = {
         let cc = ColorCode::new(Color::LightBlue, Color::DarkGray);
         let mut view = View::new(&WINDOW_STATUS, cc);
         view.clear();
-        Mutex::new(view)
+        IrqSpinLock::new(view)
     };
 }
 
 lazy_static! {
-    pub static ref STDOUT: Mutex<View> = {
+    pub static ref STDOUT: IrqSpinLock<View> = {
         let cc = ColorCode::new(Color::White, Color::DarkGray);
         let mut view = View::new(&WINDOW_PROG, cc);
         view.clear();
-        Mutex::new(view)
+        IrqSpinLock::new(view)
     };
 }
 
 lazy_static! {
-    pub static ref STDERR: Mutex<View> = {
+    pub static ref STDERR: IrqSpinLock<View> = {
         let cc = ColorCode::new(Color::LightRed, Color::DarkGray);
-        Mutex::new(View::new(&WINDOW_PROG, cc))
+        IrqSpinLock::new(View::new(&WINDOW_PROG, cc))
     };
 }
 
 lazy_static! {
-    pub static ref KEROUT: Mutex<View> = {
+    pub static ref KEROUT: IrqSpinLock<View> = {
         let cc = ColorCode::new(Color::Yellow, Color::LightGray);
         let mut view = View::new(&WINDOW_SPECIAL, cc);
         view.clear();
-        Mutex::new(view)
+        IrqSpinLock::new(view)
     };
 }
 
@@ -102,11 +102,9 @@ macro_rules! set_status_line {
 
 #[doc(hidden)]
 pub fn _set_status_line(args: Arguments) {
-    without_interrupts(|| {
-        let mut status = STATUS.lock();
-        status.put_byte(b'\r');
-        status.write_fmt(args).unwrap();
-    });
+    let mut status = STATUS.lock();
+    status.put_byte(b'\r');
+    status.write_fmt(args).unwrap();
 }
 
 #[macro_export]
@@ -122,10 +120,7 @@ macro_rules! println {
 
 #[doc(hidden)]
 pub fn _print(args: Arguments) {
-    // Could deadlock if a interrupts prints something
-    without_interrupts(|| {
-        STDOUT.lock().write_fmt(args).unwrap();
-    });
+    STDOUT.lock().write_fmt(args).unwrap();
 }
 
 #[macro_export]
@@ -141,9 +136,7 @@ macro_rules! eprintln {
 
 #[doc(hidden)]
 pub fn _eprint(args: core::fmt::Arguments) {
-    without_interrupts(|| {
-        STDERR.lock().write_fmt(args).unwrap();
-    });
+    STDERR.lock().write_fmt(args).unwrap();
 }
 
 #[macro_export]
@@ -159,7 +152,6 @@ macro_rules! kprintln {
 
 #[doc(hidden)]
 pub fn _kprint(args: core::fmt::Arguments) {
-    without_interrupts(|| {
-        KEROUT.lock().write_fmt(args).unwrap();
-    });
+    crate::klog::write_fmt(args);
+    KEROUT.lock().write_fmt(args).unwrap();
 }
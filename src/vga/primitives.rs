@@ -64,6 +64,13 @@ impl ColorCode {
     pub fn get_bg(&self) -> Color {
         unsafe { core::mem::transmute::<u8, Color>(self.0 >> 4) }
     }
+
+    /// Swaps foreground and background. Bright foregrounds become blinking backgrounds.
+    #[inline]
+    #[must_use]
+    pub fn inverted(self) -> Self {
+        Self(self.0.rotate_left(4))
+    }
 }
 
 impl Default for ColorCode {
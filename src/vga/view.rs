@@ -13,6 +13,11 @@ impl View {
         Self { color_code, area }
     }
 
+    #[must_use]
+    pub fn color_code(&self) -> ColorCode {
+        self.color_code
+    }
+
     /// Clears the whole area with the current `color_code`
     pub fn clear(&mut self) {
         let rows = self.area.lock().size().rows;
@@ -0,0 +1,193 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cbos::tests::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use cbos::task::{
+    executor::{self, Executor, RegisterError, SpawnError},
+    timer, AccessError, Task,
+};
+use cbos::*;
+use core::cell::{Cell, RefCell};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::task::Poll;
+use futures_util::future::poll_fn;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    init(boot_info);
+    test_main();
+    hal::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tests::test_panic_handler(info)
+}
+
+/// Returns `Pending` once, so the task gets woken and possibly moves to another CPU.
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await;
+}
+
+#[test_case]
+fn tasks_run_on_all_cpus() {
+    static FINISHED: AtomicUsize = AtomicUsize::new(0);
+    static CPUS_USED: AtomicU64 = AtomicU64::new(0);
+    const TASKS: usize = 64;
+
+    let mut executor = Executor::new();
+    executor.set_global_spawner().unwrap();
+    for _ in 0..TASKS {
+        let task = Task::new(async {
+            for _ in 0..10 {
+                CPUS_USED.fetch_or(1 << smp::cpu_id(), Ordering::Relaxed);
+                // Keep the CPU busy, so idle CPUs steal
+                for _ in 0..10_000 {
+                    core::hint::spin_loop();
+                }
+                yield_now().await;
+            }
+            FINISHED.fetch_add(1, Ordering::Relaxed);
+        });
+        executor::spawn(task).unwrap();
+    }
+    executor.run();
+
+    assert_eq!(FINISHED.load(Ordering::Relaxed), TASKS);
+    assert!(CPUS_USED.load(Ordering::Relaxed).count_ones() > 1);
+}
+
+#[test_case]
+fn named_executor_runs_on_application_processors() {
+    static RAN: AtomicBool = AtomicBool::new(false);
+    let drivers = Executor::named("drivers").unwrap();
+    assert_eq!(drivers.name(), Some("drivers"));
+    executor::spawn_on(
+        "drivers",
+        Task::new(async {
+            RAN.store(true, Ordering::Release);
+        }),
+    )
+    .unwrap();
+
+    // Nobody calls `run`, so an application processor has to pick it up
+    let deadline = timer::ticks() + 20;
+    while !RAN.load(Ordering::Acquire) && timer::ticks() < deadline {
+        hal::hlt();
+    }
+    assert!(RAN.load(Ordering::Acquire));
+}
+
+#[test_case]
+fn sleep_waits_for_timer_ticks() {
+    static WOKEN_AT: AtomicU64 = AtomicU64::new(0);
+    let start = timer::ticks();
+    let mut executor = Executor::new();
+    executor
+        .get_spawner()
+        .spawn(Task::new(async {
+            timer::sleep(3).await;
+            WOKEN_AT.store(timer::ticks(), Ordering::Relaxed);
+        }))
+        .unwrap();
+    executor.run();
+    assert!(WOKEN_AT.load(Ordering::Relaxed) >= start + 3);
+}
+
+#[test_case]
+fn executor_names_are_unique() {
+    let _first = Executor::named("unique").unwrap();
+    assert_eq!(
+        Executor::named("unique").err(),
+        Some(RegisterError::NameTaken)
+    );
+}
+
+#[test_case]
+fn spawner_fails_after_executor_is_dropped() {
+    let executor = Executor::named("short-lived").unwrap();
+    let spawner = executor.get_spawner();
+    assert!(spawner.is_alive());
+    drop(executor);
+
+    assert!(!spawner.is_alive());
+    assert_eq!(
+        spawner.spawn(Task::new(async {})).err(),
+        Some(SpawnError::Gone)
+    );
+    assert_eq!(
+        executor::spawn_on("short-lived", Task::new(async {})).err(),
+        Some(SpawnError::NotFound)
+    );
+}
+
+#[test_case]
+fn name_is_free_after_drop() {
+    drop(Executor::named("reused").unwrap());
+    assert!(Executor::named("reused").is_ok());
+}
+
+task_local! {
+    static NAME: RefCell<&'static str> = RefCell::new("unnamed");
+    static POLLS: Cell<usize> = Cell::new(0);
+}
+
+#[test_case]
+fn task_locals_are_separate_per_task() {
+    static CHECKED: AtomicUsize = AtomicUsize::new(0);
+    let mut executor = Executor::new();
+    let spawner = executor.get_spawner();
+    for name in ["first", "second", "third"] {
+        let task = Task::new(async move {
+            NAME.with(|own| *own.borrow_mut() = name);
+            for _ in 0..5 {
+                POLLS.with(|polls| polls.set(polls.get() + 1));
+                yield_now().await;
+            }
+            assert_eq!(NAME.with(|own| *own.borrow()), name);
+            assert_eq!(POLLS.with(Cell::get), 5);
+            CHECKED.fetch_add(1, Ordering::Relaxed);
+        });
+        spawner.spawn(task).unwrap();
+    }
+    executor.run();
+    assert_eq!(CHECKED.load(Ordering::Relaxed), 3);
+}
+
+#[test_case]
+fn task_local_can_be_set_at_spawn() {
+    static SEEN: AtomicBool = AtomicBool::new(false);
+    let mut executor = Executor::new();
+    let task = Task::new(async {
+        SEEN.store(
+            NAME.with(|name| *name.borrow() == "shell"),
+            Ordering::Relaxed,
+        );
+    })
+    .with_local(&NAME, RefCell::new("shell"));
+    executor.get_spawner().spawn(task).unwrap();
+    executor.run();
+    assert!(SEEN.load(Ordering::Relaxed));
+}
+
+#[test_case]
+fn task_local_outside_of_task() {
+    assert_eq!(NAME.try_with(|_| ()), Err(AccessError));
+}
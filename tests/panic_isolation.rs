@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cbos::tests::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use cbos::task::{
+    executor::{self, Executor, JoinError, JoinHandle},
+    timer, Task,
+};
+use cbos::*;
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    init(boot_info);
+    test_main();
+    hal::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    executor::abort_panicking_task(info);
+    tests::test_panic_handler(info)
+}
+
+/// Waits for a task on an application processor
+fn wait(handle: &JoinHandle) -> Option<Result<(), JoinError>> {
+    let deadline = timer::ticks() + 20;
+    while !handle.is_finished() && timer::ticks() < deadline {
+        hal::hlt();
+    }
+    handle.try_join()
+}
+
+#[test_case]
+fn panic_fails_the_task() {
+    executor::set_panic_isolation(true);
+    // Runs on the application processors
+    let executor = Executor::named("isolated").unwrap();
+    let spawner = executor.get_spawner();
+
+    let failing = spawner
+        .spawn(Task::new(async { panic!("boom") }).with_name("failing"))
+        .unwrap();
+    match wait(&failing) {
+        Some(Err(JoinError::Panicked(message))) => assert!(message.contains("boom")),
+        other => panic!("Task did not fail with a panic: {:?}", other),
+    }
+    assert_eq!(executor.task_count(), 0);
+
+    // The worker keeps running other tasks
+    let fine = spawner.spawn(Task::new(async {})).unwrap();
+    assert_eq!(wait(&fine), Some(Ok(())));
+    executor::set_panic_isolation(false);
+}
+
+#[test_case]
+fn dropped_executor_cancels_tasks() {
+    let executor = Executor::new();
+    let handle = executor.get_spawner().spawn(Task::new(async {})).unwrap();
+    drop(executor);
+    assert_eq!(handle.try_join(), Some(Err(JoinError::Cancelled)));
+}
@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cbos::tests::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use cbos::ps2::{self, Leds, RepeatDelay, Typematic};
+use cbos::*;
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    init(boot_info);
+    test_main();
+    hal::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tests::test_panic_handler(info)
+}
+
+/// The acknowledgements arrive through the keyboard interrupt
+fn wait_for_commands() {
+    let start = task::timer::ticks();
+    while ps2::commands_pending() {
+        assert!(
+            task::timer::ticks() < start + 18,
+            "Keyboard acknowledges commands"
+        );
+        hal::hlt();
+    }
+}
+
+#[test_case]
+fn controller_passed_self_test() {
+    assert!(ps2::is_initialised());
+    // QEMU emulates a keyboard and a mouse
+    let ports = ps2::ports();
+    assert!(ports.first && ports.second);
+}
+
+#[test_case]
+fn keyboard_interrupt_and_translation_enabled() {
+    let config = ps2::config().unwrap();
+    assert_ne!(config & ps2::CONFIG_FIRST_IRQ, 0);
+    assert_ne!(config & ps2::CONFIG_TRANSLATION, 0);
+    assert_eq!(config & ps2::CONFIG_FIRST_CLOCK_DISABLED, 0);
+}
+
+#[test_case]
+fn encodings() {
+    let leds = Leds {
+        scroll_lock: true,
+        num_lock: false,
+        caps_lock: true,
+    };
+    assert_eq!(leds.bits(), 0b101);
+    let typematic = Typematic {
+        delay: RepeatDelay::Ms1000,
+        rate: 40,
+    };
+    assert_eq!(typematic.bits(), 0x7f);
+    assert_eq!(Typematic::default().bits(), 0x2b);
+}
+
+#[test_case]
+fn leds_are_acknowledged() {
+    ps2::set_leds(Leds {
+        caps_lock: true,
+        ..Leds::default()
+    });
+    ps2::set_leds(Leds::default());
+    wait_for_commands();
+}
+
+#[test_case]
+fn typematic_is_acknowledged() {
+    ps2::set_typematic(Typematic {
+        delay: RepeatDelay::Ms250,
+        rate: 0,
+    });
+    ps2::set_typematic(Typematic::default());
+    wait_for_commands();
+}
+
+#[test_case]
+fn mouse_interrupt_enabled() {
+    let config = ps2::config().unwrap();
+    assert_ne!(config & ps2::CONFIG_SECOND_IRQ, 0);
+    assert_eq!(config & ps2::CONFIG_SECOND_CLOCK_DISABLED, 0);
+    // QEMU emulates an IntelliMouse
+    assert!(ps2::mouse_has_wheel());
+}
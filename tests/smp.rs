@@ -0,0 +1,126 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cbos::tests::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use bootloader::{entry_point, BootInfo};
+use cbos::concurrency::Notify;
+use cbos::task::{
+    executor::{self, Executor},
+    Task,
+};
+use cbos::*;
+use core::future::Future;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::Context;
+use futures_util::{
+    pin_mut,
+    task::{waker, ArcWake},
+    FutureExt,
+};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    init(boot_info);
+    test_main();
+    hal::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tests::test_panic_handler(info)
+}
+
+#[test_case]
+fn runs_on_bootstrap_processor() {
+    assert_eq!(smp::cpu_id(), 0);
+    assert_eq!(smp::current().id(), 0);
+}
+
+#[test_case]
+fn all_cpus_online() {
+    // The tests run with `-smp 4`
+    let cpus = smp::cpus();
+    assert_eq!(cpus.len(), 4);
+    assert!(cpus.iter().all(|cpu| cpu.online));
+    assert_eq!(smp::online_count(), cpus.len());
+}
+
+#[test_case]
+fn cpu_ids_are_unique() {
+    let cpus = smp::cpus();
+    for (index, cpu) in cpus.iter().enumerate() {
+        assert_eq!(cpu.id, index);
+        assert!(cpus[..index]
+            .iter()
+            .all(|other| other.apic_id != cpu.apic_id));
+    }
+}
+
+/// Notifies the bootstrap processor from an application processor, while it polls. The
+/// offsets vary, so `notify_one` also runs between the check and the registration of
+/// `Notified`.
+#[test_case]
+fn notify_one_from_another_cpu_is_not_lost() {
+    const ROUNDS: usize = 2000;
+    static ROUND: AtomicUsize = AtomicUsize::new(0);
+    static NOTIFIED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Flag(AtomicBool);
+
+    impl ArcWake for Flag {
+        fn wake_by_ref(flag: &Arc<Self>) {
+            flag.0.store(true, Ordering::Release);
+        }
+    }
+
+    let spin = |iterations: usize| {
+        for _ in 0..iterations {
+            core::hint::spin_loop();
+        }
+    };
+    let notify = Arc::new(Notify::new());
+    let _notifier = Executor::named("notifier").unwrap();
+    let remote = notify.clone();
+    executor::spawn_on(
+        "notifier",
+        Task::new(async move {
+            for round in 1..=ROUNDS {
+                while ROUND.load(Ordering::Acquire) != round {
+                    core::hint::spin_loop();
+                }
+                spin(round % 32);
+                remote.notify_one();
+                NOTIFIED.store(round, Ordering::Release);
+            }
+        }),
+    )
+    .unwrap();
+
+    for round in 1..=ROUNDS {
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = waker(flag.clone());
+        let notified = notify.notified();
+        pin_mut!(notified);
+        ROUND.store(round, Ordering::Release);
+        spin(round * 7 % 32);
+        let ready = notified
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_ready();
+        while NOTIFIED.load(Ordering::Acquire) != round {
+            core::hint::spin_loop();
+        }
+        // Resolved right away or woken later
+        assert!(ready || flag.0.load(Ordering::Acquire));
+        if !ready {
+            assert!(notified.now_or_never().is_some());
+        }
+    }
+}
@@ -0,0 +1,106 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cbos::tests::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use cbos::concurrency::thread;
+use cbos::prelude::*;
+use cbos::*;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    init(boot_info);
+    test_main();
+    hal::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tests::test_panic_handler(info)
+}
+
+#[test_case]
+fn join_returns_result() {
+    let handle = thread::spawn(|| 6 * 7);
+    assert_eq!(handle.join(), 42);
+}
+
+#[test_case]
+fn threads_run_concurrently() {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            thread::spawn(|| {
+                for _ in 0..100 {
+                    COUNTER.fetch_add(1, Ordering::Relaxed);
+                    thread::yield_now();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(COUNTER.load(Ordering::Relaxed), 400);
+}
+
+#[test_case]
+fn busy_thread_gets_preempted() {
+    static DONE: AtomicUsize = AtomicUsize::new(0);
+    // Never yields, so only the timer interrupt lets the main thread continue
+    let busy = thread::spawn(|| {
+        while DONE.load(Ordering::Relaxed) == 0 {
+            core::hint::spin_loop();
+        }
+    });
+    thread::sleep(2);
+    DONE.store(1, Ordering::Relaxed);
+    busy.join();
+}
+
+#[test_case]
+fn sleep_waits_for_ticks() {
+    let start = task::timer::ticks();
+    thread::sleep(3);
+    assert!(task::timer::ticks() >= start + 3);
+}
+
+#[test_case]
+fn blocking_mutex_and_condvar() {
+    use cbos::concurrency::blocking::{Condvar, Mutex};
+    let pair = Arc::new((Mutex::new(0), Condvar::new()));
+    let handles: Vec<_> = (0..3)
+        .map(|_| {
+            let pair = pair.clone();
+            thread::spawn(move || {
+                let (counter, condvar) = &*pair;
+                *counter.lock() += 1;
+                condvar.notify_all();
+            })
+        })
+        .collect();
+    let (counter, condvar) = &*pair;
+    let guard = condvar.wait_while(counter.lock(), |count| *count < 3);
+    assert_eq!(*guard, 3);
+    drop(guard);
+    for handle in handles {
+        handle.join();
+    }
+}
+
+#[test_case]
+fn blocking_semaphore_limits() {
+    use cbos::concurrency::blocking::Semaphore;
+    let semaphore = Semaphore::new(1);
+    let permit = semaphore.acquire();
+    assert!(semaphore.try_acquire().is_none());
+    drop(permit);
+    assert_eq!(semaphore.available_permits(), 1);
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(cbos::tests::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use cbos::task::{
+    executor::{
+        watchdog::{self, Mode},
+        Executor,
+    },
+    timer, Task,
+};
+use cbos::*;
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    init(boot_info);
+    test_main();
+    hal::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    tests::test_panic_handler(info)
+}
+
+#[test_case]
+fn settings() {
+    assert_eq!(watchdog::mode(), Mode::Report);
+    watchdog::set_threshold(0);
+    assert_eq!(watchdog::threshold(), 1);
+    watchdog::set_mode(Mode::Off);
+    assert_eq!(watchdog::mode(), Mode::Off);
+}
+
+#[test_case]
+fn stalled_task_is_killed() {
+    watchdog::set_mode(Mode::Kill);
+    watchdog::set_threshold(2);
+    // Runs on the application processors
+    let executor = Executor::named("stalled").unwrap();
+    let task = Task::new(async {
+        loop {
+            core::hint::spin_loop();
+        }
+    })
+    .with_name("busy loop");
+    executor.get_spawner().spawn(task).unwrap();
+
+    let deadline = timer::ticks() + 40;
+    while executor.task_count() > 0 && timer::ticks() < deadline {
+        hal::hlt();
+    }
+    assert_eq!(executor.task_count(), 0);
+    watchdog::set_mode(Mode::Report);
+}